@@ -3,16 +3,16 @@ use crate::{
     postprocessing::Contents,
 };
 use anyhow::anyhow;
-use chimp_protocol::{Circle, FailedResponse, Request, Response, SuccesfulResponse};
+use chimp_protocol::{Circle, FailedResponse, Request, Response, SuccesfulResponse, MAX_PRIORITY};
 use derive_more::{Deref, From};
 use futures::StreamExt;
 use lapin::{
     acker::Acker,
     options::{
-        BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, BasicRejectOptions,
-        QueueDeclareOptions,
+        BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, BasicQosOptions,
+        BasicRejectOptions, QueueDeclareOptions,
     },
-    types::{FieldTable, ShortString},
+    types::{AMQPValue, FieldTable, ShortString},
     BasicProperties, Channel, Connection, Consumer,
 };
 use tokio::sync::mpsc::{OwnedPermit, UnboundedSender};
@@ -30,22 +30,32 @@ pub async fn setup_rabbitmq_client(address: Url) -> Result<Connection, anyhow::E
 }
 
 /// Joins a RabbitMQ channel, creating a [`Consumer`] with [`Default`] [`BasicConsumeOptions`] and [`FieldTable`].
+/// The queue is declared as a priority queue, supporting priorities up to [`MAX_PRIORITY`], and at most `prefetch_count` unacknowledged jobs are held such that higher priority jobs are not stuck behind a local backlog.
 /// The consumer tag is generated following the format `chimp_chomp_${`[`Uuid::now_v7`]`}`.
 ///
 /// Returns a [`lapin::Error`] if the requested channel is not available.
 pub async fn setup_job_consumer(
     rabbitmq_channel: Channel,
     channel: impl AsRef<str>,
+    prefetch_count: u16,
 ) -> Result<Consumer, lapin::Error> {
     let worker_id = Uuid::now_v7();
     let worker_tag = format!("chimp_chomp_{worker_id}");
+    let mut queue_arguments = FieldTable::default();
+    queue_arguments.insert(
+        "x-max-priority".into(),
+        AMQPValue::ShortShortUInt(MAX_PRIORITY),
+    );
     rabbitmq_channel
         .queue_declare(
             channel.as_ref(),
             QueueDeclareOptions::default(),
-            FieldTable::default(),
+            queue_arguments,
         )
         .await?;
+    rabbitmq_channel
+        .basic_qos(prefetch_count, BasicQosOptions::default())
+        .await?;
     rabbitmq_channel
         .basic_consume(
             channel.as_ref(),
@@ -138,8 +148,7 @@ pub async fn produce_response(
             response_target.reply_to.as_str(),
             BasicPublishOptions::default(),
             &Response::Success(SuccesfulResponse {
-                plate: request.plate,
-                well: request.well,
+                image_id: request.image_id,
                 insertion_point: contents.insertion_point,
                 well_location,
                 drop: contents.drop,
@@ -174,8 +183,7 @@ pub async fn produce_error(
             response_target.reply_to.as_str(),
             BasicPublishOptions::default(),
             &Response::Failure(FailedResponse {
-                plate: request.plate,
-                well: request.well,
+                image_id: request.image_id,
                 error: error.to_string(),
             })
             .to_vec()
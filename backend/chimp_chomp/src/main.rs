@@ -46,6 +46,9 @@ struct Cli {
     /// The number of worker threads to use
     #[arg(long, env)]
     threads: Option<usize>,
+    /// The maximum number of unacknowledged jobs to hold, defaults to the inference batch size.
+    #[arg(long, env)]
+    prefetch_count: Option<u16>,
 }
 
 fn main() {
@@ -74,7 +77,10 @@ async fn run(args: Cli) {
     let rabbitmq_client = setup_rabbitmq_client(args.rabbitmq_url).await.unwrap();
     let job_channel = rabbitmq_client.create_channel().await.unwrap();
     let response_channel = rabbitmq_client.create_channel().await.unwrap();
-    let job_consumer = setup_job_consumer(job_channel, args.rabbitmq_channel)
+    let prefetch_count = args
+        .prefetch_count
+        .unwrap_or(batch_size.try_into().unwrap());
+    let job_consumer = setup_job_consumer(job_channel, args.rabbitmq_channel, prefetch_count)
         .await
         .unwrap();
 
@@ -114,32 +120,32 @@ async fn run(args: Cli) {
             biased;
 
             Some((response_target, request)) = response_target_rx.recv() => {
-                response_targets.insert((request.plate, request.well), response_target);
+                response_targets.insert(request.image_id, response_target);
             }
 
             Some((error, request)) = error_rx.recv() => {
-                let response_target = response_targets.remove(&(request.plate, request.well)).unwrap();
+                let response_target = response_targets.remove(&request.image_id).unwrap();
                 tasks.spawn(produce_error(request, response_target, error, response_channel.clone()));
             }
 
             Some((well_location, request)) = well_location_rx.recv() => {
-                if response_targets.contains_key(&(request.plate, request.well)) {
-                    if let Some(contents) = well_contents.remove(&(request.plate, request.well)) {
-                        let response_target = response_targets.remove(&(request.plate, request.well)).unwrap();
+                if response_targets.contains_key(&request.image_id) {
+                    if let Some(contents) = well_contents.remove(&request.image_id) {
+                        let response_target = response_targets.remove(&request.image_id).unwrap();
                         tasks.spawn(produce_response(request, response_target, contents, well_location, response_channel.clone()));
                     } else {
-                        well_locations.insert((request.plate, request.well), well_location);
+                        well_locations.insert(request.image_id, well_location);
                     }
                 }
             }
 
             Some((contents, request)) = contents_rx.recv() => {
-                if response_targets.contains_key(&(request.plate, request.well)) {
-                    if let Some(well_location) = well_locations.remove(&(request.plate, request.well)) {
-                        let response_target = response_targets.remove(&(request.plate, request.well)).unwrap();
+                if response_targets.contains_key(&request.image_id) {
+                    if let Some(well_location) = well_locations.remove(&request.image_id) {
+                        let response_target = response_targets.remove(&request.image_id).unwrap();
                         tasks.spawn(produce_response(request, response_target, contents, well_location, response_channel.clone()));
                     } else {
-                        well_contents.insert((request.plate, request.well), contents);
+                        well_contents.insert(request.image_id, contents);
                     }
                 }
             }
@@ -0,0 +1,188 @@
+use anyhow::{anyhow, Context};
+use clap::Args;
+use serde::Deserialize;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use url::Url;
+
+/// The period before expiry at which an access token is considered stale and will be refreshed.
+const REFRESH_MARGIN: Duration = Duration::from_secs(30);
+
+/// The lifetime assumed for access tokens when the authorization server does not report one.
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Arguments for configuring the identity with which this service authenticates.
+#[derive(Debug, Args)]
+pub struct AuthenticationArgs {
+    /// A static authorization token to make requests to the targeting service with.
+    #[arg(long, env, conflicts_with_all = ["oauth_token_url", "oauth_client_id", "oauth_client_secret"])]
+    targeting_token: Option<String>,
+    /// The URL of the OAuth2 token endpoint used for the client credentials flow.
+    #[arg(long, env, requires_all = ["oauth_client_id", "oauth_client_secret"])]
+    oauth_token_url: Option<Url>,
+    /// The OAuth2 client ID of this service.
+    #[arg(long, env, requires = "oauth_token_url")]
+    oauth_client_id: Option<String>,
+    /// The OAuth2 client secret of this service.
+    #[arg(long, env, requires = "oauth_token_url")]
+    oauth_client_secret: Option<String>,
+    /// The scopes to request in the OAuth2 client credentials flow.
+    #[arg(long, env)]
+    oauth_scope: Option<String>,
+}
+
+/// A source of bearer tokens used to authorize requests to the targeting service.
+#[derive(Debug, Clone)]
+pub enum TokenSource {
+    /// A long-lived token supplied at startup.
+    Static(String),
+    /// Tokens obtained, and refreshed upon expiry, via the OAuth2 client credentials flow.
+    ClientCredentials(Arc<ClientCredentials>),
+}
+
+impl TryFrom<AuthenticationArgs> for TokenSource {
+    type Error = anyhow::Error;
+
+    fn try_from(args: AuthenticationArgs) -> Result<Self, Self::Error> {
+        match (
+            args.targeting_token,
+            args.oauth_token_url,
+            args.oauth_client_id,
+            args.oauth_client_secret,
+        ) {
+            (Some(token), None, None, None) => Ok(Self::Static(token)),
+            (None, Some(token_url), Some(client_id), Some(client_secret)) => {
+                Ok(Self::ClientCredentials(Arc::new(ClientCredentials::new(
+                    token_url,
+                    client_id,
+                    client_secret,
+                    args.oauth_scope,
+                ))))
+            }
+            _ => Err(anyhow!(
+                "Either a targeting token or OAuth2 client credentials must be supplied"
+            )),
+        }
+    }
+}
+
+impl TokenSource {
+    /// Retrieves a currently valid bearer token, refreshing it if required.
+    pub async fn token(&self) -> Result<String, anyhow::Error> {
+        match self {
+            TokenSource::Static(token) => Ok(token.clone()),
+            TokenSource::ClientCredentials(client_credentials) => {
+                client_credentials.access_token().await
+            }
+        }
+    }
+
+    /// Produces the value of an `Authorization` header containing a currently valid bearer token.
+    pub async fn authorization_header(&self) -> Result<String, anyhow::Error> {
+        Ok(format!("Bearer {}", self.token().await?))
+    }
+}
+
+/// An OAuth2 client credentials grant, with a cached access token.
+#[derive(Debug)]
+pub struct ClientCredentials {
+    /// The URL of the OAuth2 token endpoint.
+    token_url: Url,
+    /// The OAuth2 client ID of this service.
+    client_id: String,
+    /// The OAuth2 client secret of this service.
+    client_secret: String,
+    /// The scopes to request, if any.
+    scope: Option<String>,
+    /// The HTTP client used to make token requests.
+    http_client: reqwest::Client,
+    /// The most recently issued access token.
+    cached_token: Mutex<Option<AccessToken>>,
+}
+
+/// An access token issued by the authorization server.
+#[derive(Debug, Clone)]
+struct AccessToken {
+    /// The opaque token value.
+    secret: String,
+    /// The instant after which the token should no longer be used.
+    expires_at: Instant,
+}
+
+/// The successful response of an OAuth2 token endpoint.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    /// The issued access token.
+    access_token: String,
+    /// The type of the issued token, expected to be `Bearer`.
+    token_type: String,
+    /// The lifetime of the access token in seconds.
+    expires_in: Option<u64>,
+}
+
+impl ClientCredentials {
+    /// Creates a [`ClientCredentials`] grant, no token is requested until first use.
+    pub fn new(
+        token_url: Url,
+        client_id: String,
+        client_secret: String,
+        scope: Option<String>,
+    ) -> Self {
+        Self {
+            token_url,
+            client_id,
+            client_secret,
+            scope,
+            http_client: reqwest::Client::new(),
+            cached_token: Mutex::new(None),
+        }
+    }
+
+    /// Retrieves the cached access token, or requests a new one if it is absent or close to expiry.
+    pub async fn access_token(&self) -> Result<String, anyhow::Error> {
+        let mut cached_token = self.cached_token.lock().await;
+        if let Some(token) = cached_token.as_ref() {
+            if token.expires_at > Instant::now() + REFRESH_MARGIN {
+                return Ok(token.secret.clone());
+            }
+        }
+        let token = self.request_token().await?;
+        let secret = token.secret.clone();
+        *cached_token = Some(token);
+        Ok(secret)
+    }
+
+    /// Requests a new access token from the token endpoint.
+    async fn request_token(&self) -> Result<AccessToken, anyhow::Error> {
+        let mut form = vec![("grant_type", "client_credentials")];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        let requested_at = Instant::now();
+        let response = self
+            .http_client
+            .post(self.token_url.clone())
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()
+            .context("Token request was rejected")?
+            .json::<TokenResponse>()
+            .await?;
+        if !response.token_type.eq_ignore_ascii_case("bearer") {
+            return Err(anyhow!("Unsupported token type '{}'", response.token_type));
+        }
+        let lifetime = response
+            .expires_in
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+        Ok(AccessToken {
+            secret: response.access_token,
+            expires_at: requested_at + lifetime,
+        })
+    }
+}
@@ -1,10 +1,10 @@
-use chimp_protocol::{Request, Response};
+use chimp_protocol::{Priority, Request, Response, MAX_PRIORITY};
 use futures_util::{Stream, StreamExt, TryStreamExt};
 use lapin::{
     message::Delivery,
     options::{BasicConsumeOptions, BasicPublishOptions, QueueDeclareOptions},
     protocol::basic::AMQPProperties,
-    types::FieldTable,
+    types::{AMQPValue, FieldTable},
     Channel, Connection, ConnectionProperties, Consumer,
 };
 use url::Url;
@@ -20,6 +20,19 @@ pub async fn setup_chimp_client(
 
     let channel = connection.create_channel().await?;
 
+    let mut job_queue_arguments = FieldTable::default();
+    job_queue_arguments.insert(
+        "x-max-priority".into(),
+        AMQPValue::ShortShortUInt(MAX_PRIORITY),
+    );
+    channel
+        .queue_declare(
+            &job_channel,
+            QueueDeclareOptions::default(),
+            job_queue_arguments,
+        )
+        .await?;
+
     let reply_queue_id = Uuid::now_v7();
     channel
         .queue_declare(
@@ -66,15 +79,17 @@ pub struct RequestPublisher {
 }
 
 impl RequestPublisher {
-    /// Sends a CHiMP [`Request`] to the configured channel, with direct reply-to configuration.
-    pub async fn publish(&self, request: Request) -> Result<(), anyhow::Error> {
+    /// Sends a CHiMP [`Request`] to the configured channel at the given [`Priority`], with direct reply-to configuration.
+    pub async fn publish(&self, request: Request, priority: Priority) -> Result<(), anyhow::Error> {
         self.channel
             .basic_publish(
                 "",
                 &self.job_channel,
                 BasicPublishOptions::default(),
                 &request.to_vec()?,
-                AMQPProperties::default().with_reply_to(self.reply_queue_id.to_string().into()),
+                AMQPProperties::default()
+                    .with_reply_to(self.reply_queue_id.to_string().into())
+                    .with_priority(priority.into()),
             )
             .await?
             .await?;
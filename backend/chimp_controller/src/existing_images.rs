@@ -1,36 +1,67 @@
 use crate::{
+    authentication::TokenSource,
     chimp_messages::RequestPublisher,
-    queries::image_predictions::{ExistingImage, ImagesQuery, Prediction},
+    queries::image_predictions::{
+        CursorInput, ExistingImage, ImagesQuery, ImagesQueryVariables, PageInfo,
+    },
 };
 use anyhow::anyhow;
+use chimp_protocol::Priority;
 use cynic::{http::ReqwestExt, QueryBuilder};
 use reqwest::Method;
 use url::Url;
 
-/// Retrieves a collection of unprocessed images from the targeting service.
+/// The number of images requested in each page from the targeting service.
+const PAGE_SIZE: i32 = 100;
+
+/// Retrieves a collection of images from the targeting service which have not been processed by the service subject, with the model version if one is specified.
 pub async fn get_unprocessed_images(
     targeting_client: &reqwest::Client,
     targeting_url: Url,
-    authorization_token: &str,
+    token_source: &TokenSource,
+    service_subject: &str,
+    model_version: Option<&str>,
 ) -> Result<Vec<ExistingImage>, anyhow::Error> {
-    let query = ImagesQuery::build(());
-    let response = targeting_client
-        .request(Method::POST, targeting_url)
-        .header("Authorization", format!("Bearer {authorization_token}"))
-        .run_graphql(query)
-        .await?;
-    let images = response.data.ok_or(anyhow!("Empty response"))?.images;
+    let mut images = Vec::new();
+    let mut after = None;
+    loop {
+        let query = ImagesQuery::build(ImagesQueryVariables {
+            cursor: CursorInput {
+                after,
+                first: Some(PAGE_SIZE),
+                ..Default::default()
+            },
+        });
+        let response = targeting_client
+            .request(Method::POST, targeting_url.clone())
+            .header("Authorization", token_source.authorization_header().await?)
+            .run_graphql(query)
+            .await?;
+        let page = response.data.ok_or(anyhow!("Empty response"))?.images;
+        images.extend(page.edges.into_iter().map(|edge| edge.node));
+        match page.page_info {
+            PageInfo {
+                has_next_page: true,
+                end_cursor: Some(end_cursor),
+            } => after = Some(end_cursor),
+            _ => break,
+        }
+    }
     Ok(images
         .into_iter()
         .filter(|image| {
-            !image.predictions.contains(&Prediction {
-                operator_id: "CHiMP".to_string(),
-            })
+            !image
+                .predictions
+                .iter()
+                .any(|prediction| prediction.produced_by(service_subject, model_version))
         })
         .collect())
 }
 
-/// Recieves an existing image and produces a [`chimp_protocol::Request`] for CHiMP to perform prediction.
+/// Recieves an existing image and produces a [`Priority::Low`] [`chimp_protocol::Request`] for CHiMP to perform prediction.
 pub async fn handle_existing_image(existing_image: ExistingImage, job_publisher: RequestPublisher) {
-    job_publisher.publish(existing_image.into()).await.unwrap()
+    job_publisher
+        .publish(existing_image.into(), Priority::Low)
+        .await
+        .unwrap()
 }
@@ -3,6 +3,8 @@
 #![warn(missing_docs)]
 #![warn(clippy::missing_docs_in_private_items)]
 
+/// Utilities for authenticating with the targeting service
+mod authentication;
 /// Utilities for handling messages from CHiMP
 mod chimp_messages;
 /// Utilities for handling images which existed before this started
@@ -17,6 +19,7 @@ pub mod queries;
 mod schemas;
 
 use crate::{
+    authentication::{AuthenticationArgs, TokenSource},
     chimp_messages::setup_chimp_client,
     existing_images::{get_unprocessed_images, handle_existing_image},
     new_image::{
@@ -37,9 +40,15 @@ struct Cli {
     targeting_url: Url,
     /// The URL of the Targeting service GraphQL subscription endpoint.
     targeting_subscription_url: Url,
-    /// The authorization token to make requests to the targeting service with.
+    /// The credentials with which to authenticate with the targeting service.
+    #[command(flatten)]
+    authentication: AuthenticationArgs,
+    /// The subject to which the targeting service attributes predictions made by this service.
+    #[arg(long, env, default_value = "CHiMP")]
+    service_subject: String,
+    /// The version of the CHiMP model used to produce predictions.
     #[arg(long, env)]
-    targeting_token: String,
+    model_version: Option<String>,
     /// The URL of the RabbitMQ server.
     rabbitmq_url: Url,
     /// The RabbitMQ queue on which jobs are assigned.
@@ -51,13 +60,12 @@ async fn main() {
     dotenvy::dotenv().ok();
     let args = Cli::parse();
 
+    let token_source = TokenSource::try_from(args.authentication).unwrap();
     let targeting_client = reqwest::Client::new();
-    let mut targeting_subscription_client = setup_targeting_subscription_client(
-        &args.targeting_subscription_url,
-        &args.targeting_token,
-    )
-    .await
-    .unwrap();
+    let mut targeting_subscription_client =
+        setup_targeting_subscription_client(&args.targeting_subscription_url, &token_source)
+            .await
+            .unwrap();
     let mut image_creation_stream = setup_image_creation_stream(&mut targeting_subscription_client)
         .await
         .unwrap();
@@ -73,7 +81,9 @@ async fn main() {
     let unprocessed_images = get_unprocessed_images(
         &targeting_client,
         args.targeting_url.clone(),
-        &args.targeting_token.clone(),
+        &token_source,
+        &args.service_subject,
+        args.model_version.as_deref(),
     )
     .await
     .unwrap();
@@ -93,7 +103,7 @@ async fn main() {
             },
 
             Some(prediction) = prediction_stream.next() => {
-                tasks.spawn(handle_new_prediction(prediction, targeting_client.clone(), args.targeting_url.clone(), args.targeting_token.clone()));
+                tasks.spawn(handle_new_prediction(prediction, targeting_client.clone(), args.targeting_url.clone(), token_source.clone(), args.model_version.clone()));
             }
         }
     }
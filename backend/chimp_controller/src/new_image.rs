@@ -1,5 +1,9 @@
-use crate::{chimp_messages::RequestPublisher, queries::image_created::ImageCreatedSubscription};
+use crate::{
+    authentication::TokenSource, chimp_messages::RequestPublisher,
+    queries::image_created::ImageCreatedSubscription,
+};
 use async_tungstenite::tungstenite::{client::IntoClientRequest, http::HeaderValue, Message};
+use chimp_protocol::Priority;
 use cynic::{GraphQlResponse, StreamingOperation, SubscriptionBuilder};
 use futures_util::{
     task::{FutureObj, Spawn, SpawnError},
@@ -32,7 +36,7 @@ impl Spawn for Spawner {
 /// Creates a [`AsyncWebsocketClient`], which can subscribe to the targeting service.
 pub async fn setup_targeting_subscription_client(
     targeting_url: &Url,
-    authorization_token: &str,
+    token_source: &TokenSource,
 ) -> Result<AsyncWebsocketClient<Cynic, Message>, anyhow::Error> {
     let mut request = targeting_url.into_client_request()?;
     request.headers_mut().insert(
@@ -41,7 +45,7 @@ pub async fn setup_targeting_subscription_client(
     );
     request.headers_mut().insert(
         "Authorization",
-        HeaderValue::from_str(&format!("Bearer {authorization_token}"))?,
+        HeaderValue::from_str(&token_source.authorization_header().await?)?,
     );
 
     let (connection, _) = async_tungstenite::tokio::connect_async(request).await?;
@@ -63,11 +67,14 @@ pub async fn setup_image_creation_stream(
         .await?)
 }
 
-/// Recieves an image created event and produces [`chimp_protocol::Request`] for CHiMP to perform prediction.
+/// Recieves an image created event and produces a [`Priority::High`] [`chimp_protocol::Request`] for CHiMP to perform prediction.
 pub async fn handle_new_image(
     created_image: Result<GraphQlResponse<ImageCreatedSubscription>, graphql_ws_client::Error>,
     job_publisher: RequestPublisher,
 ) {
     let image = created_image.unwrap().data.unwrap().image_created;
-    job_publisher.publish(image.into()).await.unwrap()
+    job_publisher
+        .publish(image.into(), Priority::High)
+        .await
+        .unwrap()
 }
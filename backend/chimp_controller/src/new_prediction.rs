@@ -1,30 +1,50 @@
-use crate::queries::create_prediction::{CreatePredictionMutation, CreatePredictionVariables};
+use crate::{
+    authentication::TokenSource,
+    queries::{
+        create_prediction::{CreatePredictionMutation, CreatePredictionVariables},
+        report_prediction_failure::{
+            ReportPredictionFailureMutation, ReportPredictionFailureVariables,
+        },
+    },
+};
 use chimp_protocol::Response;
 use cynic::{http::ReqwestExt, MutationBuilder};
 use reqwest::Method;
 use url::Url;
 
-/// Recieves CHiMP predictions and sends them to the targeting service.
+/// Recieves CHiMP predictions, or failures to predict, and sends them to the targeting service.
 pub async fn handle_new_prediction(
     prediction: Result<Response, anyhow::Error>,
     targeting_client: reqwest::Client,
     targeting_url: Url,
-    authorization_token: impl AsRef<str>,
+    token_source: TokenSource,
+    model_version: Option<String>,
 ) {
-    if let Response::Success(succesful_response) = prediction.unwrap() {
-        let variables = CreatePredictionVariables::from(succesful_response);
-        let mutation = CreatePredictionMutation::build(variables);
-        let response = targeting_client
-            .request(Method::POST, targeting_url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", authorization_token.as_ref()),
-            )
-            .run_graphql(mutation)
-            .await
-            .unwrap();
-        if let Some(errs) = response.errors {
-            panic!("Targeting service returned error(s): {errs:?}");
+    let request = targeting_client
+        .request(Method::POST, targeting_url)
+        .header(
+            "Authorization",
+            token_source.authorization_header().await.unwrap(),
+        );
+    let errors = match prediction.unwrap() {
+        Response::Success(succesful_response) => {
+            let variables = CreatePredictionVariables::from_response_and_model_version(
+                succesful_response,
+                model_version,
+            );
+            let mutation = CreatePredictionMutation::build(variables);
+            request.run_graphql(mutation).await.unwrap().errors
         }
+        Response::Failure(failed_response) => {
+            let variables = ReportPredictionFailureVariables::from_response_and_model_version(
+                failed_response,
+                model_version,
+            );
+            let mutation = ReportPredictionFailureMutation::build(variables);
+            request.run_graphql(mutation).await.unwrap().errors
+        }
+    };
+    if let Some(errs) = errors {
+        panic!("Targeting service returned error(s): {errs:?}");
     }
 }
@@ -29,16 +29,6 @@ impl From<Point> for PointInput {
     }
 }
 
-/// A well, idenitified by the plate it resides on and the location.
-#[derive(Debug, Clone, InputObject)]
-#[cynic(schema = "targeting", schema_module = "crate::schemas::targeting")]
-pub struct WellInput {
-    /// The ID of the plate the imaged well is on
-    pub plate: Uuid,
-    /// The number of the imaged well
-    pub well: i32,
-}
-
 /// A predicted crystal
 #[derive(Debug, Clone, InputObject)]
 #[cynic(schema = "targeting", schema_module = "crate::schemas::targeting")]
@@ -88,23 +78,26 @@ pub struct DropInput {
 #[derive(QueryVariables)]
 #[cynic(schema_module = "crate::schemas::targeting")]
 pub struct CreatePredictionVariables {
-    /// The plate which the prediction is attributed to.
-    pub plate: WellInput,
+    /// The image which the prediction is attributed to.
+    pub image_id: Uuid,
     /// The predicted centroid of the well.
     pub well_centroid: PointInput,
     /// The predicted radius of the well.
     pub well_radius: i32,
     /// A collection of predicted drops and their contents.
     pub drops: Vec<DropInput>,
+    /// The version of the model which produced the prediction.
+    pub model_version: Option<String>,
 }
 
-impl From<SuccesfulResponse> for CreatePredictionVariables {
-    fn from(value: SuccesfulResponse) -> Self {
+impl CreatePredictionVariables {
+    /// Creates the mutation arguments from a CHiMP response and the version of the model which produced it.
+    pub fn from_response_and_model_version(
+        value: SuccesfulResponse,
+        model_version: Option<String>,
+    ) -> Self {
         Self {
-            plate: WellInput {
-                plate: value.plate,
-                well: value.well,
-            },
+            image_id: value.image_id,
             well_centroid: value.well_location.center.into(),
             well_radius: value.well_location.radius,
             drops: vec![DropInput {
@@ -118,6 +111,7 @@ impl From<SuccesfulResponse> for CreatePredictionVariables {
                     })
                     .collect(),
             }],
+            model_version,
         }
     }
 }
@@ -132,6 +126,6 @@ impl From<SuccesfulResponse> for CreatePredictionVariables {
 )]
 pub struct CreatePredictionMutation {
     /// A mutation to create a prediction for an image
-    #[arguments(plate: $plate, wellCentroid: $well_centroid, wellRadius: $well_radius, drops: $drops)]
+    #[arguments(imageId: $image_id, wellCentroid: $well_centroid, wellRadius: $well_radius, drops: $drops, modelVersion: $model_version)]
     pub create_prediction: Prediction,
 }
@@ -11,10 +11,8 @@ use uuid::Uuid;
     graphql_type = "Image"
 )]
 pub struct CreatedImage {
-    /// The ID of the plate the imaged well is on
-    pub plate: Uuid,
-    /// The number of the imaged well
-    pub well: i32,
+    /// The unique identifier of the image
+    pub id: Uuid,
     /// A URL from which the image can be retrieved
     pub download_url: Url,
 }
@@ -22,8 +20,7 @@ pub struct CreatedImage {
 impl From<CreatedImage> for Request {
     fn from(value: CreatedImage) -> Self {
         Self {
-            plate: value.plate,
-            well: value.well,
+            image_id: value.id,
             download_url: value.download_url,
         }
     }
@@ -1,5 +1,5 @@
 use chimp_protocol::Request;
-use cynic::QueryFragment;
+use cynic::{InputObject, QueryFragment, QueryVariables};
 use url::Url;
 use uuid::Uuid;
 
@@ -9,6 +9,18 @@ use uuid::Uuid;
 pub struct Prediction {
     /// The ID of the operator who created the prediction
     pub operator_id: String,
+    /// The version of the model which produced the prediction
+    pub model_version: Option<String>,
+}
+
+impl Prediction {
+    /// Checks whether the prediction was created by the subject, with the model version if one is specified
+    pub fn produced_by(&self, subject: &str, model_version: Option<&str>) -> bool {
+        self.operator_id == subject
+            && model_version.map_or(true, |version| {
+                self.model_version.as_deref() == Some(version)
+            })
+    }
 }
 
 /// The metadata of an existing image, including the collection of predictions
@@ -19,10 +31,8 @@ pub struct Prediction {
     graphql_type = "Image"
 )]
 pub struct ExistingImage {
-    /// The ID of the plate the imaged well is on
-    pub plate: Uuid,
-    /// The number of the imaged well
-    pub well: i32,
+    /// The unique identifier of the image
+    pub id: Uuid,
     /// A URL from which the image can be retrieved
     pub download_url: Url,
     /// A collection of predictions for the well contents
@@ -32,21 +42,72 @@ pub struct ExistingImage {
 impl From<ExistingImage> for Request {
     fn from(value: ExistingImage) -> Self {
         Self {
-            plate: value.plate,
-            well: value.well,
+            image_id: value.id,
             download_url: value.download_url,
         }
     }
 }
 
+/// A page specification, by cursor and length
+#[derive(Debug, Clone, Default, InputObject)]
+#[cynic(schema = "targeting", schema_module = "crate::schemas::targeting")]
+pub struct CursorInput {
+    /// The cursor after which the page begins
+    pub after: Option<String>,
+    /// The cursor before which the page ends
+    pub before: Option<String>,
+    /// The number of items to take from the start of the page
+    pub first: Option<i32>,
+    /// The number of items to take from the end of the page
+    pub last: Option<i32>,
+}
+
+/// The arguments to the images query.
+#[derive(Debug, QueryVariables)]
+#[cynic(schema_module = "crate::schemas::targeting")]
+pub struct ImagesQueryVariables {
+    /// The page of images to retrieve
+    pub cursor: CursorInput,
+}
+
+/// Information about the position of a page in the collection
+#[derive(Debug, QueryFragment)]
+#[cynic(schema = "targeting", schema_module = "crate::schemas::targeting")]
+pub struct PageInfo {
+    /// Whether further items exist after this page
+    pub has_next_page: bool,
+    /// The cursor of the last item in the page
+    pub end_cursor: Option<String>,
+}
+
+/// An image in a page of images
+#[derive(Debug, QueryFragment)]
+#[cynic(schema = "targeting", schema_module = "crate::schemas::targeting")]
+pub struct ImageEdge {
+    /// The image metadata
+    pub node: ExistingImage,
+}
+
+/// A page of images
+#[derive(Debug, QueryFragment)]
+#[cynic(schema = "targeting", schema_module = "crate::schemas::targeting")]
+pub struct ImageConnection {
+    /// The images in the page
+    pub edges: Vec<ImageEdge>,
+    /// Information about the position of the page in the collection
+    pub page_info: PageInfo,
+}
+
 /// The root query type of the targeting service API
 #[derive(Debug, QueryFragment)]
 #[cynic(
     schema = "targeting",
     schema_module = "crate::schemas::targeting",
-    graphql_type = "RootQuery"
+    graphql_type = "RootQuery",
+    variables = "ImagesQueryVariables"
 )]
 pub struct ImagesQuery {
-    /// A collection of existing images
-    pub images: Vec<ExistingImage>,
+    /// A page of existing images
+    #[arguments(cursor: $cursor)]
+    pub images: ImageConnection,
 }
@@ -6,5 +6,10 @@ pub mod create_prediction;
 #[allow(clippy::missing_docs_in_private_items)]
 pub mod image_created;
 /// A query of the existing images and their predictions
+#[allow(missing_docs)]
 #[allow(clippy::missing_docs_in_private_items)]
 pub mod image_predictions;
+/// A query of the report prediction failure mutation
+#[allow(missing_docs)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub mod report_prediction_failure;
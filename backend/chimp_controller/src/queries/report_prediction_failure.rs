@@ -0,0 +1,51 @@
+use chimp_protocol::FailedResponse;
+use cynic::{QueryFragment, QueryVariables};
+use uuid::Uuid;
+
+/// The response recieved on reporting a prediction failure.
+#[derive(Debug, Clone, QueryFragment)]
+#[cynic(schema = "targeting", schema_module = "crate::schemas::targeting")]
+pub struct PredictionFailure {
+    /// The unique identifier of the image which could not be processed.
+    pub image_id: Uuid,
+}
+
+/// The arguments to the prediction failure reporting mutation.
+#[derive(QueryVariables)]
+#[cynic(schema_module = "crate::schemas::targeting")]
+pub struct ReportPredictionFailureVariables {
+    /// The image which could not be processed.
+    pub image_id: Uuid,
+    /// A description of the error encountered.
+    pub error: String,
+    /// The version of the model which failed to produce a prediction.
+    pub model_version: Option<String>,
+}
+
+impl ReportPredictionFailureVariables {
+    /// Creates the mutation arguments from a failed CHiMP response and the version of the model which produced it.
+    pub fn from_response_and_model_version(
+        value: FailedResponse,
+        model_version: Option<String>,
+    ) -> Self {
+        Self {
+            image_id: value.image_id,
+            error: value.error,
+            model_version,
+        }
+    }
+}
+
+/// The root mutation type of the targeting service API
+#[derive(Debug, Clone, QueryFragment)]
+#[cynic(
+    schema = "targeting",
+    schema_module = "crate::schemas::targeting",
+    graphql_type = "RootMutation",
+    variables = "ReportPredictionFailureVariables"
+)]
+pub struct ReportPredictionFailureMutation {
+    /// A mutation to report that a prediction could not be produced for an image
+    #[arguments(imageId: $image_id, error: $error, modelVersion: $model_version)]
+    pub report_prediction_failure: PredictionFailure,
+}
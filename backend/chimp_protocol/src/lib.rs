@@ -9,10 +9,8 @@ use uuid::Uuid;
 /// A CHiMP processing request definition.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Request {
-    /// The plate of the imaged well.
-    pub plate: Uuid,
-    /// The number of the imaged well.
-    pub well: i32,
+    /// The unique identifier of the image.
+    pub image_id: Uuid,
     /// The pre-signed URL of an object containing the image to perform inference on.
     pub download_url: Url,
 }
@@ -28,13 +26,33 @@ impl Request {
         serde_json::to_vec(&self)
     }
 }
+
+/// The maximum priority supported by the CHiMP request queue.
+pub const MAX_PRIORITY: u8 = 2;
+
+/// The urgency with which a CHiMP [`Request`] should be processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Processing of backlogged images, or reprocessing of existing images.
+    Low,
+    /// Processing of newly created images.
+    High,
+}
+
+impl From<Priority> for u8 {
+    fn from(value: Priority) -> Self {
+        match value {
+            Priority::Low => 1,
+            Priority::High => MAX_PRIORITY,
+        }
+    }
+}
+
 /// The image was processed successfully, producing the contained predictions.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SuccesfulResponse {
-    /// The plate of the imaged well.
-    pub plate: Uuid,
-    /// The number of the imaged well.
-    pub well: i32,
+    /// The unique identifier of the image.
+    pub image_id: Uuid,
     /// The proposed point for solvent insertion.
     pub insertion_point: Point,
     /// The location of the well centroid and radius.
@@ -48,10 +66,8 @@ pub struct SuccesfulResponse {
 /// Image processing failed, with the contained error.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FailedResponse {
-    /// The plate of the imaged well.
-    pub plate: Uuid,
-    /// The number of the imaged well.
-    pub well: i32,
+    /// The unique identifier of the image.
+    pub image_id: Uuid,
     /// A description of the error encountered.
     pub error: String,
 }
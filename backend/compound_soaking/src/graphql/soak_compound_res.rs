@@ -126,6 +126,26 @@ impl SoakCompoundQuery {
             well_number,
         }
     }
+
+    /// Reference resolver for soaked compounds
+    #[graphql(entity)]
+    async fn route_soaked_compound(
+        &self,
+        ctx: &Context<'_>,
+        compound_plate_id: Uuid,
+        compound_well_number: i16,
+        crystal_plate_id: Uuid,
+        crystal_well_number: i16,
+    ) -> async_graphql::Result<Option<soak_compound::Model>> {
+        self.soaked_compound(
+            ctx,
+            compound_plate_id,
+            compound_well_number,
+            crystal_plate_id,
+            crystal_well_number,
+        )
+        .await
+    }
 }
 
 #[Object]
@@ -1,8 +1,11 @@
-use crate::tables::{crystal_plates, crystal_wells};
-use async_graphql::{ComplexObject, Context, Object};
+use crate::{
+    loaders::PlateWellsLoader,
+    tables::{crystal_plates, crystal_wells},
+};
+use async_graphql::{dataloader::DataLoader, ComplexObject, Context, Object};
 use chrono::Utc;
 use opa_client::subject_authorization;
-use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait, ModelTrait};
+use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait};
 use the_paginator::graphql::{CursorInput, ModelConnection};
 use uuid::Uuid;
 
@@ -19,8 +22,11 @@ impl crystal_plates::Model {
     /// This function fetches all crystal well on the crytal plate
     async fn wells(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<crystal_wells::Model>> {
         subject_authorization!("xchemlab.crystal_library.read_crystal_plates", ctx).await?;
-        let db = ctx.data::<DatabaseConnection>()?;
-        Ok(self.find_related(crystal_wells::Entity).all(db).await?)
+        Ok(ctx
+            .data::<DataLoader<PlateWellsLoader>>()?
+            .load_one(self.plate_id)
+            .await?
+            .unwrap_or_default())
     }
 }
 
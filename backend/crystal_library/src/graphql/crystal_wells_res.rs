@@ -1,8 +1,11 @@
-use crate::tables::{crystal_plates, crystal_wells};
-use async_graphql::{ComplexObject, Context, Object};
+use crate::{
+    loaders::CrystalPlateLoader,
+    tables::{crystal_plates, crystal_wells},
+};
+use async_graphql::{dataloader::DataLoader, ComplexObject, Context, Object};
 use chrono::Utc;
 use opa_client::subject_authorization;
-use sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter};
+use sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
 use the_paginator::graphql::{CursorInput, ModelConnection};
 use uuid::Uuid;
 
@@ -22,8 +25,10 @@ impl crystal_wells::Model {
         ctx: &Context<'_>,
     ) -> async_graphql::Result<Option<crystal_plates::Model>> {
         subject_authorization!("xchemlab.crystal_library.read_crystal_wells", ctx).await?;
-        let db = ctx.data::<DatabaseConnection>()?;
-        Ok(self.find_related(crystal_plates::Entity).one(db).await?)
+        Ok(ctx
+            .data::<DataLoader<CrystalPlateLoader>>()?
+            .load_one(self.plate_id)
+            .await?)
     }
 }
 
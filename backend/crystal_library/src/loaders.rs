@@ -0,0 +1,67 @@
+use crate::{
+    graphql::{Mutation, Query},
+    tables::{crystal_plates, crystal_wells},
+};
+use async_graphql::{
+    dataloader::{DataLoader, Loader},
+    EmptySubscription, SchemaBuilder,
+};
+use axum::async_trait;
+use graphql_loaders::{load_grouped, load_keyed, share_connection};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use std::{collections::HashMap, sync::Arc};
+use uuid::Uuid;
+
+/// Adds the [`DataLoader`]s used to batch lookups of related entities to the schema
+pub fn add_loaders(
+    schema_builder: SchemaBuilder<Query, Mutation, EmptySubscription>,
+    db: &DatabaseConnection,
+) -> SchemaBuilder<Query, Mutation, EmptySubscription> {
+    schema_builder
+        .data(DataLoader::new(
+            CrystalPlateLoader(share_connection(db)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            PlateWellsLoader(share_connection(db)),
+            tokio::spawn,
+        ))
+}
+
+/// Loads crystal plates, keyed by plate ID
+pub struct CrystalPlateLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for CrystalPlateLoader {
+    type Value = crystal_plates::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_keyed(
+            &self.0,
+            crystal_plates::Entity::find()
+                .filter(crystal_plates::Column::PlateId.is_in(keys.iter().copied())),
+            |plate| plate.plate_id,
+        )
+        .await
+    }
+}
+
+/// Loads the crystal wells on plates, keyed by plate ID
+pub struct PlateWellsLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for PlateWellsLoader {
+    type Value = Vec<crystal_wells::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_grouped(
+            &self.0,
+            crystal_wells::Entity::find()
+                .filter(crystal_wells::Column::PlateId.is_in(keys.iter().copied())),
+            |well| well.plate_id,
+        )
+        .await
+    }
+}
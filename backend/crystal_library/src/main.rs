@@ -6,6 +6,8 @@
 /// This module sets up the GraphQL schema, including queries, mutations,
 /// and subscriptions. It defines how data is queried and mutated through the API.
 mod graphql;
+/// This module provides the data loaders used to batch lookups of related entities.
+mod loaders;
 /// This module is responsible for defining and applying database migrations.
 mod migrator;
 /// This module defines the structure and schema of the database tables
@@ -17,6 +19,7 @@ use axum::{routing::get, Router, Server};
 use clap::Parser;
 use graphql::{root_schema_builder, RootSchema};
 use graphql_endpoints::{GraphQLHandler, GraphQLSubscription, GraphiQLHandler};
+use loaders::add_loaders;
 use opa_client::OPAClient;
 use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbErr, TransactionError};
 use sea_orm_migration::MigratorTrait;
@@ -121,7 +124,7 @@ async fn main() {
         Cli::Serve(args) => {
             let db = setup_database(args.database_url).await.unwrap();
             let opa_client = OPAClient::new(args.opa_url);
-            let schema = root_schema_builder()
+            let schema = add_loaders(root_schema_builder(), &db)
                 .data(db)
                 .data(opa_client)
                 .extension(Tracing)
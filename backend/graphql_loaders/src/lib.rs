@@ -0,0 +1,142 @@
+#![forbid(unsafe_code)]
+#![warn(missing_docs)]
+#![doc = include_str!("../README.md")]
+
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait, Select, SqlxPostgresConnector};
+use std::{collections::HashMap, hash::Hash, sync::Arc};
+
+/// Creates a connection which shares the connection pool of another.
+pub fn share_connection(database: &DatabaseConnection) -> DatabaseConnection {
+    SqlxPostgresConnector::from_sqlx_postgres_pool(database.get_postgres_connection_pool().clone())
+}
+
+/// Performs a query, keying each of the returned models.
+///
+/// # Example
+/// ```no_run
+/// # use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+/// # use std::{collections::HashMap, sync::Arc};
+/// # mod cake {
+/// #     use sea_orm::entity::prelude::*;
+/// #     #[derive(Debug, Clone, PartialEq, DeriveEntityModel)]
+/// #     #[sea_orm(table_name = "cake")]
+/// #     pub struct Model {
+/// #         #[sea_orm(primary_key)]
+/// #         pub id: i32,
+/// #     }
+/// #     #[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
+/// #     pub enum Relation {}
+/// #     impl ActiveModelBehavior for ActiveModel {}
+/// # }
+/// async fn load_cakes(
+///     database: &DatabaseConnection,
+///     keys: &[i32],
+/// ) -> Result<HashMap<i32, cake::Model>, Arc<DbErr>> {
+///     graphql_loaders::load_keyed(
+///         database,
+///         cake::Entity::find().filter(cake::Column::Id.is_in(keys.iter().copied())),
+///         |cake| cake.id,
+///     )
+///     .await
+/// }
+/// ```
+pub async fn load_keyed<E: EntityTrait, K: Eq + Hash>(
+    database: &DatabaseConnection,
+    select: Select<E>,
+    key: impl Fn(&E::Model) -> K,
+) -> Result<HashMap<K, E::Model>, Arc<DbErr>> {
+    Ok(select
+        .all(database)
+        .await?
+        .into_iter()
+        .map(|model| (key(&model), model))
+        .collect())
+}
+
+/// Performs a query, grouping the returned models by key whilst retaining their order.
+pub async fn load_grouped<E: EntityTrait, K: Eq + Hash>(
+    database: &DatabaseConnection,
+    select: Select<E>,
+    key: impl Fn(&E::Model) -> K,
+) -> Result<HashMap<K, Vec<E::Model>>, Arc<DbErr>> {
+    let mut groups = HashMap::<_, Vec<_>>::new();
+    for model in select.all(database).await? {
+        groups.entry(key(&model)).or_default().push(model);
+    }
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_grouped, load_keyed};
+    use sea_orm::{DatabaseBackend, EntityTrait, MockDatabase};
+    use std::collections::HashMap;
+
+    mod slice {
+        use sea_orm::entity::prelude::*;
+
+        #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+        #[sea_orm(table_name = "slice")]
+        pub struct Model {
+            #[sea_orm(primary_key)]
+            pub id: i32,
+            pub cake_id: i32,
+        }
+
+        #[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
+        pub enum Relation {}
+
+        impl ActiveModelBehavior for ActiveModel {}
+    }
+
+    fn slices() -> Vec<slice::Model> {
+        [(3, 1), (1, 2), (2, 1)]
+            .into_iter()
+            .map(|(id, cake_id)| slice::Model { id, cake_id })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn models_are_keyed() {
+        let database = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([slices()])
+            .into_connection();
+
+        let keyed = load_keyed(&database, slice::Entity::find(), |slice| slice.id)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            slices()
+                .into_iter()
+                .map(|slice| (slice.id, slice))
+                .collect::<HashMap<_, _>>(),
+            keyed
+        );
+    }
+
+    #[tokio::test]
+    async fn groups_retain_query_order() {
+        let database = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([slices()])
+            .into_connection();
+
+        let grouped = load_grouped(&database, slice::Entity::find(), |slice| slice.cake_id)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            HashMap::from([
+                (
+                    1,
+                    vec![
+                        slice::Model { id: 3, cake_id: 1 },
+                        slice::Model { id: 2, cake_id: 1 }
+                    ]
+                ),
+                (2, vec![slice::Model { id: 1, cake_id: 2 }]),
+            ]),
+            grouped
+        );
+    }
+}
@@ -28,7 +28,7 @@ macro_rules! subject_authorization {
                 )
                 .await?
                 .into_result()
-                .map_err(::async_graphql::Error::from)
+                .map_err(::async_graphql::Error::new_with_source)
         }
     };
 }
@@ -0,0 +1,406 @@
+//! Barcode formats for library items.
+//!
+//! Each kind of library item may be given its own barcode format when the service is started,
+//! made up of any combination of a required prefix, a regular expression which the whole barcode
+//! must match and a check digit scheme which the final digit of the barcode must satisfy. Kinds
+//! of library item without a format accept any barcode without whitespace.
+//!
+//! Barcodes which do not satisfy the format of the expected kind of item but do satisfy the
+//! format of another kind, or which are already registered as another kind, are reported as
+//! scans of the wrong kind of item.
+
+use crate::tables::{cane_library, pin_library, puck_library, status_change::LibraryItem};
+use async_graphql::ErrorExtensions;
+use clap::{Args, ValueEnum};
+use regex::Regex;
+use sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QuerySelect, Select};
+use std::collections::HashMap;
+
+/// A scheme for computing the final digit of a barcode from the digits preceding it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CheckDigit {
+    /// The Luhn algorithm, doubling every second digit from the right
+    Luhn,
+    /// The GS1 algorithm used by EAN and GTIN codes, weighting digits from the right by three and one
+    Gs1,
+}
+
+impl CheckDigit {
+    /// Whether the final character of the barcode is the check digit of the digits preceding it
+    fn verify(self, barcode: &str) -> bool {
+        let mut digits = barcode.chars().rev();
+        let Some(check) = digits.next().and_then(|check| check.to_digit(10)) else {
+            return false;
+        };
+        let digits = digits.filter_map(|digit| digit.to_digit(10));
+        let expected = match self {
+            CheckDigit::Luhn => digits
+                .zip([2, 1].into_iter().cycle())
+                .map(|(digit, weight)| {
+                    let product = digit * weight;
+                    product / 10 + product % 10
+                })
+                .sum::<u32>(),
+            CheckDigit::Gs1 => digits
+                .zip([3, 1].into_iter().cycle())
+                .map(|(digit, weight)| digit * weight)
+                .sum::<u32>(),
+        };
+        (10 - expected % 10) % 10 == check
+    }
+}
+
+/// Parses a regular expression which must match the whole of a barcode
+fn parse_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{pattern})$"))
+}
+
+/// Arguments for configuring the barcode formats of library items, checked when they are registered.
+#[derive(Debug, Clone, Args)]
+pub struct BarcodeFormatArgs {
+    /// A prefix which pin barcodes must start with.
+    #[arg(long, env)]
+    pin_barcode_prefix: Option<String>,
+    /// A regular expression which pin barcodes must match in full.
+    #[arg(long, env, value_parser = parse_pattern)]
+    pin_barcode_pattern: Option<Regex>,
+    /// The check digit scheme which pin barcodes end with.
+    #[arg(long, env)]
+    pin_barcode_check_digit: Option<CheckDigit>,
+    /// A prefix which puck barcodes must start with.
+    #[arg(long, env)]
+    puck_barcode_prefix: Option<String>,
+    /// A regular expression which puck barcodes must match in full.
+    #[arg(long, env, value_parser = parse_pattern)]
+    puck_barcode_pattern: Option<Regex>,
+    /// The check digit scheme which puck barcodes end with.
+    #[arg(long, env)]
+    puck_barcode_check_digit: Option<CheckDigit>,
+    /// A prefix which cane barcodes must start with.
+    #[arg(long, env)]
+    cane_barcode_prefix: Option<String>,
+    /// A regular expression which cane barcodes must match in full.
+    #[arg(long, env, value_parser = parse_pattern)]
+    cane_barcode_pattern: Option<Regex>,
+    /// The check digit scheme which cane barcodes end with.
+    #[arg(long, env)]
+    cane_barcode_check_digit: Option<CheckDigit>,
+}
+
+/// A reason a barcode cannot be used for a kind of library item
+#[derive(Debug, thiserror::Error)]
+pub enum BarcodeError {
+    /// The barcode contains no characters
+    #[error("Barcode is empty")]
+    Empty,
+    /// The barcode contains whitespace or control characters
+    #[error("Barcode '{0}' contains whitespace")]
+    Whitespace(String),
+    /// The barcode does not start with the prefix of the kind of item
+    #[error("{item:?} barcode '{barcode}' does not start with '{prefix}'")]
+    MissingPrefix {
+        item: LibraryItem,
+        barcode: String,
+        prefix: String,
+    },
+    /// The barcode does not match the pattern of the kind of item
+    #[error("{item:?} barcode '{barcode}' does not match the pattern '{pattern}'")]
+    PatternMismatch {
+        item: LibraryItem,
+        barcode: String,
+        pattern: String,
+    },
+    /// The final digit of the barcode is not the check digit of the digits preceding it
+    #[error("{item:?} barcode '{barcode}' does not end with a valid {scheme:?} check digit")]
+    CheckDigitMismatch {
+        item: LibraryItem,
+        barcode: String,
+        scheme: CheckDigit,
+    },
+    /// The barcode belongs to another kind of item
+    #[error("Barcode '{barcode}' belongs to a {actual:?}, not a {expected:?}")]
+    WrongItem {
+        barcode: String,
+        expected: LibraryItem,
+        actual: LibraryItem,
+    },
+}
+
+impl BarcodeError {
+    /// A machine readable identifier for the kind of error
+    fn code(&self) -> &'static str {
+        match self {
+            BarcodeError::Empty => "BARCODE_EMPTY",
+            BarcodeError::Whitespace(_) => "BARCODE_WHITESPACE",
+            BarcodeError::MissingPrefix { .. } => "BARCODE_MISSING_PREFIX",
+            BarcodeError::PatternMismatch { .. } => "BARCODE_PATTERN_MISMATCH",
+            BarcodeError::CheckDigitMismatch { .. } => "BARCODE_CHECK_DIGIT_MISMATCH",
+            BarcodeError::WrongItem { .. } => "BARCODE_WRONG_ITEM",
+        }
+    }
+}
+
+impl ErrorExtensions for BarcodeError {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, extensions| {
+            extensions.set("code", self.code());
+            match self {
+                BarcodeError::Empty => {}
+                BarcodeError::Whitespace(barcode)
+                | BarcodeError::MissingPrefix { barcode, .. }
+                | BarcodeError::PatternMismatch { barcode, .. }
+                | BarcodeError::CheckDigitMismatch { barcode, .. } => {
+                    extensions.set("barcode", barcode.as_str())
+                }
+                BarcodeError::WrongItem {
+                    barcode,
+                    expected,
+                    actual,
+                } => {
+                    extensions.set("barcode", barcode.as_str());
+                    extensions.set("expected", *expected);
+                    extensions.set("actual", *actual);
+                }
+            }
+        })
+    }
+}
+
+/// The barcode format of a kind of library item
+#[derive(Debug, Clone, Default)]
+pub struct BarcodeFormat {
+    prefix: Option<String>,
+    pattern: Option<Regex>,
+    check_digit: Option<CheckDigit>,
+}
+
+impl BarcodeFormat {
+    /// Whether the format places any requirements on barcodes beyond the absence of whitespace
+    fn is_configured(&self) -> bool {
+        self.prefix.is_some() || self.pattern.is_some() || self.check_digit.is_some()
+    }
+
+    /// Checks that a barcode satisfies the format
+    fn check(&self, item: LibraryItem, barcode: &str) -> Result<(), BarcodeError> {
+        if barcode.is_empty() {
+            return Err(BarcodeError::Empty);
+        }
+        if barcode
+            .chars()
+            .any(|character| character.is_whitespace() || character.is_control())
+        {
+            return Err(BarcodeError::Whitespace(barcode.to_string()));
+        }
+        if let Some(prefix) = self
+            .prefix
+            .as_ref()
+            .filter(|prefix| !barcode.starts_with(*prefix))
+        {
+            return Err(BarcodeError::MissingPrefix {
+                item,
+                barcode: barcode.to_string(),
+                prefix: prefix.clone(),
+            });
+        }
+        if let Some(pattern) = self
+            .pattern
+            .as_ref()
+            .filter(|pattern| !pattern.is_match(barcode))
+        {
+            return Err(BarcodeError::PatternMismatch {
+                item,
+                barcode: barcode.to_string(),
+                pattern: pattern.as_str().to_string(),
+            });
+        }
+        if let Some(scheme) = self.check_digit.filter(|scheme| !scheme.verify(barcode)) {
+            return Err(BarcodeError::CheckDigitMismatch {
+                item,
+                barcode: barcode.to_string(),
+                scheme,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The barcode formats of each kind of library item
+#[derive(Debug, Clone, Default)]
+pub struct BarcodeFormats {
+    pin: BarcodeFormat,
+    puck: BarcodeFormat,
+    cane: BarcodeFormat,
+}
+
+impl From<BarcodeFormatArgs> for BarcodeFormats {
+    fn from(args: BarcodeFormatArgs) -> Self {
+        Self {
+            pin: BarcodeFormat {
+                prefix: args.pin_barcode_prefix,
+                pattern: args.pin_barcode_pattern,
+                check_digit: args.pin_barcode_check_digit,
+            },
+            puck: BarcodeFormat {
+                prefix: args.puck_barcode_prefix,
+                pattern: args.puck_barcode_pattern,
+                check_digit: args.puck_barcode_check_digit,
+            },
+            cane: BarcodeFormat {
+                prefix: args.cane_barcode_prefix,
+                pattern: args.cane_barcode_pattern,
+                check_digit: args.cane_barcode_check_digit,
+            },
+        }
+    }
+}
+
+impl BarcodeFormats {
+    fn format(&self, item: LibraryItem) -> &BarcodeFormat {
+        match item {
+            LibraryItem::Pin => &self.pin,
+            LibraryItem::Puck => &self.puck,
+            LibraryItem::Cane => &self.cane,
+        }
+    }
+
+    /// Checks that a barcode satisfies the format of the kind of item and no other
+    pub fn check(&self, item: LibraryItem, barcode: &str) -> Result<(), BarcodeError> {
+        let format = self.format(item);
+        let result = format.check(item, barcode);
+        if result.is_ok() && format.is_configured() {
+            return result;
+        }
+        // Barcodes which only satisfy another format were most likely scanned from another item
+        let actual = [LibraryItem::Pin, LibraryItem::Puck, LibraryItem::Cane]
+            .into_iter()
+            .filter(|other| *other != item)
+            .find(|other| {
+                let format = self.format(*other);
+                format.is_configured() && format.check(*other, barcode).is_ok()
+            });
+        match actual {
+            Some(actual) => Err(BarcodeError::WrongItem {
+                barcode: barcode.to_string(),
+                expected: item,
+                actual,
+            }),
+            None => result,
+        }
+    }
+}
+
+/// Selects the barcodes of a library which are amongst those given
+fn select_barcodes<E: EntityTrait>(
+    column: E::Column,
+    barcodes: impl IntoIterator<Item = String>,
+) -> Select<E> {
+    E::find()
+        .select_only()
+        .column(column)
+        .filter(column.is_in(barcodes))
+}
+
+/// Finds the kind of library item each of the barcodes is registered as, if any
+pub async fn find_registered(
+    database: &impl ConnectionTrait,
+    barcodes: &[String],
+) -> Result<HashMap<String, LibraryItem>, DbErr> {
+    let mut registered = HashMap::new();
+    let pins = select_barcodes::<pin_library::Entity>(
+        pin_library::Column::Barcode,
+        barcodes.iter().cloned(),
+    );
+    let pucks = select_barcodes::<puck_library::Entity>(
+        puck_library::Column::Barcode,
+        barcodes.iter().cloned(),
+    );
+    let canes = select_barcodes::<cane_library::Entity>(
+        cane_library::Column::Barcode,
+        barcodes.iter().cloned(),
+    );
+    for barcode in pins.into_tuple::<String>().all(database).await? {
+        registered.insert(barcode, LibraryItem::Pin);
+    }
+    for barcode in pucks.into_tuple::<String>().all(database).await? {
+        registered.insert(barcode, LibraryItem::Puck);
+    }
+    for barcode in canes.into_tuple::<String>().all(database).await? {
+        registered.insert(barcode, LibraryItem::Cane);
+    }
+    Ok(registered)
+}
+
+/// Checks that a barcode is not registered as another kind of item
+pub async fn check_unregistered_elsewhere(
+    database: &impl ConnectionTrait,
+    item: LibraryItem,
+    barcode: &str,
+) -> async_graphql::Result<()> {
+    let registered = find_registered(database, &[barcode.to_string()]).await?;
+    match registered.get(barcode) {
+        Some(actual) if *actual != item => Err(BarcodeError::WrongItem {
+            barcode: barcode.to_string(),
+            expected: item,
+            actual: *actual,
+        }
+        .extend()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_pattern, BarcodeError, BarcodeFormat, BarcodeFormats, CheckDigit};
+    use crate::tables::status_change::LibraryItem;
+
+    #[test]
+    fn luhn_check_digit() {
+        assert!(CheckDigit::Luhn.verify("79927398713"));
+        assert!(CheckDigit::Luhn.verify("PIN-7992739871-3"));
+        assert!(!CheckDigit::Luhn.verify("79927398710"));
+        assert!(!CheckDigit::Luhn.verify("7992739871X"));
+    }
+
+    #[test]
+    fn gs1_check_digit() {
+        assert!(CheckDigit::Gs1.verify("4006381333931"));
+        assert!(CheckDigit::Gs1.verify("036000291452"));
+        assert!(!CheckDigit::Gs1.verify("4006381333932"));
+    }
+
+    #[test]
+    fn wrong_item_detected_by_format() {
+        let formats = BarcodeFormats {
+            pin: BarcodeFormat {
+                prefix: Some("PIN".to_string()),
+                ..Default::default()
+            },
+            puck: BarcodeFormat {
+                pattern: Some(parse_pattern("PK[0-9]{4}").unwrap()),
+                ..Default::default()
+            },
+            cane: BarcodeFormat::default(),
+        };
+        assert!(formats.check(LibraryItem::Pin, "PIN0001").is_ok());
+        assert!(formats.check(LibraryItem::Puck, "PK0001").is_ok());
+        assert!(matches!(
+            formats.check(LibraryItem::Pin, "PK0001"),
+            Err(BarcodeError::WrongItem {
+                actual: LibraryItem::Puck,
+                ..
+            })
+        ));
+        assert!(matches!(
+            formats.check(LibraryItem::Cane, "PIN0001"),
+            Err(BarcodeError::WrongItem {
+                actual: LibraryItem::Pin,
+                ..
+            })
+        ));
+        assert!(matches!(
+            formats.check(LibraryItem::Puck, "PK00011"),
+            Err(BarcodeError::PatternMismatch { .. })
+        ));
+        assert!(formats.check(LibraryItem::Cane, "CANE0001").is_ok());
+    }
+}
@@ -2,10 +2,12 @@ use crate::resolvers::{
     cane_library::{CaneLibraryMutation, CaneLibraryQuery},
     cane_mount::{CaneMountMutation, CaneMountQuery},
     crystal::{CrystalMutation, CrystalQuery},
+    manifest::ManifestQuery,
     pin_library::{PinLibraryMutation, PinLibraryQuery},
     pin_mount::{PinMountMutation, PinMountQuery},
     puck_library::{PuckLibraryMutation, PuckLibraryQuery},
     puck_mount::{PuckMountMutation, PuckMountQuery},
+    subgraph_extensions::SubgraphExtensionsQuery,
 };
 use async_graphql::{EmptySubscription, MergedObject, Schema, SchemaBuilder};
 
@@ -15,6 +17,7 @@ pub fn root_schema_builder() -> SchemaBuilder<RootQuery, RootMutation, EmptySubs
         RootMutation::default(),
         EmptySubscription,
     )
+    .enable_federation()
 }
 
 pub type RootSchema = Schema<RootQuery, RootMutation, EmptySubscription>;
@@ -28,6 +31,8 @@ pub struct RootQuery(
     PuckMountQuery,
     PinLibraryQuery,
     PinMountQuery,
+    ManifestQuery,
+    SubgraphExtensionsQuery,
 );
 
 #[derive(Debug, Clone, MergedObject, Default)]
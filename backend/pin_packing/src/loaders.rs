@@ -0,0 +1,356 @@
+use crate::{
+    graphql::{RootMutation, RootQuery},
+    tables::{
+        cane_library, cane_mount, cane_type, crystal, pin_mount, puck_library, puck_mount,
+        puck_type,
+        status_change::{self, LibraryItem},
+    },
+};
+use async_graphql::{
+    dataloader::{DataLoader, Loader},
+    EmptySubscription, SchemaBuilder,
+};
+use axum::async_trait;
+use graphql_loaders::{load_grouped, load_keyed, share_connection};
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, JoinType, QueryFilter,
+    QueryOrder, QuerySelect, RelationTrait,
+};
+use std::{collections::HashMap, sync::Arc};
+use uuid::Uuid;
+
+/// Adds the [`DataLoader`]s used to batch lookups of related entities to the schema
+pub fn add_loaders(
+    schema_builder: SchemaBuilder<RootQuery, RootMutation, EmptySubscription>,
+    database: &DatabaseConnection,
+) -> SchemaBuilder<RootQuery, RootMutation, EmptySubscription> {
+    schema_builder
+        .data(DataLoader::new(
+            CrystalLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            WellCrystalsLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            CrystalPinMountLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            PuckMountLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            PuckPinsLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            CaneMountLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            CanePucksLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            PinMountsByBarcodeLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            PuckMountsByBarcodeLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            CaneMountsByBarcodeLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            StatusHistoryLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            PuckMountTypeLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            CaneMountTypeLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+}
+
+pub struct CrystalLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for CrystalLoader {
+    type Value = crystal::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_keyed(
+            &self.0,
+            crystal::Entity::find().filter(crystal::Column::Id.is_in(keys.iter().copied())),
+            |crystal| crystal.id,
+        )
+        .await
+    }
+}
+
+/// Loads the crystals in wells, keyed by plate and well
+pub struct WellCrystalsLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<(Uuid, i16)> for WellCrystalsLoader {
+    type Value = Vec<crystal::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[(Uuid, i16)],
+    ) -> Result<HashMap<(Uuid, i16), Self::Value>, Self::Error> {
+        let wells = keys
+            .iter()
+            .fold(Condition::any(), |condition, (plate, well)| {
+                condition.add(
+                    crystal::Column::Plate
+                        .eq(*plate)
+                        .and(crystal::Column::Well.eq(*well)),
+                )
+            });
+        load_grouped(&self.0, crystal::Entity::find().filter(wells), |crystal| {
+            (crystal.plate, crystal.well)
+        })
+        .await
+    }
+}
+
+/// Loads the most recent pin mount of crystals, keyed by crystal
+pub struct CrystalPinMountLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for CrystalPinMountLoader {
+    type Value = pin_mount::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_keyed(
+            &self.0,
+            pin_mount::Entity::find()
+                .filter(pin_mount::Column::CrystalId.is_in(keys.iter().copied()))
+                .order_by_asc(pin_mount::Column::Timestamp),
+            |pin_mount| pin_mount.crystal_id,
+        )
+        .await
+    }
+}
+
+pub struct PuckMountLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for PuckMountLoader {
+    type Value = puck_mount::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_keyed(
+            &self.0,
+            puck_mount::Entity::find().filter(puck_mount::Column::Id.is_in(keys.iter().copied())),
+            |puck_mount| puck_mount.id,
+        )
+        .await
+    }
+}
+
+/// Loads the pins currently mounted in pucks, keyed by puck mount
+pub struct PuckPinsLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for PuckPinsLoader {
+    type Value = Vec<pin_mount::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_grouped(
+            &self.0,
+            pin_mount::Entity::find()
+                .filter(pin_mount::Column::PuckMountId.is_in(keys.iter().copied()))
+                .filter(pin_mount::Column::UnmountedAt.is_null()),
+            |pin_mount| pin_mount.puck_mount_id,
+        )
+        .await
+    }
+}
+
+pub struct CaneMountLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for CaneMountLoader {
+    type Value = cane_mount::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_keyed(
+            &self.0,
+            cane_mount::Entity::find().filter(cane_mount::Column::Id.is_in(keys.iter().copied())),
+            |cane_mount| cane_mount.id,
+        )
+        .await
+    }
+}
+
+/// Loads the pucks mounted in canes, keyed by cane mount
+pub struct CanePucksLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for CanePucksLoader {
+    type Value = Vec<puck_mount::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let mut pucks = load_grouped(
+            &self.0,
+            puck_mount::Entity::find()
+                .filter(puck_mount::Column::CaneMountId.is_in(keys.iter().copied())),
+            |puck_mount| puck_mount.cane_mount_id,
+        )
+        .await?;
+        Ok(keys
+            .iter()
+            .filter_map(|key| Some((*key, pucks.remove(&Some(*key))?)))
+            .collect())
+    }
+}
+
+/// Loads the mounts of library pins in the order they were made, keyed by barcode
+pub struct PinMountsByBarcodeLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<String> for PinMountsByBarcodeLoader {
+    type Value = Vec<pin_mount::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        load_grouped(
+            &self.0,
+            pin_mount::Entity::find()
+                .filter(pin_mount::Column::Barcode.is_in(keys.iter().cloned()))
+                .order_by_asc(pin_mount::Column::Timestamp),
+            |pin_mount| pin_mount.barcode.clone(),
+        )
+        .await
+    }
+}
+
+/// Loads the mounts of library pucks, keyed by barcode
+pub struct PuckMountsByBarcodeLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<String> for PuckMountsByBarcodeLoader {
+    type Value = Vec<puck_mount::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        load_grouped(
+            &self.0,
+            puck_mount::Entity::find()
+                .filter(puck_mount::Column::Barcode.is_in(keys.iter().cloned())),
+            |puck_mount| puck_mount.barcode.clone(),
+        )
+        .await
+    }
+}
+
+/// Loads the mounts of library canes, keyed by barcode
+pub struct CaneMountsByBarcodeLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<String> for CaneMountsByBarcodeLoader {
+    type Value = Vec<cane_mount::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        load_grouped(
+            &self.0,
+            cane_mount::Entity::find()
+                .filter(cane_mount::Column::Barcode.is_in(keys.iter().cloned())),
+            |cane_mount| cane_mount.barcode.clone(),
+        )
+        .await
+    }
+}
+
+/// Loads the status changes of library items in the order they were made, keyed by item and barcode
+pub struct StatusHistoryLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<(LibraryItem, String)> for StatusHistoryLoader {
+    type Value = Vec<status_change::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[(LibraryItem, String)],
+    ) -> Result<HashMap<(LibraryItem, String), Self::Value>, Self::Error> {
+        let items = keys
+            .iter()
+            .fold(Condition::any(), |condition, (item, barcode)| {
+                condition.add(
+                    status_change::Column::Item
+                        .eq(*item)
+                        .and(status_change::Column::Barcode.eq(barcode.as_str())),
+                )
+            });
+        load_grouped(
+            &self.0,
+            status_change::Entity::find()
+                .filter(items)
+                .order_by_asc(status_change::Column::Timestamp),
+            |change| (change.item, change.barcode.clone()),
+        )
+        .await
+    }
+}
+
+/// Loads the types of mounted pucks, keyed by puck mount
+pub struct PuckMountTypeLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for PuckMountTypeLoader {
+    type Value = puck_type::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        Ok(puck_type::Entity::find()
+            .join(JoinType::InnerJoin, puck_type::Relation::LibraryPuck.def())
+            .join(JoinType::InnerJoin, puck_library::Relation::PuckMount.def())
+            .filter(puck_mount::Column::Id.is_in(keys.iter().copied()))
+            .select_also(puck_mount::Entity)
+            .all(&self.0)
+            .await?
+            .into_iter()
+            .filter_map(|(puck_type, puck_mount)| Some((puck_mount?.id, puck_type)))
+            .collect())
+    }
+}
+
+/// Loads the types of mounted canes, keyed by cane mount
+pub struct CaneMountTypeLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for CaneMountTypeLoader {
+    type Value = cane_type::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        Ok(cane_type::Entity::find()
+            .join(JoinType::InnerJoin, cane_type::Relation::LibraryCane.def())
+            .join(JoinType::InnerJoin, cane_library::Relation::CaneMount.def())
+            .filter(cane_mount::Column::Id.is_in(keys.iter().copied()))
+            .select_also(cane_mount::Entity)
+            .all(&self.0)
+            .await?
+            .into_iter()
+            .filter_map(|(cane_type, cane_mount)| Some((cane_mount?.id, cane_type)))
+            .collect())
+    }
+}
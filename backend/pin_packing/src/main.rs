@@ -1,17 +1,24 @@
 #![doc=include_str!("../README.md")]
 #![forbid(unsafe_code)]
+mod barcode;
 mod graphql;
+mod loaders;
+mod manifest;
 mod migrations;
 mod resolvers;
 mod tables;
 
 use async_graphql::extensions::Tracing;
+use aws_sdk_s3::Client;
 use axum::{routing::get, Router, Server};
-use clap::Parser;
+use barcode::{BarcodeFormatArgs, BarcodeFormats};
+use clap::{ArgAction::SetTrue, Parser};
 use graphql::{root_schema_builder, RootSchema};
 use graphql_endpoints::{GraphQLHandler, GraphQLSubscription, GraphiQLHandler};
+use loaders::add_loaders;
 use migrations::Migrator;
 use opa_client::OPAClient;
+use s3_config::{setup_bucket, FromS3ClientArgs, S3Bucket, S3ClientArgs};
 use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbErr, TransactionError};
 use sea_orm_migration::MigratorTrait;
 use std::{
@@ -80,6 +87,18 @@ struct ServeArgs {
     /// The URL of an Open Policy Agent instance serving the required policy endpoints.
     #[arg(long, env)]
     opa_url: Url,
+    /// The S3 bucket which crystal photos are to be stored in. Photos are rejected if not set.
+    #[arg(long, env)]
+    s3_bucket: Option<S3Bucket>,
+    /// Create the S3 bucket if it does not exist.
+    #[arg(long, env, action = SetTrue)]
+    s3_create_bucket: bool,
+    /// Configuration argument of the S3 client.
+    #[command(flatten)]
+    s3_client: S3ClientArgs,
+    /// The formats which library item barcodes must satisfy.
+    #[command(flatten)]
+    barcode_formats: BarcodeFormatArgs,
 }
 
 #[derive(Debug, Parser)]
@@ -103,11 +122,19 @@ async fn main() {
         Cli::Serve(args) => {
             let opa_client = OPAClient::new(args.opa_url);
             let database = setup_database(args.database_url).await.unwrap();
-            let schema = root_schema_builder()
+            let mut schema_builder = add_loaders(root_schema_builder(), &database)
                 .extension(Tracing)
                 .data(opa_client)
                 .data(database)
-                .finish();
+                .data(BarcodeFormats::from(args.barcode_formats));
+            if let Some(s3_bucket) = args.s3_bucket {
+                let s3_client = Client::from_s3_client_args(args.s3_client);
+                if args.s3_create_bucket {
+                    setup_bucket(&s3_client, s3_bucket.clone()).await.unwrap();
+                }
+                schema_builder = schema_builder.data(s3_client).data(s3_bucket);
+            }
+            let schema = schema_builder.finish();
             let router = setup_router(schema);
             serve(router, args.port).await;
         }
@@ -0,0 +1,220 @@
+//! Shipping manifests for filled canes.
+//!
+//! A manifest describes the contents of a dewar as a tree of containers, following the naming of
+//! the ISPyB `Shipping`, `Dewar`, `Container` and `BLSample` tables. Canes are recorded as
+//! containers which hold the puck containers, each of which holds the mounted samples:
+//!
+//! | Element     | Field                   | Contents                                                  |
+//! |-------------|-------------------------|-----------------------------------------------------------|
+//! | `Shipping`  | `proposal`              | The proposal the samples belong to, such as `mx1234`      |
+//! | `Shipping`  | `visit`                 | The visit the samples are shipped for, such as `mx1234-1` |
+//! | `Dewar`     | `barCode`               | The barcode of the dewar                                  |
+//! | `Container` | `code`                  | The barcode of the cane or puck                           |
+//! | `Container` | `containerType`         | `Cane` for canes, or the name of the puck type            |
+//! | `Container` | `capacity`              | The number of slots in the container                      |
+//! | `Container` | `sampleChangerLocation` | The slot of the cane holding the puck                     |
+//! | `BLSample`  | `name`                  | The plate and well the crystal was harvested from         |
+//! | `BLSample`  | `code`                  | The barcode of the pin                                    |
+//! | `BLSample`  | `location`              | The slot of the puck holding the pin                      |
+//!
+//! The manifest may also be flattened into a comma separated values file with one row per pin.
+
+use async_graphql::SimpleObject;
+use serde::Serialize;
+
+/// A shipment of a single dewar
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename = "Shipping", rename_all = "camelCase")]
+pub struct Shipping {
+    pub proposal: String,
+    pub visit: String,
+    #[serde(rename = "Dewar")]
+    pub dewar: Dewar,
+}
+
+/// A dewar holding canes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dewar {
+    pub bar_code: String,
+    #[serde(rename = "Container")]
+    pub containers: Vec<Container>,
+}
+
+/// A cane holding pucks, or a puck holding pins
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Container {
+    pub code: String,
+    pub container_type: String,
+    pub capacity: i16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_changer_location: Option<i16>,
+    #[serde(rename = "Container", skip_serializing_if = "Vec::is_empty")]
+    pub containers: Vec<Container>,
+    #[serde(rename = "BLSample", skip_serializing_if = "Vec::is_empty")]
+    pub samples: Vec<Sample>,
+}
+
+/// A crystal mounted on a pin
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sample {
+    pub name: String,
+    pub code: String,
+    pub location: i16,
+}
+
+/// A single pin in a shipment
+#[derive(Debug, Clone, PartialEq, Serialize, SimpleObject)]
+pub struct ManifestRow {
+    #[serde(rename = "Dewar Barcode")]
+    pub dewar_barcode: String,
+    #[serde(rename = "Cane Barcode")]
+    pub cane_barcode: String,
+    #[serde(rename = "Puck Barcode")]
+    pub puck_barcode: String,
+    /// The slot of the cane holding the puck
+    #[serde(rename = "Puck Position")]
+    pub puck_position: Option<i16>,
+    #[serde(rename = "Pin Barcode")]
+    pub pin_barcode: String,
+    /// The slot of the puck holding the pin
+    #[serde(rename = "Pin Position")]
+    pub pin_position: i16,
+    #[serde(rename = "Sample Name")]
+    pub sample_name: String,
+    #[serde(rename = "Proposal")]
+    pub proposal: String,
+    #[serde(rename = "Visit")]
+    pub visit: String,
+}
+
+impl Shipping {
+    /// Flattens the shipment into one row per pin
+    pub fn rows(&self) -> Vec<ManifestRow> {
+        let mut rows = Vec::new();
+        for cane in &self.dewar.containers {
+            for puck in &cane.containers {
+                for sample in &puck.samples {
+                    rows.push(ManifestRow {
+                        dewar_barcode: self.dewar.bar_code.clone(),
+                        cane_barcode: cane.code.clone(),
+                        puck_barcode: puck.code.clone(),
+                        puck_position: puck.sample_changer_location,
+                        pin_barcode: sample.code.clone(),
+                        pin_position: sample.location,
+                        sample_name: sample.name.clone(),
+                        proposal: self.proposal.clone(),
+                        visit: self.visit.clone(),
+                    });
+                }
+            }
+        }
+        rows
+    }
+
+    /// Writes the shipment as ISPyB JSON
+    pub fn to_ispyb_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Writes the shipment as ISPyB XML
+    pub fn to_ispyb_xml(&self) -> Result<String, quick_xml::DeError> {
+        let mut xml = String::new();
+        let mut serializer = quick_xml::se::Serializer::new(&mut xml);
+        serializer.indent(' ', 2);
+        self.serialize(serializer)?;
+        Ok(xml)
+    }
+}
+
+/// Writes manifest rows as a comma separated values file
+pub fn write_manifest(rows: &[ManifestRow]) -> Result<String, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    let contents = writer
+        .into_inner()
+        .map_err(|err| csv::Error::from(err.into_error()))?;
+    Ok(String::from_utf8(contents).expect("Serialized strings are valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_manifest, Container, Dewar, Sample, Shipping};
+
+    fn shipping() -> Shipping {
+        let puck = |code: &str, location, samples| Container {
+            code: code.to_string(),
+            container_type: "Unipuck".to_string(),
+            capacity: 16,
+            sample_changer_location: Some(location),
+            containers: Vec::new(),
+            samples,
+        };
+        let sample = |name: &str, code: &str, location| Sample {
+            name: name.to_string(),
+            code: code.to_string(),
+            location,
+        };
+        Shipping {
+            proposal: "mx1234".to_string(),
+            visit: "mx1234-1".to_string(),
+            dewar: Dewar {
+                bar_code: "DLS-MX-0001".to_string(),
+                containers: vec![Container {
+                    code: "CANE-0001".to_string(),
+                    container_type: "Cane".to_string(),
+                    capacity: 7,
+                    sample_changer_location: None,
+                    containers: vec![
+                        puck(
+                            "PUCK-0001",
+                            1,
+                            vec![
+                                sample("0193ab6e-7f3c-7c8e-a3a6-2f1e4d0b9c11-1-1", "PIN-0001", 1),
+                                sample("0193ab6e-7f3c-7c8e-a3a6-2f1e4d0b9c11-1-2", "PIN-0002", 2),
+                            ],
+                        ),
+                        puck(
+                            "PUCK-0002",
+                            3,
+                            vec![sample(
+                                "0193ab6e-7f3c-7c8e-a3a6-2f1e4d0b9c11-96-1",
+                                "PIN-0003",
+                                16,
+                            )],
+                        ),
+                    ],
+                    samples: Vec::new(),
+                }],
+            },
+        }
+    }
+
+    #[test]
+    fn manifest_matches_csv_fixture() {
+        assert_eq!(
+            include_str!("../fixtures/manifest.csv"),
+            write_manifest(&shipping().rows()).unwrap()
+        );
+    }
+
+    #[test]
+    fn manifest_matches_ispyb_json_fixture() {
+        assert_eq!(
+            include_str!("../fixtures/manifest.json").trim_end(),
+            shipping().to_ispyb_json().unwrap()
+        );
+    }
+
+    #[test]
+    fn manifest_matches_ispyb_xml_fixture() {
+        assert_eq!(
+            include_str!("../fixtures/manifest.xml").trim_end(),
+            shipping().to_ispyb_xml().unwrap()
+        );
+    }
+}
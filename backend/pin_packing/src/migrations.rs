@@ -1,19 +1,36 @@
 use crate::tables::{
-    cane_library, cane_mount, crystal, pin_library,
-    pin_mount::{self, unique_puck_mount_location},
+    cane_library, cane_mount,
+    cane_type::{self, DEFAULT_CANE_SLOTS, DEFAULT_CANE_TYPE},
+    crystal::{self, unique_well_crystal_number},
+    pin_library,
+    pin_mount::{self, unique_puck_mount_location, UNIQUE_ACTIVE_CRYSTAL_MOUNT},
     puck_library,
     puck_mount::{self, unique_cane_mount_location},
+    puck_type::{self, DEFAULT_PUCK_SLOTS, DEFAULT_PUCK_TYPE},
+    status_change,
 };
 use axum::async_trait;
-use sea_orm::{DbErr, DeriveMigrationName, Schema};
-use sea_orm_migration::{MigrationTrait, MigratorTrait, SchemaManager};
+use sea_orm::{
+    sea_query::{ColumnDef, ForeignKey, OnConflict, Query, Table},
+    ConnectionTrait, DbErr, DeriveMigrationName, EntityName, IdenStatic, Schema,
+};
+use sea_orm_migration::{MigrationName, MigrationTrait, MigratorTrait, SchemaManager};
 
 pub struct Migrator;
 
 #[async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(Initial)]
+        vec![
+            Box::new(Initial),
+            Box::new(PinUnmount),
+            Box::new(StatusHistory),
+            Box::new(CrystalSize),
+            Box::new(LibraryTypes),
+            Box::new(CrystalHarvest),
+            Box::new(CrystalReferences),
+            Box::new(ActiveCrystalMount),
+        ]
     }
 }
 
@@ -71,3 +88,363 @@ impl MigrationTrait for Initial {
         Ok(())
     }
 }
+
+struct PinUnmount;
+
+impl MigrationName for PinUnmount {
+    fn name(&self) -> &str {
+        "pin_unmount"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for PinUnmount {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(pin_mount::Entity)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(pin_mount::Column::UnmountedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(pin_mount::Column::UnmountReason)
+                            .string()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Puck locations are only occupied by pins which have not been unmounted
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                DROP INDEX IF EXISTS "unique-puck-mount-location";
+                CREATE UNIQUE INDEX IF NOT EXISTS "unique-active-puck-mount-location"
+                    ON pin_mount (puck_mount_id, puck_location)
+                    WHERE unmounted_at IS NULL;
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+struct StatusHistory;
+
+impl MigrationName for StatusHistory {
+    fn name(&self) -> &str {
+        "status_history"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for StatusHistory {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+        let schema = Schema::new(backend);
+
+        manager
+            .create_table(
+                schema
+                    .create_table_from_entity(status_change::Entity)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+struct CrystalSize;
+
+impl MigrationName for CrystalSize {
+    fn name(&self) -> &str {
+        "crystal_size"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for CrystalSize {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(crystal::Entity)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(crystal::Column::Size).small_integer().null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+struct LibraryTypes;
+
+impl MigrationName for LibraryTypes {
+    fn name(&self) -> &str {
+        "library_types"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for LibraryTypes {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+        let schema = Schema::new(backend);
+
+        manager
+            .create_table(
+                schema
+                    .create_table_from_entity(puck_type::Entity)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(puck_type::Entity)
+                    .columns([puck_type::Column::Name, puck_type::Column::Slots])
+                    .values_panic([DEFAULT_PUCK_TYPE.into(), DEFAULT_PUCK_SLOTS.into()])
+                    .on_conflict(OnConflict::new().do_nothing().to_owned())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(puck_library::Entity)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(puck_library::Column::PuckType)
+                            .string()
+                            .not_null()
+                            .default(DEFAULT_PUCK_TYPE),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .name("fk-puck_library-puck_type")
+                    .from(puck_library::Entity, puck_library::Column::PuckType)
+                    .to(puck_type::Entity, puck_type::Column::Name)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                schema
+                    .create_table_from_entity(cane_type::Entity)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(cane_type::Entity)
+                    .columns([cane_type::Column::Name, cane_type::Column::Slots])
+                    .values_panic([DEFAULT_CANE_TYPE.into(), DEFAULT_CANE_SLOTS.into()])
+                    .on_conflict(OnConflict::new().do_nothing().to_owned())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(cane_library::Entity)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(cane_library::Column::CaneType)
+                            .string()
+                            .not_null()
+                            .default(DEFAULT_CANE_TYPE),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .name("fk-cane_library-cane_type")
+                    .from(cane_library::Entity, cane_library::Column::CaneType)
+                    .to(cane_type::Entity, cane_type::Column::Name)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+struct CrystalHarvest;
+
+impl MigrationName for CrystalHarvest {
+    fn name(&self) -> &str {
+        "crystal_harvest"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for CrystalHarvest {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !manager
+            .has_column(
+                crystal::Entity.table_name(),
+                crystal::Column::Number.as_str(),
+            )
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(crystal::Entity)
+                        .add_column(
+                            ColumnDef::new(crystal::Column::Number)
+                                .small_integer()
+                                .null(),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+            // Crystals already harvested from a well are numbered in the order they were harvested
+            manager
+                .get_connection()
+                .execute_unprepared(
+                    r#"
+                    UPDATE crystal SET number = numbered.number
+                    FROM (
+                        SELECT id, ROW_NUMBER() OVER (
+                            PARTITION BY plate, well ORDER BY timestamp, id
+                        ) AS number
+                        FROM crystal
+                    ) AS numbered
+                    WHERE crystal.id = numbered.id;
+                    "#,
+                )
+                .await?;
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(crystal::Entity)
+                        .modify_column(
+                            ColumnDef::new(crystal::Column::Number)
+                                .small_integer()
+                                .not_null(),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .create_index(unique_well_crystal_number().if_not_exists().to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(crystal::Entity)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(crystal::Column::Cryoprotectant)
+                            .string()
+                            .null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(crystal::Column::TimeOutOfDrop)
+                            .integer()
+                            .null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(crystal::Column::LoopOrientation)
+                            .string()
+                            .null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(crystal::Column::PhotoKey).string().null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(crystal::Column::Notes).string().null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+struct CrystalReferences;
+
+impl MigrationName for CrystalReferences {
+    fn name(&self) -> &str {
+        "crystal_references"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for CrystalReferences {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(crystal::Entity)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(crystal::Column::PredictedCrystalId)
+                            .uuid()
+                            .null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(crystal::Column::SoakedCompoundPlate)
+                            .uuid()
+                            .null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(crystal::Column::SoakedCompoundWell)
+                            .small_integer()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+struct ActiveCrystalMount;
+
+impl MigrationName for ActiveCrystalMount {
+    fn name(&self) -> &str {
+        "active_crystal_mount"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for ActiveCrystalMount {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Crystals are only held by pins which have not been unmounted
+        manager
+            .get_connection()
+            .execute_unprepared(&format!(
+                r#"
+                CREATE UNIQUE INDEX IF NOT EXISTS "{UNIQUE_ACTIVE_CRYSTAL_MOUNT}"
+                    ON pin_mount (crystal_id)
+                    WHERE unmounted_at IS NULL;
+                "#
+            ))
+            .await?;
+
+        Ok(())
+    }
+}
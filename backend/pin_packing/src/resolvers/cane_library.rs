@@ -1,18 +1,47 @@
-use crate::tables::{
-    cane_library::{self, CaneStatus},
-    cane_mount,
+use super::library_import::{
+    read_import_file, register_rows, type_names, ImportMode, ImportRecord, ImportReport,
+    LibraryTypedInput, PendingRow,
+};
+use crate::{
+    barcode::{check_unregistered_elsewhere, BarcodeFormats},
+    loaders::{CaneMountsByBarcodeLoader, StatusHistoryLoader},
+    tables::{
+        cane_library::{self, CaneStatus},
+        cane_mount,
+        cane_type::{self, DEFAULT_CANE_TYPE},
+        status_change::{self, transition, LibraryItem},
+    },
+};
+use async_graphql::{
+    dataloader::DataLoader, ComplexObject, Context, ErrorExtensions, Object, Upload,
 };
-use async_graphql::{ComplexObject, Context, Object};
 use opa_client::subject_authorization;
-use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel, ModelTrait};
+use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel, TransactionTrait};
+use std::collections::HashSet;
 use the_paginator::graphql::{CursorInput, ModelConnection};
 
 #[ComplexObject]
 impl cane_library::Model {
     async fn mounts(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<cane_mount::Model>> {
         subject_authorization!("xchemlab.pin_packing.read_cane_mount", ctx).await?;
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self.find_related(cane_mount::Entity).all(database).await?)
+        Ok(ctx
+            .data::<DataLoader<CaneMountsByBarcodeLoader>>()?
+            .load_one(self.barcode.clone())
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// The changes made to the status of the cane, in the order they were made
+    async fn status_history(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Vec<status_change::Model>> {
+        subject_authorization!("xchemlab.pin_packing.read_cane_library", ctx).await?;
+        Ok(ctx
+            .data::<DataLoader<StatusHistoryLoader>>()?
+            .load_one((LibraryItem::Cane, self.barcode.clone()))
+            .await?
+            .unwrap_or_default())
     }
 }
 
@@ -34,6 +63,31 @@ impl CaneLibraryQuery {
             .await?
             .try_into_connection()?)
     }
+
+    async fn cane_types(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<cane_type::Model>> {
+        subject_authorization!("xchemlab.pin_packing.read_cane_library", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        Ok(cane_type::Entity::find().all(database).await?)
+    }
+}
+
+/// Prepares a cane for registration with the cane type it names, if registered
+fn pending_cane(
+    row: i32,
+    cane: LibraryTypedInput,
+    cane_types: &HashSet<String>,
+) -> PendingRow<cane_library::ActiveModel> {
+    cane.into_pending(
+        row,
+        LibraryItem::Cane,
+        cane_types,
+        DEFAULT_CANE_TYPE,
+        |barcode, cane_type| cane_library::ActiveModel {
+            barcode: ActiveValue::Set(barcode),
+            status: ActiveValue::Set(CaneStatus::Ready),
+            cane_type: ActiveValue::Set(cane_type),
+        },
+    )
 }
 
 #[derive(Debug, Clone, Default)]
@@ -41,36 +95,146 @@ pub struct CaneLibraryMutation;
 
 #[Object]
 impl CaneLibraryMutation {
+    async fn register_cane_type(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        #[graphql(desc = "The number of puck slots")] slots: i16,
+    ) -> async_graphql::Result<cane_type::Model> {
+        subject_authorization!("xchemlab.pin_packing.write_cane_library", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        if slots < 1 {
+            Err("Cane types must have at least one slot")?;
+        }
+        let cane_type = cane_type::ActiveModel {
+            name: ActiveValue::Set(name),
+            slots: ActiveValue::Set(slots),
+        };
+        Ok(cane_type::Entity::insert(cane_type)
+            .exec_with_returning(database)
+            .await?)
+    }
+
     async fn register_library_cane(
         &self,
         ctx: &Context<'_>,
         barcode: String,
+        #[graphql(desc = "The name of the cane type, defaults to the original cane type")]
+        cane_type: Option<String>,
     ) -> async_graphql::Result<cane_library::Model> {
         subject_authorization!("xchemlab.pin_packing.write_cane_library", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
+        ctx.data::<BarcodeFormats>()?
+            .check(LibraryItem::Cane, &barcode)
+            .map_err(|error| error.extend())?;
+        check_unregistered_elsewhere(database, LibraryItem::Cane, &barcode).await?;
+        let cane_type = cane_type.unwrap_or(DEFAULT_CANE_TYPE.to_string());
+        cane_type::Entity::find_by_id(&cane_type)
+            .one(database)
+            .await?
+            .ok_or(format!("Could not find cane type '{cane_type}'"))?;
         let cane = cane_library::ActiveModel {
             barcode: ActiveValue::Set(barcode),
             status: ActiveValue::Set(CaneStatus::Ready),
+            cane_type: ActiveValue::Set(cane_type),
         };
         Ok(cane_library::Entity::insert(cane)
             .exec_with_returning(database)
             .await?)
     }
 
+    /// Registers many canes at once, reporting the rows which could not be registered
+    async fn register_library_canes(
+        &self,
+        ctx: &Context<'_>,
+        canes: Vec<LibraryTypedInput>,
+        #[graphql(default)] mode: ImportMode,
+        #[graphql(desc = "Reports the outcome without registering any canes", default)]
+        dry_run: bool,
+    ) -> async_graphql::Result<ImportReport> {
+        subject_authorization!("xchemlab.pin_packing.write_cane_library", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        let cane_types = type_names(database, cane_type::Column::Name).await?;
+        let rows = canes
+            .into_iter()
+            .zip(1..)
+            .map(|(cane, row)| pending_cane(row, cane, &cane_types))
+            .collect();
+        register_rows(
+            database,
+            ctx.data::<BarcodeFormats>()?,
+            LibraryItem::Cane,
+            rows,
+            mode,
+            dry_run,
+        )
+        .await
+    }
+
+    /// Registers the canes listed in a CSV or XLSX file with `barcode` and optional `cane_type`
+    /// columns
+    async fn import_library_canes(
+        &self,
+        ctx: &Context<'_>,
+        file: Upload,
+        #[graphql(default)] mode: ImportMode,
+        #[graphql(desc = "Reports the outcome without registering any canes", default)]
+        dry_run: bool,
+    ) -> async_graphql::Result<ImportReport> {
+        subject_authorization!("xchemlab.pin_packing.write_cane_library", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        let cane_types = type_names(database, cane_type::Column::Name).await?;
+        let rows = read_import_file(ctx, file)?
+            .into_iter()
+            .map(|(row, mut record): (_, ImportRecord)| {
+                let cane = LibraryTypedInput {
+                    barcode: record.remove("barcode").unwrap_or_default(),
+                    type_name: record.remove("cane_type"),
+                };
+                pending_cane(row, cane, &cane_types)
+            })
+            .collect();
+        register_rows(
+            database,
+            ctx.data::<BarcodeFormats>()?,
+            LibraryItem::Cane,
+            rows,
+            mode,
+            dry_run,
+        )
+        .await
+    }
+
     async fn update_library_cane_status(
         &self,
         ctx: &Context<'_>,
         barcode: String,
         status: CaneStatus,
     ) -> async_graphql::Result<cane_library::Model> {
-        subject_authorization!("xchemlab.pin_packing.write_cane_library", ctx).await?;
+        let operator_id =
+            subject_authorization!("xchemlab.pin_packing.write_cane_library", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
-        let mut cane = cane_library::Entity::find_by_id(&barcode)
+        if status == CaneStatus::Filling {
+            Err("Canes start filling when they are mounted")?;
+        }
+        let cane = cane_library::Entity::find_by_id(&barcode)
             .one(database)
             .await?
-            .ok_or(format!("Could not find cane with barcode '{barcode}'"))?
-            .into_active_model();
+            .ok_or(format!("Could not find cane with barcode '{barcode}'"))?;
+        let status_change = transition(barcode, cane.status, status, operator_id)?;
+        let mut cane = cane.into_active_model();
         cane.status = ActiveValue::Set(status);
-        Ok(cane_library::Entity::update(cane).exec(database).await?)
+
+        let cane = database
+            .transaction(|transaction| {
+                Box::pin(async {
+                    status_change::Entity::insert(status_change)
+                        .exec(transaction)
+                        .await?;
+                    cane_library::Entity::update(cane).exec(transaction).await
+                })
+            })
+            .await?;
+        Ok(cane)
     }
 }
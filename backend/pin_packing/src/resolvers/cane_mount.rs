@@ -1,22 +1,81 @@
-use crate::tables::{
-    cane_library::{self, CaneStatus},
-    cane_mount, puck_mount,
+use crate::{
+    barcode::check_unregistered_elsewhere,
+    loaders::{CaneMountTypeLoader, CanePucksLoader},
+    resolvers::next_free_slot,
+    tables::{
+        cane_library::{self, CaneStatus},
+        cane_mount, puck_mount,
+        status_change::{self, transition, LibraryItem},
+    },
 };
-use async_graphql::{ComplexObject, Context, Object};
+use async_graphql::{dataloader::DataLoader, ComplexObject, Context, Object, SimpleObject};
 use chrono::Utc;
 use opa_client::subject_authorization;
-use sea_orm::{
-    ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel, ModelTrait, TransactionTrait,
-};
+use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel, TransactionTrait};
 use the_paginator::graphql::{CursorInput, ModelConnection};
 use uuid::Uuid;
 
+/// A slot of a cane, along with the puck it holds
+#[derive(Debug, Clone, SimpleObject)]
+pub struct CaneSlot {
+    location: i16,
+    puck: Option<puck_mount::Model>,
+}
+
 #[ComplexObject]
 impl cane_mount::Model {
     async fn pucks(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<puck_mount::Model>> {
         subject_authorization!("xchemlab.pin_packing.read_puck_mount", ctx).await?;
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self.find_related(puck_mount::Entity).all(database).await?)
+        Ok(ctx
+            .data::<DataLoader<CanePucksLoader>>()?
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Every slot of the cane, along with the puck it holds
+    async fn layout(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<CaneSlot>> {
+        subject_authorization!("xchemlab.pin_packing.read_puck_mount", ctx).await?;
+        let pucks = ctx
+            .data::<DataLoader<CanePucksLoader>>()?
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default();
+        let slots = self.slots(ctx).await?;
+        Ok((1..=slots)
+            .map(|location| CaneSlot {
+                location,
+                puck: pucks
+                    .iter()
+                    .find(|puck| puck.cane_location == Some(location))
+                    .cloned(),
+            })
+            .collect())
+    }
+
+    /// The lowest numbered slot of the cane which does not hold a puck
+    async fn next_free_slot(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<i16>> {
+        subject_authorization!("xchemlab.pin_packing.read_puck_mount", ctx).await?;
+        let pucks = ctx
+            .data::<DataLoader<CanePucksLoader>>()?
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default();
+        Ok(next_free_slot(
+            pucks.iter().filter_map(|puck| puck.cane_location),
+            self.slots(ctx).await?,
+        ))
+    }
+
+    /// The number of puck slots in the cane
+    async fn slots(&self, ctx: &Context<'_>) -> async_graphql::Result<i16> {
+        subject_authorization!("xchemlab.pin_packing.read_cane_library", ctx).await?;
+        Ok(ctx
+            .data::<DataLoader<CaneMountTypeLoader>>()?
+            .load_one(self.id)
+            .await?
+            .ok_or("Could not find the type of mounted cane")?
+            .slots)
     }
 }
 
@@ -63,15 +122,18 @@ impl CaneMountMutation {
         let operator_id =
             subject_authorization!("xchemlab.pin_packing.write_cane_mount", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
+        check_unregistered_elsewhere(database, LibraryItem::Cane, &barcode).await?;
 
         let library_cane = cane_library::Entity::find_by_id(&barcode)
             .one(database)
             .await?
             .ok_or(format!("Could not find cane with barcode '{barcode}'"))?;
-        match library_cane.status {
-            CaneStatus::Ready => Ok(()),
-            status => Err(format!("Mount cannot be started whilst Cane is {status}")),
-        }?;
+        let status_change = transition(
+            barcode.clone(),
+            library_cane.status,
+            CaneStatus::Filling,
+            operator_id.clone(),
+        )?;
 
         let cane = cane_mount::ActiveModel {
             id: ActiveValue::Set(Uuid::now_v7()),
@@ -85,6 +147,9 @@ impl CaneMountMutation {
         let cane = database
             .transaction(|transaction| {
                 Box::pin(async {
+                    status_change::Entity::insert(status_change)
+                        .exec(transaction)
+                        .await?;
                     cane_library::Entity::update(library_cane)
                         .exec(transaction)
                         .await?;
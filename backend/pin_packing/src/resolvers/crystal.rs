@@ -1,24 +1,83 @@
 use crate::{
-    resolvers::Well,
+    loaders::CrystalPinMountLoader,
+    resolvers::{
+        subgraph_extensions::{PredictedCrystal, SoakedCompound},
+        violates_constraint, Well,
+    },
     tables::{
-        crystal::{self, CompoundState, CrystalState},
+        crystal::{self, photo_key, CompoundState, CrystalState, UNIQUE_WELL_CRYSTAL_NUMBER},
         pin_mount,
     },
 };
-use async_graphql::{ComplexObject, Context, Object};
+use async_graphql::{dataloader::DataLoader, ComplexObject, Context, Object, Upload};
+use aws_sdk_s3::presigning::PresigningConfig;
 use chrono::Utc;
 use opa_client::subject_authorization;
-use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait, ModelTrait};
+use s3_config::S3Bucket;
+use sea_orm::{
+    ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect,
+    TransactionTrait,
+};
+use std::{io::Read, time::Duration};
+use url::Url;
 use uuid::Uuid;
 
+/// The number of times a crystal is numbered before giving up, should other crystals harvested
+/// from the same well concurrently take each number
+const CRYSTAL_NUMBER_ATTEMPTS: usize = 5;
+
+/// Finds the S3 client and bucket which crystal photos are stored in, if one is configured
+fn photo_storage<'a>(
+    ctx: &Context<'a>,
+) -> async_graphql::Result<(&'a aws_sdk_s3::Client, &'a S3Bucket)> {
+    Ok(ctx
+        .data_opt::<aws_sdk_s3::Client>()
+        .zip(ctx.data_opt::<S3Bucket>())
+        .ok_or("Crystal photos are unavailable as no S3 bucket is configured")?)
+}
+
 #[ComplexObject]
 impl crystal::Model {
     async fn pin_mount(
         &self,
         ctx: &Context<'_>,
     ) -> async_graphql::Result<Option<pin_mount::Model>> {
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self.find_related(pin_mount::Entity).one(database).await?)
+        Ok(ctx
+            .data::<DataLoader<CrystalPinMountLoader>>()?
+            .load_one(self.id)
+            .await?)
+    }
+
+    /// The crystal predicted by the targeting service which was harvested
+    async fn predicted_crystal(&self) -> Option<PredictedCrystal> {
+        self.predicted_crystal_id.map(|id| PredictedCrystal { id })
+    }
+
+    /// The soak of the compound the crystal was treated with
+    async fn soaked_compound(&self) -> Option<SoakedCompound> {
+        Some(SoakedCompound {
+            compound_plate_id: self.soaked_compound_plate?,
+            compound_well_number: self.soaked_compound_well?,
+            crystal_plate_id: self.plate,
+            crystal_well_number: self.well,
+        })
+    }
+
+    /// A URL from which the photo of the harvested crystal can be retrieved, if one was taken
+    async fn photo_url(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Url>> {
+        let Some(key) = self.photo_key.clone() else {
+            return Ok(None);
+        };
+        let (s3_client, bucket) = photo_storage(ctx)?;
+        let object_uri = s3_client
+            .get_object()
+            .bucket(bucket.clone())
+            .key(key)
+            .presigned(PresigningConfig::expires_in(Duration::from_secs(10 * 60))?)
+            .await?
+            .uri()
+            .clone();
+        Ok(Some(Url::parse(&object_uri.to_string())?))
     }
 }
 
@@ -43,26 +102,130 @@ pub struct CrystalMutation;
 
 #[Object]
 impl CrystalMutation {
+    /// Records a crystal harvested from a well, numbered after those already harvested from it
+    #[allow(clippy::too_many_arguments)]
     async fn create_crystal(
         &self,
         ctx: &Context<'_>,
         well: Well,
         crystal_state: CrystalState,
         compound_state: CompoundState,
+        #[graphql(desc = "The largest dimension of the crystal in micrometers")] size: Option<i16>,
+        #[graphql(desc = "The cryoprotectant the crystal was treated with")] cryoprotectant: Option<
+            String,
+        >,
+        #[graphql(
+            desc = "The number of seconds the crystal spent out of the drop before being frozen"
+        )]
+        time_out_of_drop: Option<i32>,
+        #[graphql(desc = "The orientation of the crystal in the loop")] loop_orientation: Option<
+            String,
+        >,
+        #[graphql(desc = "A photo of the harvested crystal")] photo: Option<Upload>,
+        #[graphql(desc = "Free text remarks recorded whilst harvesting")] notes: Option<String>,
+        #[graphql(desc = "The crystal predicted by the targeting service which was harvested")]
+        predicted_crystal_id: Option<Uuid>,
+        #[graphql(desc = "The well holding the compound the crystal was soaked in")]
+        soaked_compound: Option<Well>,
     ) -> async_graphql::Result<crystal::Model> {
         let operator_id = subject_authorization!("xchemlab.pin_packing.write_crystal", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
-        let crystal = crystal::ActiveModel {
-            id: ActiveValue::Set(Uuid::now_v7()),
-            plate: ActiveValue::Set(well.plate),
-            well: ActiveValue::Set(well.well),
-            crystal_state: ActiveValue::Set(crystal_state),
-            compound_state: ActiveValue::Set(compound_state),
-            timestamp: ActiveValue::Set(Utc::now()),
-            operator_id: ActiveValue::Set(operator_id),
+        if time_out_of_drop.is_some_and(|time_out_of_drop| time_out_of_drop < 0) {
+            Err("Time out of drop cannot be negative")?;
+        }
+        let id = Uuid::now_v7();
+
+        let photo = match photo {
+            Some(photo) => {
+                let (s3_client, bucket) = photo_storage(ctx)?;
+                let upload = photo.value(ctx)?;
+                let content_type = upload
+                    .content_type
+                    .clone()
+                    .filter(|content_type| content_type.starts_with("image/"))
+                    .ok_or(format!("Photo '{}' is not an image", upload.filename))?;
+                let mut contents = Vec::new();
+                upload.into_read().read_to_end(&mut contents)?;
+                Some((s3_client, bucket, photo_key(id), content_type, contents))
+            }
+            None => None,
         };
-        Ok(crystal::Entity::insert(crystal)
-            .exec_with_returning(database)
-            .await?)
+        let photo_key = photo.as_ref().map(|(_, _, key, _, _)| key.clone());
+
+        let mut attempts = 1;
+        let (transaction, crystal) = loop {
+            let transaction = database.begin().await?;
+            let harvested = crystal::Entity::find()
+                .select_only()
+                .column_as(crystal::Column::Number.max(), "number")
+                .filter(crystal::Column::Plate.eq(well.plate))
+                .filter(crystal::Column::Well.eq(well.well))
+                .into_tuple::<Option<i16>>()
+                .one(&transaction)
+                .await?
+                .flatten()
+                .unwrap_or_default();
+            let crystal = crystal::ActiveModel {
+                id: ActiveValue::Set(id),
+                plate: ActiveValue::Set(well.plate),
+                well: ActiveValue::Set(well.well),
+                crystal_state: ActiveValue::Set(crystal_state),
+                compound_state: ActiveValue::Set(compound_state),
+                size: ActiveValue::Set(size),
+                number: ActiveValue::Set(harvested + 1),
+                cryoprotectant: ActiveValue::Set(cryoprotectant.clone()),
+                time_out_of_drop: ActiveValue::Set(time_out_of_drop),
+                loop_orientation: ActiveValue::Set(loop_orientation.clone()),
+                photo_key: ActiveValue::Set(photo_key.clone()),
+                notes: ActiveValue::Set(notes.clone()),
+                predicted_crystal_id: ActiveValue::Set(predicted_crystal_id),
+                soaked_compound_plate: ActiveValue::Set(
+                    soaked_compound.as_ref().map(|compound| compound.plate),
+                ),
+                soaked_compound_well: ActiveValue::Set(
+                    soaked_compound.as_ref().map(|compound| compound.well),
+                ),
+                timestamp: ActiveValue::Set(Utc::now()),
+                operator_id: ActiveValue::Set(operator_id.clone()),
+            };
+            match crystal::Entity::insert(crystal)
+                .exec_with_returning(&transaction)
+                .await
+            {
+                Ok(crystal) => break (transaction, crystal),
+                // Another crystal harvested from the well concurrently took the number
+                Err(error)
+                    if violates_constraint(&error, UNIQUE_WELL_CRYSTAL_NUMBER)
+                        && attempts < CRYSTAL_NUMBER_ATTEMPTS =>
+                {
+                    attempts += 1;
+                }
+                Err(error) => Err(error)?,
+            }
+        };
+
+        // The photo is stored only once the crystal is, and removed if the crystal is not saved
+        if let Some((s3_client, bucket, key, content_type, contents)) = photo {
+            s3_client
+                .put_object()
+                .bucket(bucket.clone())
+                .key(key.clone())
+                .content_type(content_type)
+                .body(contents.into())
+                .send()
+                .await?;
+            if let Err(error) = transaction.commit().await {
+                s3_client
+                    .delete_object()
+                    .bucket(bucket.clone())
+                    .key(key)
+                    .send()
+                    .await?;
+                Err(error)?;
+            }
+        } else {
+            transaction.commit().await?;
+        }
+        Ok(crystal)
     }
 }
@@ -0,0 +1,443 @@
+use crate::{
+    barcode::{find_registered, BarcodeError, BarcodeFormats},
+    tables::status_change::LibraryItem,
+};
+use async_graphql::{Context, Enum, InputObject, SimpleObject, Upload};
+use calamine::{Reader, Xlsx};
+use sea_orm::{
+    sea_query::OnConflict, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection,
+    DbErr, EntityTrait, Iterable, PrimaryKeyToColumn, QuerySelect, QueryTrait, TransactionTrait,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Cursor, Read},
+};
+
+/// How a registration proceeds when some rows cannot be registered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum)]
+pub enum ImportMode {
+    /// Registers nothing unless every row can be registered
+    #[default]
+    Atomic,
+    /// Registers every row which can be registered, skipping the others
+    SkipInvalid,
+}
+
+/// The outcome of registering a single row
+#[derive(Debug, Clone, SimpleObject)]
+pub struct ImportRow {
+    /// The position of the row, as numbered in the file if imported from one
+    pub row: i32,
+    pub barcode: String,
+    /// The reason the row could not be registered, if it could not be
+    pub error: Option<String>,
+}
+
+/// The outcome of registering many library items at once
+#[derive(Debug, Clone, SimpleObject)]
+pub struct ImportReport {
+    pub rows: Vec<ImportRow>,
+    /// The number of items which were registered
+    pub registered: i32,
+    /// Whether any changes were saved, which is never the case for a dry run
+    pub committed: bool,
+}
+
+/// A row awaiting registration, along with its model or the reason it cannot be registered
+pub struct PendingRow<M> {
+    pub row: i32,
+    pub barcode: String,
+    pub model: Result<M, String>,
+}
+
+/// A puck or cane to register, along with the name of its type
+#[derive(Debug, Clone, InputObject)]
+pub struct LibraryTypedInput {
+    pub barcode: String,
+    /// The name of the type, defaults to the original type of the item
+    pub type_name: Option<String>,
+}
+
+impl LibraryTypedInput {
+    /// Prepares the item for registration, provided its type is one of the registered types
+    pub fn into_pending<M>(
+        self,
+        row: i32,
+        item: LibraryItem,
+        types: &HashSet<String>,
+        default_type: &str,
+        model: impl FnOnce(String, String) -> M,
+    ) -> PendingRow<M> {
+        let type_name = self
+            .type_name
+            .filter(|type_name| !type_name.is_empty())
+            .unwrap_or(default_type.to_string());
+        let model = if types.contains(&type_name) {
+            Ok(model(self.barcode.clone(), type_name))
+        } else {
+            Err(format!("{item:?} type '{type_name}' is not registered"))
+        };
+        PendingRow {
+            row,
+            barcode: self.barcode,
+            model,
+        }
+    }
+}
+
+/// Finds the names of all registered types, given the name column of the type table
+pub async fn type_names<C>(database: &DatabaseConnection, name: C) -> Result<HashSet<String>, DbErr>
+where
+    C: ColumnTrait,
+    C::EntityName: EntityTrait,
+{
+    Ok(C::EntityName::find()
+        .select_only()
+        .column(name)
+        .into_tuple::<String>()
+        .all(database)
+        .await?
+        .into_iter()
+        .collect())
+}
+
+/// The number of rows inserted by each statement, keeping the bound parameters of even the
+/// widest library table well below the limit of the database
+const INSERT_CHUNK_SIZE: usize = 1000;
+
+/// The rows of a registration, their outcomes and the models which are to be registered
+struct RegistrationPlan<M> {
+    rows: Vec<ImportRow>,
+    models: Vec<M>,
+    committed: bool,
+}
+
+/// Decides the outcome of each row given the items already registered, keeping the models only
+/// if they are to be committed
+fn plan_registration<M>(
+    formats: &BarcodeFormats,
+    item: LibraryItem,
+    registered: &HashMap<String, LibraryItem>,
+    rows: Vec<PendingRow<M>>,
+    mode: ImportMode,
+    dry_run: bool,
+) -> RegistrationPlan<M> {
+    let mut seen = HashSet::new();
+    let mut models = Vec::new();
+    let mut results = Vec::new();
+    for row in rows {
+        let model = formats
+            .check(item, &row.barcode)
+            .map_err(|error| error.to_string())
+            .and(row.model)
+            .and_then(|model| {
+                if let Some(actual) = registered
+                    .get(&row.barcode)
+                    .filter(|actual| **actual != item)
+                {
+                    Err(BarcodeError::WrongItem {
+                        barcode: row.barcode.clone(),
+                        expected: item,
+                        actual: *actual,
+                    }
+                    .to_string())
+                } else if registered.contains_key(&row.barcode) {
+                    Err(format!("Barcode '{}' is already registered", row.barcode))
+                } else if !seen.insert(row.barcode.clone()) {
+                    Err(format!("Barcode '{}' is duplicated", row.barcode))
+                } else {
+                    Ok(model)
+                }
+            });
+        let error = match model {
+            Ok(model) => {
+                models.push(model);
+                None
+            }
+            Err(error) => Some(error),
+        };
+        results.push(ImportRow {
+            row: row.row,
+            barcode: row.barcode,
+            error,
+        });
+    }
+
+    let failed = results.iter().any(|result| result.error.is_some());
+    let committed = !(dry_run || failed && mode == ImportMode::Atomic);
+    if !committed {
+        models.clear();
+    }
+    RegistrationPlan {
+        rows: results,
+        models,
+        committed,
+    }
+}
+
+/// Registers the rows which can be registered, unless this is a dry run or an atomic
+/// registration with rows which cannot be registered
+pub async fn register_rows<A: ActiveModelTrait>(
+    database: &DatabaseConnection,
+    formats: &BarcodeFormats,
+    item: LibraryItem,
+    rows: Vec<PendingRow<A>>,
+    mode: ImportMode,
+    dry_run: bool,
+) -> async_graphql::Result<ImportReport> {
+    let transaction = database.begin().await?;
+    let mut registered = HashMap::new();
+    for barcodes in rows.chunks(INSERT_CHUNK_SIZE) {
+        let barcodes = barcodes
+            .iter()
+            .map(|row| row.barcode.clone())
+            .collect::<Vec<_>>();
+        registered.extend(find_registered(&transaction, &barcodes).await?);
+    }
+
+    let plan = plan_registration(formats, item, &registered, rows, mode, dry_run);
+    let mut inserted = 0;
+    let mut models = plan.models.into_iter().peekable();
+    while models.peek().is_some() {
+        let insert = A::Entity::insert_many(models.by_ref().take(INSERT_CHUNK_SIZE));
+        // Items registered concurrently are skipped rather than failing the other rows
+        let insert = match mode {
+            ImportMode::Atomic => insert,
+            ImportMode::SkipInvalid => insert.on_conflict(
+                OnConflict::columns(
+                    <A::Entity as EntityTrait>::PrimaryKey::iter().map(|key| key.into_column()),
+                )
+                .do_nothing()
+                .to_owned(),
+            ),
+        };
+        inserted += transaction
+            .execute(insert.build(transaction.get_database_backend()))
+            .await?
+            .rows_affected();
+    }
+    transaction.commit().await?;
+
+    Ok(ImportReport {
+        rows: plan.rows,
+        registered: inserted.try_into()?,
+        committed: plan.committed,
+    })
+}
+
+/// A row of an imported file, keyed by the lower case column headers
+pub type ImportRecord = HashMap<String, String>;
+
+/// Reads the rows of an uploaded CSV or XLSX file, numbered as they appear in the file
+pub fn read_import_file(
+    ctx: &Context<'_>,
+    file: Upload,
+) -> async_graphql::Result<Vec<(i32, ImportRecord)>> {
+    let upload = file.value(ctx)?;
+    let xlsx = upload.filename.to_lowercase().ends_with(".xlsx")
+        || upload.content_type.as_deref()
+            == Some("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet");
+    let mut contents = Vec::new();
+    upload.into_read().read_to_end(&mut contents)?;
+    parse_import_file(contents, xlsx)
+}
+
+/// Parses the rows of a CSV or XLSX file, numbered as they appear in the file, skipping blank rows
+fn parse_import_file(
+    contents: Vec<u8>,
+    xlsx: bool,
+) -> async_graphql::Result<Vec<(i32, ImportRecord)>> {
+    let mut rows = if xlsx {
+        let mut workbook = Xlsx::new(Cursor::new(contents))?;
+        let sheet = workbook
+            .worksheet_range_at(0)
+            .ok_or("Spreadsheet contains no worksheets")??;
+        let first_row = sheet.start().map_or(0, |(row, _)| row) + 1;
+        sheet
+            .rows()
+            .zip(first_row..)
+            .map(|(row, line)| {
+                let cells: Vec<_> = row.iter().map(|cell| cell.to_string()).collect();
+                Ok((i32::try_from(line)?, cells))
+            })
+            .collect::<async_graphql::Result<Vec<_>>>()?
+    } else {
+        csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(contents.as_slice())
+            .records()
+            .map(|record| {
+                let record = record?;
+                let line = record.position().map_or(0, |position| position.line());
+                let cells: Vec<_> = record.iter().map(str::to_string).collect();
+                Ok((i32::try_from(line)?, cells))
+            })
+            .collect::<async_graphql::Result<Vec<_>>>()?
+    }
+    .into_iter();
+
+    let (_, headers) = rows.next().ok_or("File contains no header row")?;
+    let headers = headers
+        .into_iter()
+        .map(|header| header.trim().to_lowercase())
+        .collect::<Vec<_>>();
+    Ok(rows
+        .filter(|(_, cells)| cells.iter().any(|cell| !cell.trim().is_empty()))
+        .map(|(line, cells)| {
+            let record = headers
+                .iter()
+                .cloned()
+                .zip(cells.into_iter().map(|cell| cell.trim().to_string()))
+                .collect();
+            (line, record)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_import_file, plan_registration, ImportMode, ImportRecord, ImportRow, PendingRow,
+    };
+    use crate::{barcode::BarcodeFormats, tables::status_change::LibraryItem};
+    use std::collections::HashMap;
+
+    fn record(cells: &[(&str, &str)]) -> ImportRecord {
+        cells
+            .iter()
+            .map(|(header, cell)| (header.to_string(), cell.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn csv_rows_are_numbered_by_line() {
+        let contents = b"Barcode, Loop_Size \nPIN-0001, 50\n\n,\nPIN-0002,100,extra\nPIN-0003\n";
+        assert_eq!(
+            vec![
+                (2, record(&[("barcode", "PIN-0001"), ("loop_size", "50")])),
+                (5, record(&[("barcode", "PIN-0002"), ("loop_size", "100")])),
+                (6, record(&[("barcode", "PIN-0003")])),
+            ],
+            parse_import_file(contents.to_vec(), false).unwrap()
+        );
+    }
+
+    #[test]
+    fn xlsx_rows_are_numbered_by_sheet_row() {
+        let contents = include_bytes!("../../fixtures/library_pins.xlsx");
+        assert_eq!(
+            vec![
+                (3, record(&[("barcode", "PIN-0001"), ("loop_size", "50")])),
+                (5, record(&[("barcode", "PIN-0002"), ("loop_size", "100")])),
+            ],
+            parse_import_file(contents.to_vec(), true).unwrap()
+        );
+    }
+
+    #[test]
+    fn files_without_headers_are_rejected() {
+        assert!(parse_import_file(Vec::new(), false).is_err());
+        assert!(parse_import_file(b"not a spreadsheet".to_vec(), true).is_err());
+    }
+
+    fn pending(rows: &[(&str, Result<(), &str>)]) -> Vec<PendingRow<String>> {
+        rows.iter()
+            .zip(1..)
+            .map(|((barcode, model), row)| PendingRow {
+                row,
+                barcode: barcode.to_string(),
+                model: model
+                    .map(|_| barcode.to_string())
+                    .map_err(|error| error.to_string()),
+            })
+            .collect()
+    }
+
+    fn errors(rows: &[ImportRow]) -> Vec<Option<&str>> {
+        rows.iter().map(|row| row.error.as_deref()).collect()
+    }
+
+    #[test]
+    fn invalid_rows_are_explained() {
+        let registered = HashMap::from([
+            ("PIN-0002".to_string(), LibraryItem::Pin),
+            ("PUCK-0001".to_string(), LibraryItem::Puck),
+        ]);
+        let plan = plan_registration(
+            &BarcodeFormats::default(),
+            LibraryItem::Pin,
+            &registered,
+            pending(&[
+                ("PIN-0001", Ok(())),
+                ("PIN-0002", Ok(())),
+                ("PUCK-0001", Ok(())),
+                ("PIN-0001", Ok(())),
+                ("PIN-0003", Err("Loop size 0 is not positive")),
+                ("PIN-0004", Ok(())),
+            ]),
+            ImportMode::SkipInvalid,
+            false,
+        );
+        assert_eq!(
+            vec![
+                None,
+                Some("Barcode 'PIN-0002' is already registered"),
+                Some("Barcode 'PUCK-0001' belongs to a Puck, not a Pin"),
+                Some("Barcode 'PIN-0001' is duplicated"),
+                Some("Loop size 0 is not positive"),
+                None,
+            ],
+            errors(&plan.rows)
+        );
+        assert_eq!(vec!["PIN-0001", "PIN-0004"], plan.models);
+        assert!(plan.committed);
+    }
+
+    #[test]
+    fn atomic_registrations_commit_only_without_invalid_rows() {
+        let rows = || pending(&[("PIN-0001", Ok(())), ("PIN-0002", Err("Invalid"))]);
+        let formats = BarcodeFormats::default();
+        let registered = HashMap::new();
+
+        let plan = plan_registration(
+            &formats,
+            LibraryItem::Pin,
+            &registered,
+            rows(),
+            ImportMode::Atomic,
+            false,
+        );
+        assert!(!plan.committed);
+        assert!(plan.models.is_empty());
+        assert_eq!(vec![None, Some("Invalid")], errors(&plan.rows));
+
+        let plan = plan_registration(
+            &formats,
+            LibraryItem::Pin,
+            &registered,
+            pending(&[("PIN-0001", Ok(())), ("PIN-0002", Ok(()))]),
+            ImportMode::Atomic,
+            false,
+        );
+        assert!(plan.committed);
+        assert_eq!(vec!["PIN-0001", "PIN-0002"], plan.models);
+    }
+
+    #[test]
+    fn dry_runs_never_commit() {
+        for mode in [ImportMode::Atomic, ImportMode::SkipInvalid] {
+            let plan = plan_registration(
+                &BarcodeFormats::default(),
+                LibraryItem::Pin,
+                &HashMap::new(),
+                pending(&[("PIN-0001", Ok(()))]),
+                mode,
+                true,
+            );
+            assert!(!plan.committed);
+            assert!(plan.models.is_empty());
+            assert_eq!(vec![None], errors(&plan.rows));
+        }
+    }
+}
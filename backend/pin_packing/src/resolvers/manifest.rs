@@ -0,0 +1,365 @@
+use crate::{
+    manifest::{write_manifest, Container, Dewar, ManifestRow, Sample, Shipping},
+    tables::{
+        cane_library, cane_mount, cane_type, crystal, pin_mount, puck_library, puck_mount,
+        puck_type,
+    },
+};
+use async_graphql::{Context, Object, SimpleObject};
+use opa_client::subject_authorization;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct ShippingManifest {
+    rows: Vec<ManifestRow>,
+    /// The manifest formatted as a comma separated values file
+    csv: String,
+    /// The manifest formatted as ISPyB JSON
+    ispyb_json: String,
+    /// The manifest formatted as ISPyB XML
+    ispyb_xml: String,
+}
+
+/// The mounts held by the canes in a dewar, keyed for assembly into manifest containers
+#[derive(Debug, Default)]
+struct DewarContents {
+    /// The barcode and number of slots of each cane, by cane mount
+    canes: HashMap<Uuid, (String, i16)>,
+    /// The pucks held by each cane mount, ordered by location
+    pucks: HashMap<Option<Uuid>, Vec<puck_mount::Model>>,
+    /// The type of each puck, by barcode
+    puck_types: HashMap<String, puck_type::Model>,
+    /// The pins held by each puck mount, ordered by location
+    pins: HashMap<Uuid, Vec<pin_mount::Model>>,
+    /// The sample name of each crystal, formed from its plate, well and number
+    sample_names: HashMap<Uuid, String>,
+}
+
+impl DewarContents {
+    /// Builds a container for each cane, in the order given, holding its pucks and their pins
+    fn into_containers(mut self, cane_mount_ids: &[Uuid]) -> Result<Vec<Container>, String> {
+        let mut listed = HashSet::new();
+        cane_mount_ids
+            .iter()
+            .map(|&cane_mount_id| {
+                if !listed.insert(cane_mount_id) {
+                    return Err(format!(
+                        "Cane mount {cane_mount_id} is listed more than once"
+                    ));
+                }
+                let (barcode, slots) = self
+                    .canes
+                    .remove(&cane_mount_id)
+                    .ok_or(format!("Could not find cane mount {cane_mount_id}"))?;
+                let pucks = self
+                    .pucks
+                    .remove(&Some(cane_mount_id))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|puck| {
+                        let puck_type = self
+                            .puck_types
+                            .get(&puck.barcode)
+                            .ok_or(format!("Could not find the type of puck {}", puck.barcode))?;
+                        let samples = self
+                            .pins
+                            .remove(&puck.id)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(|pin| {
+                                Ok(Sample {
+                                    name: self
+                                        .sample_names
+                                        .get(&pin.crystal_id)
+                                        .ok_or(format!(
+                                            "Could not find crystal {}",
+                                            pin.crystal_id
+                                        ))?
+                                        .clone(),
+                                    code: pin.barcode,
+                                    location: pin.puck_location,
+                                })
+                            })
+                            .collect::<Result<Vec<_>, String>>()?;
+                        Ok(Container {
+                            code: puck.barcode,
+                            container_type: puck_type.name.clone(),
+                            capacity: puck_type.slots,
+                            sample_changer_location: puck.cane_location,
+                            containers: Vec::new(),
+                            samples,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                Ok(Container {
+                    code: barcode,
+                    container_type: "Cane".to_string(),
+                    capacity: slots,
+                    sample_changer_location: None,
+                    containers: pucks,
+                    samples: Vec::new(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ManifestQuery;
+
+#[Object]
+impl ManifestQuery {
+    /// Produces a shipping manifest for the pins mounted in pucks held by canes in a dewar
+    async fn shipping_manifest(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The canes in the dewar, in the order they should be listed")]
+        cane_mount_ids: Vec<Uuid>,
+        dewar_barcode: String,
+        #[graphql(desc = "The proposal the samples belong to, such as mx1234")] proposal: String,
+        #[graphql(desc = "The visit the samples are shipped for, such as mx1234-1")] visit: String,
+    ) -> async_graphql::Result<ShippingManifest> {
+        subject_authorization!("xchemlab.pin_packing.read_cane_mount", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+
+        let canes = cane_mount::Entity::find()
+            .filter(cane_mount::Column::Id.is_in(cane_mount_ids.clone()))
+            .all(database)
+            .await?
+            .into_iter()
+            .map(|cane| (cane.id, cane))
+            .collect::<HashMap<_, _>>();
+        let cane_slots = cane_library::Entity::find()
+            .filter(
+                cane_library::Column::Barcode
+                    .is_in(canes.values().map(|cane| cane.barcode.clone())),
+            )
+            .find_also_related(cane_type::Entity)
+            .all(database)
+            .await?
+            .into_iter()
+            .filter_map(|(cane, cane_type)| Some((cane.barcode, cane_type?.slots)))
+            .collect::<HashMap<_, _>>();
+        let canes = canes
+            .into_iter()
+            .filter_map(|(id, cane)| {
+                let slots = *cane_slots.get(&cane.barcode)?;
+                Some((id, (cane.barcode, slots)))
+            })
+            .collect();
+
+        let mut pucks = HashMap::<_, Vec<_>>::new();
+        for puck in puck_mount::Entity::find()
+            .filter(puck_mount::Column::CaneMountId.is_in(cane_mount_ids.clone()))
+            .order_by_asc(puck_mount::Column::CaneLocation)
+            .all(database)
+            .await?
+        {
+            pucks.entry(puck.cane_mount_id).or_default().push(puck);
+        }
+        let puck_types = puck_library::Entity::find()
+            .filter(
+                puck_library::Column::Barcode
+                    .is_in(pucks.values().flatten().map(|puck| puck.barcode.clone())),
+            )
+            .find_also_related(puck_type::Entity)
+            .all(database)
+            .await?
+            .into_iter()
+            .filter_map(|(puck, puck_type)| Some((puck.barcode, puck_type?)))
+            .collect::<HashMap<_, _>>();
+
+        let mut pins = HashMap::<_, Vec<_>>::new();
+        for pin in pin_mount::Entity::find()
+            .filter(
+                pin_mount::Column::PuckMountId.is_in(pucks.values().flatten().map(|puck| puck.id)),
+            )
+            .filter(pin_mount::Column::UnmountedAt.is_null())
+            .order_by_asc(pin_mount::Column::PuckLocation)
+            .all(database)
+            .await?
+        {
+            pins.entry(pin.puck_mount_id).or_default().push(pin);
+        }
+        let sample_names = crystal::Entity::find()
+            .filter(crystal::Column::Id.is_in(pins.values().flatten().map(|pin| pin.crystal_id)))
+            .all(database)
+            .await?
+            .into_iter()
+            .map(|crystal| {
+                let name = format!("{}-{}-{}", crystal.plate, crystal.well, crystal.number);
+                (crystal.id, name)
+            })
+            .collect();
+
+        let containers = DewarContents {
+            canes,
+            pucks,
+            puck_types,
+            pins,
+            sample_names,
+        }
+        .into_containers(&cane_mount_ids)?;
+
+        let shipping = Shipping {
+            proposal,
+            visit,
+            dewar: Dewar {
+                bar_code: dewar_barcode,
+                containers,
+            },
+        };
+        let rows = shipping.rows();
+        Ok(ShippingManifest {
+            csv: write_manifest(&rows)?,
+            ispyb_json: shipping.to_ispyb_json()?,
+            ispyb_xml: shipping.to_ispyb_xml()?,
+            rows,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DewarContents;
+    use crate::{
+        manifest::{Container, Sample},
+        tables::{pin_mount, puck_mount, puck_type},
+    };
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn dewar() -> (DewarContents, [Uuid; 2]) {
+        let canes = [Uuid::from_u128(1), Uuid::from_u128(2)];
+        let puck = |id, cane_mount_id, cane_location, barcode: &str| puck_mount::Model {
+            id: Uuid::from_u128(id),
+            cane_mount_id: Some(cane_mount_id),
+            cane_location: Some(cane_location),
+            barcode: barcode.to_string(),
+            timestamp: Utc::now(),
+            operator_id: "operator".to_string(),
+        };
+        let pin = |crystal, puck_mount, puck_location, barcode: &str| pin_mount::Model {
+            id: Uuid::now_v7(),
+            crystal_id: Uuid::from_u128(crystal),
+            puck_mount_id: Uuid::from_u128(puck_mount),
+            puck_location,
+            barcode: barcode.to_string(),
+            timestamp: Utc::now(),
+            operator_id: "operator".to_string(),
+            unmounted_at: None,
+            unmount_reason: None,
+        };
+        let contents = DewarContents {
+            canes: HashMap::from([
+                (canes[0], ("CANE-0001".to_string(), 7)),
+                (canes[1], ("CANE-0002".to_string(), 7)),
+            ]),
+            pucks: HashMap::from([(
+                Some(canes[0]),
+                vec![
+                    puck(10, canes[0], 1, "PUCK-0001"),
+                    puck(11, canes[0], 3, "PUCK-0002"),
+                ],
+            )]),
+            puck_types: ["PUCK-0001", "PUCK-0002"]
+                .map(|barcode| {
+                    (
+                        barcode.to_string(),
+                        puck_type::Model {
+                            name: "Unipuck".to_string(),
+                            slots: 16,
+                        },
+                    )
+                })
+                .into(),
+            pins: HashMap::from([
+                (
+                    Uuid::from_u128(10),
+                    vec![pin(20, 10, 1, "PIN-0001"), pin(21, 10, 2, "PIN-0002")],
+                ),
+                (Uuid::from_u128(11), vec![pin(22, 11, 16, "PIN-0003")]),
+            ]),
+            sample_names: [(20, "A"), (21, "B"), (22, "C")]
+                .map(|(crystal, name)| (Uuid::from_u128(crystal), name.to_string()))
+                .into(),
+        };
+        (contents, canes)
+    }
+
+    fn sample(name: &str, code: &str, location: i16) -> Sample {
+        Sample {
+            name: name.to_string(),
+            code: code.to_string(),
+            location,
+        }
+    }
+
+    #[test]
+    fn canes_hold_their_pucks_and_pins_in_the_order_given() {
+        let (contents, canes) = dewar();
+        let puck = |code: &str, location, samples| Container {
+            code: code.to_string(),
+            container_type: "Unipuck".to_string(),
+            capacity: 16,
+            sample_changer_location: Some(location),
+            containers: Vec::new(),
+            samples,
+        };
+        let cane = |code: &str, containers| Container {
+            code: code.to_string(),
+            container_type: "Cane".to_string(),
+            capacity: 7,
+            sample_changer_location: None,
+            containers,
+            samples: Vec::new(),
+        };
+
+        assert_eq!(
+            Ok(vec![
+                cane("CANE-0002", Vec::new()),
+                cane(
+                    "CANE-0001",
+                    vec![
+                        puck(
+                            "PUCK-0001",
+                            1,
+                            vec![sample("A", "PIN-0001", 1), sample("B", "PIN-0002", 2)]
+                        ),
+                        puck("PUCK-0002", 3, vec![sample("C", "PIN-0003", 16)]),
+                    ]
+                ),
+            ]),
+            contents.into_containers(&[canes[1], canes[0]])
+        );
+    }
+
+    #[test]
+    fn duplicate_canes_are_rejected() {
+        let (contents, canes) = dewar();
+        assert_eq!(
+            Err(format!("Cane mount {} is listed more than once", canes[0])),
+            contents.into_containers(&[canes[0], canes[1], canes[0]])
+        );
+    }
+
+    #[test]
+    fn missing_records_are_reported() {
+        let (contents, _) = dewar();
+        let unknown = Uuid::from_u128(3);
+        assert_eq!(
+            Err(format!("Could not find cane mount {unknown}")),
+            contents.into_containers(&[unknown])
+        );
+
+        let (mut contents, canes) = dewar();
+        contents.sample_names.remove(&Uuid::from_u128(22));
+        assert_eq!(
+            Err(format!("Could not find crystal {}", Uuid::from_u128(22))),
+            contents.into_containers(&canes)
+        );
+    }
+}
@@ -1,12 +1,17 @@
 pub mod cane_library;
 pub mod cane_mount;
 pub mod crystal;
+pub mod library_import;
+pub mod manifest;
 pub mod pin_library;
 pub mod pin_mount;
 pub mod puck_library;
 pub mod puck_mount;
+pub mod subgraph_extensions;
 
 use async_graphql::{InputObject, SimpleObject};
+use sea_orm::{DbErr, RuntimeErr};
+use std::collections::HashSet;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, SimpleObject, InputObject)]
@@ -15,3 +20,41 @@ pub struct Well {
     pub plate: Uuid,
     pub well: i16,
 }
+
+/// Finds the lowest numbered slot which is not occupied
+pub fn next_free_slot(occupied: impl IntoIterator<Item = i16>, slots: i16) -> Option<i16> {
+    let occupied = occupied.into_iter().collect::<HashSet<_>>();
+    (1..=slots).find(|slot| !occupied.contains(slot))
+}
+
+/// Whether a database error was caused by violating the named constraint or unique index
+pub fn violates_constraint(error: &DbErr, constraint: &str) -> bool {
+    match error {
+        DbErr::Exec(RuntimeErr::SqlxError(error)) | DbErr::Query(RuntimeErr::SqlxError(error)) => {
+            error
+                .as_database_error()
+                .and_then(|error| error.constraint())
+                == Some(constraint)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::next_free_slot;
+
+    #[test]
+    fn lowest_free_slot_is_found() {
+        assert_eq!(Some(1), next_free_slot([], 16));
+        assert_eq!(Some(2), next_free_slot([1, 3], 16));
+        assert_eq!(Some(4), next_free_slot([3, 1, 2], 16));
+    }
+
+    #[test]
+    fn full_or_slotless_containers_have_no_free_slot() {
+        assert_eq!(None, next_free_slot(1..=16, 16));
+        assert_eq!(None, next_free_slot([], 0));
+        assert_eq!(Some(16), next_free_slot((1..=15).chain([17]), 16));
+    }
+}
@@ -1,18 +1,47 @@
-use crate::tables::{
-    pin_library::{self, PinStatus},
-    pin_mount,
+use super::library_import::{
+    read_import_file, register_rows, ImportMode, ImportRecord, ImportReport, PendingRow,
+};
+use crate::{
+    barcode::{check_unregistered_elsewhere, BarcodeFormats},
+    loaders::{PinMountsByBarcodeLoader, StatusHistoryLoader},
+    tables::{
+        pin_library::{self, PinStatus},
+        pin_mount,
+        status_change::{self, transition, LibraryItem},
+    },
+};
+use async_graphql::{
+    dataloader::DataLoader, ComplexObject, Context, ErrorExtensions, InputObject, Object, Upload,
 };
-use async_graphql::{ComplexObject, Context, Object};
 use opa_client::subject_authorization;
-use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel, ModelTrait};
+use sea_orm::{
+    ActiveEnum, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, TransactionTrait,
+};
 use the_paginator::graphql::{CursorInput, ModelConnection};
 
 #[ComplexObject]
 impl pin_library::Model {
     async fn mounts(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<pin_mount::Model>> {
         subject_authorization!("xchemlab.pin_packing.read_pin_mount", ctx).await?;
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self.find_related(pin_mount::Entity).all(database).await?)
+        Ok(ctx
+            .data::<DataLoader<PinMountsByBarcodeLoader>>()?
+            .load_one(self.barcode.clone())
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// The changes made to the status of the pin, in the order they were made
+    async fn status_history(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Vec<status_change::Model>> {
+        subject_authorization!("xchemlab.pin_packing.read_pin_library", ctx).await?;
+        Ok(ctx
+            .data::<DataLoader<StatusHistoryLoader>>()?
+            .load_one((LibraryItem::Pin, self.barcode.clone()))
+            .await?
+            .unwrap_or_default())
     }
 }
 
@@ -36,6 +65,44 @@ impl PinLibraryQuery {
     }
 }
 
+#[derive(Debug, Clone, InputObject)]
+pub struct LibraryPinInput {
+    barcode: String,
+    /// Mounting loop size in micrometers
+    loop_size: i16,
+}
+
+impl LibraryPinInput {
+    fn into_pending(self, row: i32) -> PendingRow<pin_library::ActiveModel> {
+        let model = if self.loop_size > 0 {
+            Ok(pin_library::ActiveModel {
+                barcode: ActiveValue::Set(self.barcode.clone()),
+                loop_size: ActiveValue::Set(self.loop_size),
+                status: ActiveValue::Set(PinStatus::Ready),
+            })
+        } else {
+            Err(format!("Loop size {} is not positive", self.loop_size))
+        };
+        PendingRow {
+            row,
+            barcode: self.barcode,
+            model,
+        }
+    }
+}
+
+fn pending_pin(row: i32, mut record: ImportRecord) -> PendingRow<pin_library::ActiveModel> {
+    let barcode = record.remove("barcode").unwrap_or_default();
+    match record.get("loop_size").map(|loop_size| loop_size.parse()) {
+        Some(Ok(loop_size)) => LibraryPinInput { barcode, loop_size }.into_pending(row),
+        Some(Err(_)) | None => PendingRow {
+            row,
+            barcode,
+            model: Err("Loop size is missing or not a whole number".to_string()),
+        },
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct PinLibraryMutation;
 
@@ -49,6 +116,13 @@ impl PinLibraryMutation {
     ) -> async_graphql::Result<pin_library::Model> {
         subject_authorization!("xchemlab.pin_packing.write_pin_library", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
+        if loop_size <= 0 {
+            Err(format!("Loop size {} is not positive", loop_size))?;
+        }
+        ctx.data::<BarcodeFormats>()?
+            .check(LibraryItem::Pin, &barcode)
+            .map_err(|error| error.extend())?;
+        check_unregistered_elsewhere(database, LibraryItem::Pin, &barcode).await?;
         let pin = pin_library::ActiveModel {
             barcode: ActiveValue::Set(barcode),
             loop_size: ActiveValue::Set(loop_size),
@@ -59,20 +133,164 @@ impl PinLibraryMutation {
             .await?)
     }
 
+    /// Registers many pins at once, reporting the rows which could not be registered
+    async fn register_library_pins(
+        &self,
+        ctx: &Context<'_>,
+        pins: Vec<LibraryPinInput>,
+        #[graphql(default)] mode: ImportMode,
+        #[graphql(desc = "Reports the outcome without registering any pins", default)]
+        dry_run: bool,
+    ) -> async_graphql::Result<ImportReport> {
+        subject_authorization!("xchemlab.pin_packing.write_pin_library", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        let rows = pins
+            .into_iter()
+            .zip(1..)
+            .map(|(pin, row)| pin.into_pending(row))
+            .collect();
+        register_rows(
+            database,
+            ctx.data::<BarcodeFormats>()?,
+            LibraryItem::Pin,
+            rows,
+            mode,
+            dry_run,
+        )
+        .await
+    }
+
+    /// Registers the pins listed in a CSV or XLSX file with `barcode` and `loop_size` columns
+    async fn import_library_pins(
+        &self,
+        ctx: &Context<'_>,
+        file: Upload,
+        #[graphql(default)] mode: ImportMode,
+        #[graphql(desc = "Reports the outcome without registering any pins", default)]
+        dry_run: bool,
+    ) -> async_graphql::Result<ImportReport> {
+        subject_authorization!("xchemlab.pin_packing.write_pin_library", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        let rows = read_import_file(ctx, file)?
+            .into_iter()
+            .map(|(row, record)| pending_pin(row, record))
+            .collect();
+        register_rows(
+            database,
+            ctx.data::<BarcodeFormats>()?,
+            LibraryItem::Pin,
+            rows,
+            mode,
+            dry_run,
+        )
+        .await
+    }
+
     async fn update_library_pin_status(
         &self,
         ctx: &Context<'_>,
         barcode: String,
         status: PinStatus,
     ) -> async_graphql::Result<pin_library::Model> {
-        subject_authorization!("xchemlab.pin_packing.write_pin_library", ctx).await?;
+        let operator_id =
+            subject_authorization!("xchemlab.pin_packing.write_pin_library", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
-        let mut pin = pin_library::Entity::find_by_id(&barcode)
+        if matches!(status, PinStatus::Occupied | PinStatus::Dirty) {
+            Err("Pins are occupied by mounting and become dirty by unmounting")?;
+        }
+        let pin = pin_library::Entity::find_by_id(&barcode)
             .one(database)
             .await?
-            .ok_or(format!("Could not find pin with barcode '{barcode}'"))?
-            .into_active_model();
+            .ok_or(format!("Could not find pin with barcode '{barcode}'"))?;
+        let status_change = transition(barcode, pin.status, status, operator_id)?;
+        let mut pin = pin.into_active_model();
         pin.status = ActiveValue::Set(status);
-        Ok(pin_library::Entity::update(pin).exec(database).await?)
+
+        let pin = database
+            .transaction(|transaction| {
+                Box::pin(async {
+                    status_change::Entity::insert(status_change)
+                        .exec(transaction)
+                        .await?;
+                    pin_library::Entity::update(pin).exec(transaction).await
+                })
+            })
+            .await?;
+        Ok(pin)
+    }
+
+    /// Returns washed pins from dirty to ready, or all dirty pins if no barcodes are given
+    async fn wash_pins(
+        &self,
+        ctx: &Context<'_>,
+        barcodes: Option<Vec<String>>,
+    ) -> async_graphql::Result<Vec<pin_library::Model>> {
+        let operator_id =
+            subject_authorization!("xchemlab.pin_packing.write_pin_library", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        let transaction = database.begin().await?;
+
+        let pins = match barcodes {
+            Some(barcodes) => {
+                let pins = pin_library::Entity::find()
+                    .filter(pin_library::Column::Barcode.is_in(barcodes.clone()))
+                    .all(&transaction)
+                    .await?;
+                if let Some(barcode) = barcodes
+                    .iter()
+                    .find(|barcode| !pins.iter().any(|pin| &pin.barcode == *barcode))
+                {
+                    Err(format!("Could not find pin with barcode '{barcode}'"))?;
+                }
+                pins
+            }
+            None => {
+                pin_library::Entity::find()
+                    .filter(pin_library::Column::Status.eq(PinStatus::Dirty))
+                    .all(&transaction)
+                    .await?
+            }
+        };
+        if let Some(pin) = pins.iter().find(|pin| pin.status != PinStatus::Dirty) {
+            Err(format!(
+                "Pin with barcode '{}' cannot be washed whilst {}",
+                pin.barcode, pin.status
+            ))?;
+        }
+        let status_changes = pins
+            .iter()
+            .map(|pin| {
+                transition(
+                    pin.barcode.clone(),
+                    pin.status,
+                    PinStatus::Ready,
+                    operator_id.clone(),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let washed = pin_library::Entity::update_many()
+            .col_expr(pin_library::Column::Status, PinStatus::Ready.as_enum())
+            .filter(pin_library::Column::Barcode.is_in(pins.iter().map(|pin| pin.barcode.clone())))
+            .filter(pin_library::Column::Status.eq(PinStatus::Dirty))
+            .exec(&transaction)
+            .await?;
+        if washed.rows_affected != pins.len() as u64 {
+            Err("Pins were modified whilst being washed")?;
+        }
+        if !status_changes.is_empty() {
+            status_change::Entity::insert_many(status_changes)
+                .exec(&transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        let pins = pins
+            .into_iter()
+            .map(|pin| pin_library::Model {
+                status: PinStatus::Ready,
+                ..pin
+            })
+            .collect();
+        Ok(pins)
     }
 }
@@ -1,13 +1,25 @@
-use crate::tables::{
-    crystal,
-    pin_library::{self, PinStatus},
-    pin_mount, puck_mount,
+use crate::{
+    barcode::check_unregistered_elsewhere,
+    loaders::{CrystalLoader, PuckMountLoader},
+    resolvers::{next_free_slot, violates_constraint},
+    tables::{
+        crystal,
+        pin_library::{self, PinStatus},
+        pin_mount::{
+            self, UnmountReason, UNIQUE_ACTIVE_CRYSTAL_MOUNT, UNIQUE_ACTIVE_PUCK_MOUNT_LOCATION,
+        },
+        puck_library::{self, PuckStatus},
+        puck_mount,
+        puck_type::slots_of_puck_mount,
+        status_change::{self, transition, LibraryItem},
+    },
 };
-use async_graphql::{ComplexObject, Context, Object};
+use async_graphql::{dataloader::DataLoader, ComplexObject, Context, ErrorExtensions, Object};
 use chrono::Utc;
 use opa_client::subject_authorization;
 use sea_orm::{
-    ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel, ModelTrait, TransactionTrait,
+    sea_query::Expr, ActiveEnum, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder, TransactionTrait,
 };
 use uuid::Uuid;
 
@@ -15,25 +27,221 @@ use uuid::Uuid;
 impl pin_mount::Model {
     async fn crystal(&self, ctx: &Context<'_>) -> async_graphql::Result<crystal::Model> {
         subject_authorization!("xchemlab.pin_packing.read_crystal", ctx).await?;
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self
-            .find_related(crystal::Entity)
-            .one(database)
+        Ok(ctx
+            .data::<DataLoader<CrystalLoader>>()?
+            .load_one(self.crystal_id)
             .await?
             .ok_or("Could not find mounted crystal")?)
     }
 
     async fn puck(&self, ctx: &Context<'_>) -> async_graphql::Result<puck_mount::Model> {
         subject_authorization!("xchemlab.pin_packing.read_puck_mount", ctx).await?;
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self
-            .find_related(puck_mount::Entity)
-            .one(database)
+        Ok(ctx
+            .data::<DataLoader<PuckMountLoader>>()?
+            .load_one(self.puck_mount_id)
             .await?
             .ok_or("Could not find mounted puck")?)
     }
 }
 
+/// A reason a pin could not be mounted
+#[derive(Debug, thiserror::Error)]
+pub enum PinMountError {
+    /// The crystal to be mounted does not exist
+    #[error("Could not find crystal {0}")]
+    CrystalNotFound(Uuid),
+    /// The puck to be mounted into does not exist
+    #[error("Could not find puck mount {0}")]
+    PuckNotFound(Uuid),
+    /// The puck is no longer being filled
+    #[error("Puck with barcode '{0}' has been sealed")]
+    PuckSealed(String),
+    /// Every slot of the puck holds a pin
+    #[error("Puck with barcode '{0}' is full")]
+    PuckFull(String),
+    /// The location is not a slot of the puck
+    #[error("Puck location {0} does not exist")]
+    InvalidSlot(i16),
+    /// The slot already holds a pin
+    #[error("Puck location {location} is occupied by pin with barcode '{barcode}'")]
+    SlotOccupied { location: i16, barcode: String },
+    /// The crystal is already held by a pin
+    #[error("Crystal {crystal_id} is already mounted on pin with barcode '{barcode}'")]
+    CrystalAlreadyMounted { crystal_id: Uuid, barcode: String },
+    /// The loop is too small to hold the crystal
+    #[error("A {loop_size}µm loop cannot hold a {crystal_size}µm crystal")]
+    LoopSizeIncompatible { loop_size: i16, crystal_size: i16 },
+}
+
+impl PinMountError {
+    /// A machine readable identifier for the kind of error
+    fn code(&self) -> &'static str {
+        match self {
+            PinMountError::CrystalNotFound(_) => "CRYSTAL_NOT_FOUND",
+            PinMountError::PuckNotFound(_) => "PUCK_NOT_FOUND",
+            PinMountError::PuckSealed(_) => "PUCK_SEALED",
+            PinMountError::PuckFull(_) => "PUCK_FULL",
+            PinMountError::InvalidSlot(_) => "INVALID_SLOT",
+            PinMountError::SlotOccupied { .. } => "SLOT_OCCUPIED",
+            PinMountError::CrystalAlreadyMounted { .. } => "CRYSTAL_ALREADY_MOUNTED",
+            PinMountError::LoopSizeIncompatible { .. } => "LOOP_SIZE_INCOMPATIBLE",
+        }
+    }
+}
+
+impl ErrorExtensions for PinMountError {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, extensions| {
+            extensions.set("code", self.code());
+            match self {
+                PinMountError::CrystalNotFound(crystal_id) => {
+                    extensions.set("crystalId", crystal_id.to_string())
+                }
+                PinMountError::PuckNotFound(puck_mount_id) => {
+                    extensions.set("puckMountId", puck_mount_id.to_string())
+                }
+                PinMountError::PuckSealed(barcode) | PinMountError::PuckFull(barcode) => {
+                    extensions.set("barcode", barcode.as_str())
+                }
+                PinMountError::InvalidSlot(location) => extensions.set("location", *location),
+                PinMountError::SlotOccupied { location, barcode } => {
+                    extensions.set("location", *location);
+                    extensions.set("barcode", barcode.as_str());
+                }
+                PinMountError::CrystalAlreadyMounted {
+                    crystal_id,
+                    barcode,
+                } => {
+                    extensions.set("crystalId", crystal_id.to_string());
+                    extensions.set("barcode", barcode.as_str());
+                }
+                PinMountError::LoopSizeIncompatible {
+                    loop_size,
+                    crystal_size,
+                } => {
+                    extensions.set("loopSize", *loop_size);
+                    extensions.set("crystalSize", *crystal_size);
+                }
+            }
+        })
+    }
+}
+
+/// Checks that a crystal is not held by a pin which has not been unmounted
+async fn check_crystal_unmounted(
+    database: &DatabaseConnection,
+    crystal_id: Uuid,
+) -> async_graphql::Result<()> {
+    match pin_mount::Entity::find()
+        .filter(pin_mount::Column::CrystalId.eq(crystal_id))
+        .filter(pin_mount::Column::UnmountedAt.is_null())
+        .one(database)
+        .await?
+    {
+        Some(mount) => Err(PinMountError::CrystalAlreadyMounted {
+            crystal_id,
+            barcode: mount.barcode,
+        }
+        .extend()),
+        None => Ok(()),
+    }
+}
+
+/// Checks that a puck slot is not held by a pin which has not been unmounted
+async fn check_slot_free(
+    database: &DatabaseConnection,
+    puck_mount_id: Uuid,
+    puck_location: i16,
+) -> async_graphql::Result<()> {
+    match pin_mount::Entity::find()
+        .filter(pin_mount::Column::PuckMountId.eq(puck_mount_id))
+        .filter(pin_mount::Column::PuckLocation.eq(puck_location))
+        .filter(pin_mount::Column::UnmountedAt.is_null())
+        .one(database)
+        .await?
+    {
+        Some(occupant) => Err(PinMountError::SlotOccupied {
+            location: puck_location,
+            barcode: occupant.barcode,
+        }
+        .extend()),
+        None => Ok(()),
+    }
+}
+
+/// Checks that a pin can hold the crystal in a free slot of a puck which is still being filled, returning the slot
+async fn validate_pin_mount(
+    database: &DatabaseConnection,
+    crystal_id: Uuid,
+    puck_mount_id: Uuid,
+    puck_location: Option<i16>,
+    library_pin: &pin_library::Model,
+) -> async_graphql::Result<i16> {
+    let puck_mount = puck_mount::Entity::find_by_id(puck_mount_id)
+        .one(database)
+        .await?
+        .ok_or_else(|| PinMountError::PuckNotFound(puck_mount_id).extend())?;
+    let library_puck = puck_library::Entity::find_by_id(&puck_mount.barcode)
+        .one(database)
+        .await?;
+    let latest_puck_mount = puck_mount::Entity::find()
+        .filter(puck_mount::Column::Barcode.eq(&puck_mount.barcode))
+        .order_by_desc(puck_mount::Column::Timestamp)
+        .one(database)
+        .await?;
+    let filling = library_puck.is_some_and(|puck| puck.status == PuckStatus::Filling)
+        && latest_puck_mount.is_some_and(|latest| latest.id == puck_mount.id)
+        && puck_mount.cane_mount_id.is_none();
+    if !filling {
+        Err(PinMountError::PuckSealed(puck_mount.barcode.clone()).extend())?;
+    }
+
+    let occupants = pin_mount::Entity::find()
+        .filter(pin_mount::Column::PuckMountId.eq(puck_mount_id))
+        .filter(pin_mount::Column::UnmountedAt.is_null())
+        .all(database)
+        .await?;
+    let slots = slots_of_puck_mount(database, puck_mount_id).await?;
+    let puck_location = match puck_location {
+        Some(puck_location) => {
+            if !(1..=slots).contains(&puck_location) {
+                Err(PinMountError::InvalidSlot(puck_location).extend())?;
+            }
+            if let Some(occupant) = occupants
+                .into_iter()
+                .find(|occupant| occupant.puck_location == puck_location)
+            {
+                Err(PinMountError::SlotOccupied {
+                    location: puck_location,
+                    barcode: occupant.barcode,
+                }
+                .extend())?;
+            }
+            puck_location
+        }
+        None => next_free_slot(
+            occupants.iter().map(|occupant| occupant.puck_location),
+            slots,
+        )
+        .ok_or_else(|| PinMountError::PuckFull(puck_mount.barcode).extend())?,
+    };
+
+    let crystal = crystal::Entity::find_by_id(crystal_id)
+        .one(database)
+        .await?
+        .ok_or_else(|| PinMountError::CrystalNotFound(crystal_id).extend())?;
+    check_crystal_unmounted(database, crystal_id).await?;
+    if let Some(crystal_size) = crystal.size.filter(|size| *size > library_pin.loop_size) {
+        Err(PinMountError::LoopSizeIncompatible {
+            loop_size: library_pin.loop_size,
+            crystal_size,
+        }
+        .extend())?;
+    }
+
+    Ok(puck_location)
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct PinMountQuery;
 
@@ -60,21 +268,33 @@ impl PinMountMutation {
         ctx: &Context<'_>,
         crystal_id: Uuid,
         puck_mount_id: Uuid,
-        puck_location: i16,
+        #[graphql(desc = "The slot to mount into, defaults to the first free slot")]
+        puck_location: Option<i16>,
         barcode: String,
     ) -> async_graphql::Result<pin_mount::Model> {
         let operator_id =
             subject_authorization!("xchemlab.pin_packing.write_pin_mount", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
+        check_unregistered_elsewhere(database, LibraryItem::Pin, &barcode).await?;
 
         let library_pin = pin_library::Entity::find_by_id(&barcode)
             .one(database)
             .await?
             .ok_or(format!("Could not find pin with barcode {barcode}"))?;
-        match library_pin.status {
-            PinStatus::Ready => Ok(()),
-            status => Err(format!("Mount cannot be started whilst Pin is {status}")),
-        }?;
+        let puck_location = validate_pin_mount(
+            database,
+            crystal_id,
+            puck_mount_id,
+            puck_location,
+            &library_pin,
+        )
+        .await?;
+        let status_change = transition(
+            barcode.clone(),
+            library_pin.status,
+            PinStatus::Occupied,
+            operator_id.clone(),
+        )?;
 
         let pin_mount = pin_mount::ActiveModel {
             id: ActiveValue::Set(Uuid::now_v7()),
@@ -84,22 +304,259 @@ impl PinMountMutation {
             barcode: ActiveValue::Set(barcode),
             timestamp: ActiveValue::Set(Utc::now()),
             operator_id: ActiveValue::Set(operator_id),
+            unmounted_at: ActiveValue::Set(None),
+            unmount_reason: ActiveValue::Set(None),
         };
-        let mut library_pin = library_pin.into_active_model();
-        library_pin.status = ActiveValue::Set(PinStatus::Occupied);
-
-        let pin_mount = database
-            .transaction(|transaction| {
-                Box::pin(async {
-                    pin_library::Entity::update(library_pin)
-                        .exec(transaction)
-                        .await?;
-                    pin_mount::Entity::insert(pin_mount)
-                        .exec_with_returning(transaction)
-                        .await
-                })
-            })
+        let transaction = database.begin().await?;
+        status_change::Entity::insert(status_change)
+            .exec(&transaction)
+            .await?;
+        let occupied = pin_library::Entity::update_many()
+            .col_expr(pin_library::Column::Status, PinStatus::Occupied.as_enum())
+            .filter(pin_library::Column::Barcode.eq(&library_pin.barcode))
+            .filter(pin_library::Column::Status.eq(library_pin.status))
+            .exec(&transaction)
+            .await?;
+        if occupied.rows_affected == 0 {
+            Err(format!(
+                "Pin with barcode '{}' was modified whilst being mounted",
+                library_pin.barcode
+            ))?;
+        }
+        match pin_mount::Entity::insert(pin_mount)
+            .exec_with_returning(&transaction)
+            .await
+        {
+            Ok(pin_mount) => {
+                transaction.commit().await?;
+                Ok(pin_mount)
+            }
+            // The crystal or slot was taken since they were validated
+            Err(error) => {
+                transaction.rollback().await?;
+                if violates_constraint(&error, UNIQUE_ACTIVE_CRYSTAL_MOUNT) {
+                    check_crystal_unmounted(database, crystal_id).await?;
+                } else if violates_constraint(&error, UNIQUE_ACTIVE_PUCK_MOUNT_LOCATION) {
+                    check_slot_free(database, puck_mount_id, puck_location).await?;
+                }
+                Err(error)?
+            }
+        }
+    }
+
+    /// Ends a pin mount, leaving the pin dirty until it has been washed, or broken if it was lost
+    async fn unmount_pin(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        reason: UnmountReason,
+    ) -> async_graphql::Result<pin_mount::Model> {
+        let operator_id =
+            subject_authorization!("xchemlab.pin_packing.write_pin_mount", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        let transaction = database.begin().await?;
+
+        let pin_mount = pin_mount::Entity::find_by_id(id)
+            .one(&transaction)
+            .await?
+            .ok_or(format!("Could not find pin mount {id}"))?;
+        if let Some(unmounted_at) = pin_mount.unmounted_at {
+            Err(format!("Pin mount {id} was unmounted at {unmounted_at}"))?;
+        }
+        let unmounted = pin_mount::Entity::update_many()
+            .col_expr(pin_mount::Column::UnmountedAt, Expr::value(Utc::now()))
+            .col_expr(
+                pin_mount::Column::UnmountReason,
+                Expr::value(reason.to_value()),
+            )
+            .filter(pin_mount::Column::Id.eq(id))
+            .filter(pin_mount::Column::UnmountedAt.is_null())
+            .exec(&transaction)
             .await?;
+        if unmounted.rows_affected == 0 {
+            Err(format!("Pin mount {id} has already been unmounted"))?;
+        }
+
+        let library_pin = pin_library::Entity::find_by_id(&pin_mount.barcode)
+            .one(&transaction)
+            .await?
+            .ok_or(format!(
+                "Could not find pin with barcode {}",
+                pin_mount.barcode
+            ))?;
+        if let Some(status) = unmounted_status(library_pin.status, reason) {
+            status_change::Entity::insert(transition(
+                library_pin.barcode.clone(),
+                library_pin.status,
+                status,
+                operator_id,
+            )?)
+            .exec(&transaction)
+            .await?;
+            let mut library_pin = library_pin.into_active_model();
+            library_pin.status = ActiveValue::Set(status);
+            pin_library::Entity::update(library_pin)
+                .exec(&transaction)
+                .await?;
+        }
+
+        let pin_mount = pin_mount::Entity::find_by_id(id)
+            .one(&transaction)
+            .await?
+            .ok_or(format!("Could not find pin mount {id}"))?;
+        transaction.commit().await?;
         Ok(pin_mount)
     }
 }
+
+/// The status a pin takes once unmounted, if it changes
+///
+/// Pins must be washed before reuse, whilst lost pins are broken and broken pins remain broken.
+fn unmounted_status(status: PinStatus, reason: UnmountReason) -> Option<PinStatus> {
+    match (status, reason) {
+        (PinStatus::Broken, _) => None,
+        (_, UnmountReason::Lost) => Some(PinStatus::Broken),
+        _ => Some(PinStatus::Dirty),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unmounted_status, PinMountError};
+    use crate::tables::{pin_library::PinStatus, pin_mount::UnmountReason};
+    use async_graphql::{ErrorExtensions, Value};
+    use uuid::Uuid;
+
+    /// The extensions of the GraphQL error, by name
+    fn extensions(error: PinMountError) -> Vec<(String, Value)> {
+        let error = error.extend();
+        let extensions = error.extensions.unwrap();
+        let mut names = [
+            "code",
+            "crystalId",
+            "puckMountId",
+            "barcode",
+            "location",
+            "loopSize",
+            "crystalSize",
+        ]
+        .into_iter()
+        .filter_map(|name| Some((name.to_string(), extensions.get(name)?.clone())))
+        .collect::<Vec<_>>();
+        names.sort_by(|(a, _), (b, _)| a.cmp(b));
+        names
+    }
+
+    fn expected(extensions: &[(&str, Value)]) -> Vec<(String, Value)> {
+        let mut extensions = extensions
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect::<Vec<_>>();
+        extensions.sort_by(|(a, _), (b, _)| a.cmp(b));
+        extensions
+    }
+
+    #[test]
+    fn errors_are_extended_with_code_and_fields() {
+        let id = Uuid::nil();
+        let barcode = || "PK-1".to_string();
+        let cases = [
+            (
+                PinMountError::CrystalNotFound(id),
+                expected(&[
+                    ("code", "CRYSTAL_NOT_FOUND".into()),
+                    ("crystalId", id.to_string().into()),
+                ]),
+            ),
+            (
+                PinMountError::PuckNotFound(id),
+                expected(&[
+                    ("code", "PUCK_NOT_FOUND".into()),
+                    ("puckMountId", id.to_string().into()),
+                ]),
+            ),
+            (
+                PinMountError::PuckSealed(barcode()),
+                expected(&[("code", "PUCK_SEALED".into()), ("barcode", "PK-1".into())]),
+            ),
+            (
+                PinMountError::PuckFull(barcode()),
+                expected(&[("code", "PUCK_FULL".into()), ("barcode", "PK-1".into())]),
+            ),
+            (
+                PinMountError::InvalidSlot(17),
+                expected(&[("code", "INVALID_SLOT".into()), ("location", 17.into())]),
+            ),
+            (
+                PinMountError::SlotOccupied {
+                    location: 3,
+                    barcode: barcode(),
+                },
+                expected(&[
+                    ("code", "SLOT_OCCUPIED".into()),
+                    ("location", 3.into()),
+                    ("barcode", "PK-1".into()),
+                ]),
+            ),
+            (
+                PinMountError::CrystalAlreadyMounted {
+                    crystal_id: id,
+                    barcode: barcode(),
+                },
+                expected(&[
+                    ("code", "CRYSTAL_ALREADY_MOUNTED".into()),
+                    ("crystalId", id.to_string().into()),
+                    ("barcode", "PK-1".into()),
+                ]),
+            ),
+            (
+                PinMountError::LoopSizeIncompatible {
+                    loop_size: 50,
+                    crystal_size: 80,
+                },
+                expected(&[
+                    ("code", "LOOP_SIZE_INCOMPATIBLE".into()),
+                    ("loopSize", 50.into()),
+                    ("crystalSize", 80.into()),
+                ]),
+            ),
+        ];
+        for (error, expected) in cases {
+            let code = expected
+                .iter()
+                .find(|(name, _)| name == "code")
+                .map(|(_, code)| code.clone());
+            assert_eq!(code, Some(Value::from(error.code())));
+            assert_eq!(expected, extensions(error));
+        }
+    }
+
+    #[test]
+    fn unmounted_pins_are_dirty() {
+        for reason in [UnmountReason::Collected, UnmountReason::Failed] {
+            assert_eq!(
+                Some(PinStatus::Dirty),
+                unmounted_status(PinStatus::Occupied, reason)
+            );
+        }
+    }
+
+    #[test]
+    fn lost_pins_are_broken() {
+        assert_eq!(
+            Some(PinStatus::Broken),
+            unmounted_status(PinStatus::Occupied, UnmountReason::Lost)
+        );
+    }
+
+    #[test]
+    fn broken_pins_remain_broken() {
+        for reason in [
+            UnmountReason::Collected,
+            UnmountReason::Failed,
+            UnmountReason::Lost,
+        ] {
+            assert_eq!(None, unmounted_status(PinStatus::Broken, reason));
+        }
+    }
+}
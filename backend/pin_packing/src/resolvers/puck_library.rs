@@ -1,18 +1,47 @@
-use crate::tables::{
-    puck_library::{self, PuckStatus},
-    puck_mount,
+use super::library_import::{
+    read_import_file, register_rows, type_names, ImportMode, ImportRecord, ImportReport,
+    LibraryTypedInput, PendingRow,
+};
+use crate::{
+    barcode::{check_unregistered_elsewhere, BarcodeFormats},
+    loaders::{PuckMountsByBarcodeLoader, StatusHistoryLoader},
+    tables::{
+        puck_library::{self, PuckStatus},
+        puck_mount,
+        puck_type::{self, DEFAULT_PUCK_TYPE},
+        status_change::{self, transition, LibraryItem},
+    },
+};
+use async_graphql::{
+    dataloader::DataLoader, ComplexObject, Context, ErrorExtensions, Object, Upload,
 };
-use async_graphql::{ComplexObject, Context, Object};
 use opa_client::subject_authorization;
-use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel, ModelTrait};
+use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel, TransactionTrait};
+use std::collections::HashSet;
 use the_paginator::graphql::{CursorInput, ModelConnection};
 
 #[ComplexObject]
 impl puck_library::Model {
     async fn mounts(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<puck_mount::Model>> {
         subject_authorization!("xchemlab.pin_packing.read_puck_mount", ctx).await?;
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self.find_related(puck_mount::Entity).all(database).await?)
+        Ok(ctx
+            .data::<DataLoader<PuckMountsByBarcodeLoader>>()?
+            .load_one(self.barcode.clone())
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// The changes made to the status of the puck, in the order they were made
+    async fn status_history(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Vec<status_change::Model>> {
+        subject_authorization!("xchemlab.pin_packing.read_puck_library", ctx).await?;
+        Ok(ctx
+            .data::<DataLoader<StatusHistoryLoader>>()?
+            .load_one((LibraryItem::Puck, self.barcode.clone()))
+            .await?
+            .unwrap_or_default())
     }
 }
 
@@ -34,6 +63,31 @@ impl PuckLibraryQuery {
             .await?
             .try_into_connection()?)
     }
+
+    async fn puck_types(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<puck_type::Model>> {
+        subject_authorization!("xchemlab.pin_packing.read_puck_library", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        Ok(puck_type::Entity::find().all(database).await?)
+    }
+}
+
+/// Prepares a puck for registration with the puck type it names, if registered
+fn pending_puck(
+    row: i32,
+    puck: LibraryTypedInput,
+    puck_types: &HashSet<String>,
+) -> PendingRow<puck_library::ActiveModel> {
+    puck.into_pending(
+        row,
+        LibraryItem::Puck,
+        puck_types,
+        DEFAULT_PUCK_TYPE,
+        |barcode, puck_type| puck_library::ActiveModel {
+            barcode: ActiveValue::Set(barcode),
+            status: ActiveValue::Set(PuckStatus::Ready),
+            puck_type: ActiveValue::Set(puck_type),
+        },
+    )
 }
 
 #[derive(Debug, Clone, Default)]
@@ -41,36 +95,146 @@ pub struct PuckLibraryMutation;
 
 #[Object]
 impl PuckLibraryMutation {
+    async fn register_puck_type(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        #[graphql(desc = "The number of pin slots")] slots: i16,
+    ) -> async_graphql::Result<puck_type::Model> {
+        subject_authorization!("xchemlab.pin_packing.write_puck_library", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        if slots < 1 {
+            Err("Puck types must have at least one slot")?;
+        }
+        let puck_type = puck_type::ActiveModel {
+            name: ActiveValue::Set(name),
+            slots: ActiveValue::Set(slots),
+        };
+        Ok(puck_type::Entity::insert(puck_type)
+            .exec_with_returning(database)
+            .await?)
+    }
+
     async fn register_library_puck(
         &self,
         ctx: &Context<'_>,
         barcode: String,
+        #[graphql(desc = "The name of the puck type, defaults to the original puck type")]
+        puck_type: Option<String>,
     ) -> async_graphql::Result<puck_library::Model> {
         subject_authorization!("xchemlab.pin_packing.write_puck_library", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
+        ctx.data::<BarcodeFormats>()?
+            .check(LibraryItem::Puck, &barcode)
+            .map_err(|error| error.extend())?;
+        check_unregistered_elsewhere(database, LibraryItem::Puck, &barcode).await?;
+        let puck_type = puck_type.unwrap_or(DEFAULT_PUCK_TYPE.to_string());
+        puck_type::Entity::find_by_id(&puck_type)
+            .one(database)
+            .await?
+            .ok_or(format!("Could not find puck type '{puck_type}'"))?;
         let puck = puck_library::ActiveModel {
             barcode: ActiveValue::Set(barcode),
             status: ActiveValue::Set(PuckStatus::Ready),
+            puck_type: ActiveValue::Set(puck_type),
         };
         Ok(puck_library::Entity::insert(puck)
             .exec_with_returning(database)
             .await?)
     }
 
+    /// Registers many pucks at once, reporting the rows which could not be registered
+    async fn register_library_pucks(
+        &self,
+        ctx: &Context<'_>,
+        pucks: Vec<LibraryTypedInput>,
+        #[graphql(default)] mode: ImportMode,
+        #[graphql(desc = "Reports the outcome without registering any pucks", default)]
+        dry_run: bool,
+    ) -> async_graphql::Result<ImportReport> {
+        subject_authorization!("xchemlab.pin_packing.write_puck_library", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        let puck_types = type_names(database, puck_type::Column::Name).await?;
+        let rows = pucks
+            .into_iter()
+            .zip(1..)
+            .map(|(puck, row)| pending_puck(row, puck, &puck_types))
+            .collect();
+        register_rows(
+            database,
+            ctx.data::<BarcodeFormats>()?,
+            LibraryItem::Puck,
+            rows,
+            mode,
+            dry_run,
+        )
+        .await
+    }
+
+    /// Registers the pucks listed in a CSV or XLSX file with `barcode` and optional `puck_type`
+    /// columns
+    async fn import_library_pucks(
+        &self,
+        ctx: &Context<'_>,
+        file: Upload,
+        #[graphql(default)] mode: ImportMode,
+        #[graphql(desc = "Reports the outcome without registering any pucks", default)]
+        dry_run: bool,
+    ) -> async_graphql::Result<ImportReport> {
+        subject_authorization!("xchemlab.pin_packing.write_puck_library", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        let puck_types = type_names(database, puck_type::Column::Name).await?;
+        let rows = read_import_file(ctx, file)?
+            .into_iter()
+            .map(|(row, mut record): (_, ImportRecord)| {
+                let puck = LibraryTypedInput {
+                    barcode: record.remove("barcode").unwrap_or_default(),
+                    type_name: record.remove("puck_type"),
+                };
+                pending_puck(row, puck, &puck_types)
+            })
+            .collect();
+        register_rows(
+            database,
+            ctx.data::<BarcodeFormats>()?,
+            LibraryItem::Puck,
+            rows,
+            mode,
+            dry_run,
+        )
+        .await
+    }
+
     async fn update_library_puck_status(
         &self,
         ctx: &Context<'_>,
         barcode: String,
         status: PuckStatus,
     ) -> async_graphql::Result<puck_library::Model> {
-        subject_authorization!("xchemlab.pin_packing.write_puck_library", ctx).await?;
+        let operator_id =
+            subject_authorization!("xchemlab.pin_packing.write_puck_library", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
-        let mut puck = puck_library::Entity::find_by_id(&barcode)
+        if status == PuckStatus::Filling {
+            Err("Pucks start filling when they are mounted")?;
+        }
+        let puck = puck_library::Entity::find_by_id(&barcode)
             .one(database)
             .await?
-            .ok_or(format!("Could not find puck with barcode '{barcode}'"))?
-            .into_active_model();
+            .ok_or(format!("Could not find puck with barcode '{barcode}'"))?;
+        let status_change = transition(barcode, puck.status, status, operator_id)?;
+        let mut puck = puck.into_active_model();
         puck.status = ActiveValue::Set(status);
-        Ok(puck_library::Entity::update(puck).exec(database).await?)
+
+        let puck = database
+            .transaction(|transaction| {
+                Box::pin(async {
+                    status_change::Entity::insert(status_change)
+                        .exec(transaction)
+                        .await?;
+                    puck_library::Entity::update(puck).exec(transaction).await
+                })
+            })
+            .await?;
+        Ok(puck)
     }
 }
@@ -1,28 +1,151 @@
-use crate::tables::{
-    cane_mount, pin_mount,
-    puck_library::{self, PuckStatus},
-    puck_mount,
+use crate::{
+    barcode::check_unregistered_elsewhere,
+    loaders::{CaneMountLoader, PuckMountTypeLoader, PuckPinsLoader},
+    resolvers::next_free_slot,
+    tables::{
+        cane_library::{self, CaneStatus},
+        cane_mount,
+        cane_type::slots_of_cane_mount,
+        pin_mount,
+        puck_library::{self, PuckStatus},
+        puck_mount,
+        status_change::{self, transition, LibraryItem},
+    },
 };
-use async_graphql::{ComplexObject, Context, Object};
+use async_graphql::{dataloader::DataLoader, ComplexObject, Context, Object, SimpleObject};
 use chrono::Utc;
 use opa_client::subject_authorization;
 use sea_orm::{
-    ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel, ModelTrait, TransactionTrait,
+    ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, TransactionTrait,
 };
 use uuid::Uuid;
 
+/// A slot of a puck, along with the pin it holds
+#[derive(Debug, Clone, SimpleObject)]
+pub struct PuckSlot {
+    location: i16,
+    pin: Option<pin_mount::Model>,
+}
+
 #[ComplexObject]
 impl puck_mount::Model {
     async fn pins(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<pin_mount::Model>> {
         subject_authorization!("xchemlab.pin_packing.read_pin_mount", ctx).await?;
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self.find_related(pin_mount::Entity).all(database).await?)
+        Ok(ctx
+            .data::<DataLoader<PuckPinsLoader>>()?
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Every slot of the puck, along with the pin it holds
+    async fn layout(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<PuckSlot>> {
+        subject_authorization!("xchemlab.pin_packing.read_pin_mount", ctx).await?;
+        let pins = ctx
+            .data::<DataLoader<PuckPinsLoader>>()?
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default();
+        let slots = self.slots(ctx).await?;
+        Ok((1..=slots)
+            .map(|location| PuckSlot {
+                location,
+                pin: pins
+                    .iter()
+                    .find(|pin| pin.puck_location == location)
+                    .cloned(),
+            })
+            .collect())
+    }
+
+    /// The lowest numbered slot of the puck which does not hold a pin
+    async fn next_free_slot(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<i16>> {
+        subject_authorization!("xchemlab.pin_packing.read_pin_mount", ctx).await?;
+        let pins = ctx
+            .data::<DataLoader<PuckPinsLoader>>()?
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default();
+        Ok(next_free_slot(
+            pins.iter().map(|pin| pin.puck_location),
+            self.slots(ctx).await?,
+        ))
+    }
+
+    /// The number of pin slots in the puck
+    async fn slots(&self, ctx: &Context<'_>) -> async_graphql::Result<i16> {
+        subject_authorization!("xchemlab.pin_packing.read_puck_library", ctx).await?;
+        Ok(ctx
+            .data::<DataLoader<PuckMountTypeLoader>>()?
+            .load_one(self.id)
+            .await?
+            .ok_or("Could not find the type of mounted puck")?
+            .slots)
     }
 
     async fn cane(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<cane_mount::Model>> {
         subject_authorization!("xchemlab.pin_packing.read_cane_mount", ctx).await?;
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self.find_related(cane_mount::Entity).one(database).await?)
+        Ok(match self.cane_mount_id {
+            Some(cane_mount_id) => {
+                ctx.data::<DataLoader<CaneMountLoader>>()?
+                    .load_one(cane_mount_id)
+                    .await?
+            }
+            None => None,
+        })
+    }
+}
+
+/// Checks that a cane is still being filled and has a free slot, returning the slot
+async fn validate_cane_placement(
+    database: &DatabaseConnection,
+    cane_mount_id: Uuid,
+    cane_location: Option<i16>,
+) -> async_graphql::Result<i16> {
+    let cane_mount = cane_mount::Entity::find_by_id(cane_mount_id)
+        .one(database)
+        .await?
+        .ok_or(format!("Could not find cane mount {cane_mount_id}"))?;
+    let library_cane = cane_library::Entity::find_by_id(&cane_mount.barcode)
+        .one(database)
+        .await?;
+    let latest_cane_mount = cane_mount::Entity::find()
+        .filter(cane_mount::Column::Barcode.eq(&cane_mount.barcode))
+        .order_by_desc(cane_mount::Column::Timestamp)
+        .one(database)
+        .await?;
+    let filling = library_cane.is_some_and(|cane| cane.status == CaneStatus::Filling)
+        && latest_cane_mount.is_some_and(|latest| latest.id == cane_mount.id);
+    if !filling {
+        Err(format!(
+            "Cane with barcode '{}' has been sealed",
+            cane_mount.barcode
+        ))?;
+    }
+
+    let slots = slots_of_cane_mount(database, cane_mount_id).await?;
+    let occupied = puck_mount::Entity::find()
+        .filter(puck_mount::Column::CaneMountId.eq(cane_mount_id))
+        .all(database)
+        .await?
+        .into_iter()
+        .filter_map(|puck| puck.cane_location)
+        .collect::<Vec<_>>();
+    match cane_location {
+        Some(cane_location) => {
+            if !(1..=slots).contains(&cane_location) {
+                Err(format!("Cane location {cane_location} does not exist"))?;
+            }
+            if occupied.contains(&cane_location) {
+                Err(format!("Cane location {cane_location} is occupied"))?;
+            }
+            Ok(cane_location)
+        }
+        None => Ok(next_free_slot(occupied, slots).ok_or(format!(
+            "Cane with barcode '{}' is full",
+            cane_mount.barcode
+        ))?),
     }
 }
 
@@ -51,24 +174,40 @@ impl PuckMountMutation {
         &self,
         ctx: &Context<'_>,
         barcode: String,
+        #[graphql(desc = "The cane to place the puck into, if any")] cane_mount_id: Option<Uuid>,
+        #[graphql(
+            desc = "The slot of the cane to place the puck into, defaults to the first free slot"
+        )]
+        cane_location: Option<i16>,
     ) -> async_graphql::Result<puck_mount::Model> {
         let operator_id =
             subject_authorization!("xchemlab.pin_packing.write_puck_mount", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
+        check_unregistered_elsewhere(database, LibraryItem::Puck, &barcode).await?;
+
+        let cane_location = match cane_mount_id {
+            Some(cane_mount_id) => {
+                Some(validate_cane_placement(database, cane_mount_id, cane_location).await?)
+            }
+            None if cane_location.is_some() => Err("A cane location requires a cane mount")?,
+            None => None,
+        };
 
         let library_puck = puck_library::Entity::find_by_id(&barcode)
             .one(database)
             .await?
             .ok_or(format!("Could not find puck with barcode '{barcode}'"))?;
-        match library_puck.status {
-            PuckStatus::Ready => Ok(()),
-            status => Err(format!("Mount cannot be started whilst Cane is {status}")),
-        }?;
+        let status_change = transition(
+            barcode.clone(),
+            library_puck.status,
+            PuckStatus::Filling,
+            operator_id.clone(),
+        )?;
 
         let puck = puck_mount::ActiveModel {
             id: ActiveValue::Set(Uuid::now_v7()),
-            cane_mount_id: ActiveValue::Set(None),
-            cane_location: ActiveValue::Set(None),
+            cane_mount_id: ActiveValue::Set(cane_mount_id),
+            cane_location: ActiveValue::Set(cane_location),
             barcode: ActiveValue::Set(barcode),
             timestamp: ActiveValue::Set(Utc::now()),
             operator_id: ActiveValue::Set(operator_id),
@@ -79,6 +218,9 @@ impl PuckMountMutation {
         let puck = database
             .transaction(|transaction| {
                 Box::pin(async {
+                    status_change::Entity::insert(status_change)
+                        .exec(transaction)
+                        .await?;
                     puck_library::Entity::update(library_puck)
                         .exec(transaction)
                         .await?;
@@ -0,0 +1,86 @@
+use crate::{loaders::WellCrystalsLoader, tables::crystal};
+use async_graphql::{dataloader::DataLoader, ComplexObject, Context, Object, SimpleObject};
+use opa_client::subject_authorization;
+use uuid::Uuid;
+
+/// A crystal well, extended from the crystal library subgraph
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(name = "crystal_wells", complex)]
+pub struct CrystalWells {
+    /// ID of the plate on which the crystal is located
+    pub plate_id: Uuid,
+    /// Well on the plate in which crystal is located
+    pub well_number: i16,
+}
+
+#[ComplexObject]
+impl CrystalWells {
+    /// The crystals harvested from the well
+    async fn crystals(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<crystal::Model>> {
+        subject_authorization!("xchemlab.pin_packing.read_crystal", ctx).await?;
+        Ok(ctx
+            .data::<DataLoader<WellCrystalsLoader>>()?
+            .load_one((self.plate_id, self.well_number))
+            .await?
+            .unwrap_or_default())
+    }
+}
+
+/// A predicted crystal, extended from the targeting subgraph
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(name = "PredictedCrystal")]
+pub struct PredictedCrystal {
+    pub id: Uuid,
+}
+
+/// A compound soaked into a crystal well, extended from the compound soaking subgraph
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(name = "soaked_compounds")]
+pub struct SoakedCompound {
+    /// ID of the plate on which the compound is located
+    pub compound_plate_id: Uuid,
+    /// The well on the plate which the compound is located
+    pub compound_well_number: i16,
+    /// ID of the plate on which the crystal is located
+    pub crystal_plate_id: Uuid,
+    /// The well on the plate which the crystal is located
+    pub crystal_well_number: i16,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SubgraphExtensionsQuery;
+
+#[Object]
+impl SubgraphExtensionsQuery {
+    /// Reference resolver for crystal wells
+    #[graphql(entity)]
+    async fn route_crystal_well(&self, plate_id: Uuid, well_number: i16) -> CrystalWells {
+        CrystalWells {
+            plate_id,
+            well_number,
+        }
+    }
+
+    /// Reference resolver for predicted crystals
+    #[graphql(entity)]
+    async fn route_predicted_crystal(&self, id: Uuid) -> PredictedCrystal {
+        PredictedCrystal { id }
+    }
+
+    /// Reference resolver for soaked compounds
+    #[graphql(entity)]
+    async fn route_soaked_compound(
+        &self,
+        compound_plate_id: Uuid,
+        compound_well_number: i16,
+        crystal_plate_id: Uuid,
+        crystal_well_number: i16,
+    ) -> SoakedCompound {
+        SoakedCompound {
+            compound_plate_id,
+            compound_well_number,
+            crystal_plate_id,
+            crystal_well_number,
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use super::cane_mount;
+use super::{
+    cane_mount, cane_type,
+    status_change::{LibraryItem, LibraryStatus},
+};
 use async_graphql::{Enum, SimpleObject};
 use axum::async_trait;
 use sea_orm::{
@@ -19,6 +22,18 @@ pub enum CaneStatus {
     Broken,
 }
 
+impl LibraryStatus for CaneStatus {
+    const ITEM: LibraryItem = LibraryItem::Cane;
+
+    fn can_become(self, status: Self) -> bool {
+        use CaneStatus::*;
+        matches!(
+            (self, status),
+            (Ready, Filling | Broken) | (Filling, Away | Broken) | (Away, Ready | Broken)
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
 #[sea_orm(table_name = "cane_library")]
 #[graphql(name = "LibraryCane", complex)]
@@ -26,6 +41,8 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub barcode: String,
     pub status: CaneStatus,
+    #[sea_orm(default_value = "Standard")]
+    pub cane_type: String,
 }
 
 #[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
@@ -40,5 +57,16 @@ impl Related<cane_mount::Entity> for Entity {
     }
 }
 
+// Not a variant of [`Relation`], which would add its foreign key to the table created by the
+// initial migration before cane types existed
+impl Related<cane_type::Entity> for Entity {
+    fn to() -> sea_orm::RelationDef {
+        Entity::belongs_to(cane_type::Entity)
+            .from(Column::CaneType)
+            .to(cane_type::Column::Name)
+            .into()
+    }
+}
+
 #[async_trait]
 impl ActiveModelBehavior for ActiveModel {}
@@ -7,8 +7,6 @@ use sea_orm::{
 };
 use uuid::Uuid;
 
-pub const CANE_SLOTS: i16 = 7;
-
 #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
 #[sea_orm(table_name = "cane_mount")]
 #[graphql(name = "MountedCane", complex)]
@@ -0,0 +1,56 @@
+use super::{cane_library, cane_mount};
+use async_graphql::SimpleObject;
+use axum::async_trait;
+use sea_orm::{
+    ActiveModelBehavior, ColumnTrait, ConnectionTrait, DbErr, DeriveEntityModel, DerivePrimaryKey,
+    DeriveRelation, EntityTrait, EnumIter, JoinType, PrimaryKeyTrait, QueryFilter, QuerySelect,
+    Related, RelationTrait,
+};
+use uuid::Uuid;
+
+/// The type of canes registered without one, including those registered before cane types existed
+pub const DEFAULT_CANE_TYPE: &str = "Standard";
+/// The number of slots in the default cane type
+pub const DEFAULT_CANE_SLOTS: i16 = 7;
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
+#[sea_orm(table_name = "cane_type")]
+#[graphql(name = "CaneType")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub name: String,
+    /// The number of puck slots
+    pub slots: i16,
+}
+
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "cane_library::Entity")]
+    LibraryCane,
+}
+
+impl Related<cane_library::Entity> for Entity {
+    fn to() -> sea_orm::RelationDef {
+        Relation::LibraryCane.def()
+    }
+}
+
+#[async_trait]
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Finds the number of slots in a mounted cane
+pub async fn slots_of_cane_mount(
+    database: &impl ConnectionTrait,
+    cane_mount_id: Uuid,
+) -> Result<i16, DbErr> {
+    Ok(Entity::find()
+        .join(JoinType::InnerJoin, Relation::LibraryCane.def())
+        .join(JoinType::InnerJoin, cane_library::Relation::CaneMount.def())
+        .filter(cane_mount::Column::Id.eq(cane_mount_id))
+        .one(database)
+        .await?
+        .ok_or(DbErr::RecordNotFound(format!(
+            "Could not find the type of cane mount {cane_mount_id}"
+        )))?
+        .slots)
+}
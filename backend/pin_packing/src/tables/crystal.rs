@@ -3,6 +3,7 @@ use async_graphql::{Enum, SimpleObject};
 use axum::async_trait;
 use chrono::{DateTime, Utc};
 use sea_orm::{
+    sea_query::{Index, IndexCreateStatement},
     ActiveModelBehavior, DeriveActiveEnum, DeriveEntityModel, DerivePrimaryKey, DeriveRelation,
     EntityTrait, EnumIter, PrimaryKeyTrait, Related, RelationTrait,
 };
@@ -48,10 +49,49 @@ pub struct Model {
     pub well: i16,
     pub crystal_state: CrystalState,
     pub compound_state: CompoundState,
+    /// The largest dimension of the crystal in micrometers, if measured
+    pub size: Option<i16>,
+    /// The position of the crystal amongst those harvested from the same well, starting from one
+    pub number: i16,
+    /// The cryoprotectant the crystal was treated with, if any
+    pub cryoprotectant: Option<String>,
+    /// The number of seconds the crystal spent out of the drop before being frozen
+    pub time_out_of_drop: Option<i32>,
+    /// The orientation of the crystal in the loop
+    pub loop_orientation: Option<String>,
+    /// The key of the photo of the harvested crystal in the S3 bucket
+    #[graphql(skip)]
+    pub photo_key: Option<String>,
+    /// Free text remarks recorded by the operator whilst harvesting
+    pub notes: Option<String>,
+    /// The crystal predicted by the targeting service which was harvested, if known
+    #[graphql(skip)]
+    pub predicted_crystal_id: Option<Uuid>,
+    /// The plate holding the compound the crystal was soaked in, if known
+    #[graphql(skip)]
+    pub soaked_compound_plate: Option<Uuid>,
+    /// The well holding the compound the crystal was soaked in, if known
+    #[graphql(skip)]
+    pub soaked_compound_well: Option<i16>,
     pub timestamp: DateTime<Utc>,
     pub operator_id: String,
 }
 
+/// The name of the index preventing two crystals harvested from the same well sharing a number
+pub const UNIQUE_WELL_CRYSTAL_NUMBER: &str = "unique-well-crystal-number";
+
+/// Prevents two crystals harvested from the same well sharing a number
+pub fn unique_well_crystal_number() -> IndexCreateStatement {
+    Index::create()
+        .name(UNIQUE_WELL_CRYSTAL_NUMBER)
+        .table(Entity)
+        .col(Column::Plate)
+        .col(Column::Well)
+        .col(Column::Number)
+        .unique()
+        .to_owned()
+}
+
 #[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_one = "pin_mount::Entity")]
@@ -66,3 +106,8 @@ impl Related<pin_mount::Entity> for Entity {
 
 #[async_trait]
 impl ActiveModelBehavior for ActiveModel {}
+
+/// The key under which the photo of a crystal is stored
+pub fn photo_key(id: Uuid) -> String {
+    format!("crystals/{id}")
+}
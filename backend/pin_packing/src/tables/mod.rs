@@ -1,7 +1,10 @@
 pub mod cane_library;
 pub mod cane_mount;
+pub mod cane_type;
 pub mod crystal;
 pub mod pin_library;
 pub mod pin_mount;
 pub mod puck_library;
 pub mod puck_mount;
+pub mod puck_type;
+pub mod status_change;
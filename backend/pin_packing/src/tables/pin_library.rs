@@ -1,4 +1,7 @@
-use super::pin_mount;
+use super::{
+    pin_mount,
+    status_change::{LibraryItem, LibraryStatus},
+};
 use async_graphql::{Enum, SimpleObject};
 use axum::async_trait;
 use sea_orm::{
@@ -19,6 +22,18 @@ pub enum PinStatus {
     Broken,
 }
 
+impl LibraryStatus for PinStatus {
+    const ITEM: LibraryItem = LibraryItem::Pin;
+
+    fn can_become(self, status: Self) -> bool {
+        use PinStatus::*;
+        matches!(
+            (self, status),
+            (Ready, Occupied | Broken) | (Occupied, Dirty | Broken) | (Dirty, Ready | Broken)
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
 #[sea_orm(table_name = "pin_library")]
 #[graphql(name = "LibraryPin", complex)]
@@ -1,17 +1,30 @@
-use super::{
-    crystal, pin_library,
-    puck_mount::{self, PUCK_SLOTS},
-};
-use async_graphql::SimpleObject;
+use super::{crystal, pin_library, puck_mount, puck_type::slots_of_puck_mount};
+use async_graphql::{Enum, SimpleObject};
 use axum::async_trait;
 use chrono::{DateTime, Utc};
 use sea_orm::{
     sea_query::{Index, IndexCreateStatement},
-    ActiveModelBehavior, ConnectionTrait, DbErr, DeriveEntityModel, DerivePrimaryKey,
-    DeriveRelation, EntityTrait, EnumIter, PrimaryKeyTrait, Related, RelationTrait,
+    ActiveModelBehavior, ConnectionTrait, DbErr, DeriveActiveEnum, DeriveEntityModel,
+    DerivePrimaryKey, DeriveRelation, EntityTrait, EnumIter, PrimaryKeyTrait, Related,
+    RelationTrait,
 };
 use uuid::Uuid;
 
+/// The reason a pin was unmounted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum UnmountReason {
+    /// Data was collected from the mounted crystal
+    #[sea_orm(string_value = "Collected")]
+    Collected,
+    /// Data could not be collected from the mounted crystal
+    #[sea_orm(string_value = "Failed")]
+    Failed,
+    /// The pin was lost
+    #[sea_orm(string_value = "Lost")]
+    Lost,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
 #[sea_orm(table_name = "pin_mount")]
 #[graphql(name = "MountedPin", complex)]
@@ -24,8 +37,17 @@ pub struct Model {
     pub barcode: String,
     pub timestamp: DateTime<Utc>,
     pub operator_id: String,
+    /// The time at which the pin was unmounted, if it has been
+    pub unmounted_at: Option<DateTime<Utc>>,
+    pub unmount_reason: Option<UnmountReason>,
 }
 
+/// The name of the index ensuring a crystal is held by at most one pin which has not been unmounted
+pub const UNIQUE_ACTIVE_CRYSTAL_MOUNT: &str = "unique-active-crystal-mount";
+
+/// The name of the index ensuring a puck slot holds at most one pin which has not been unmounted
+pub const UNIQUE_ACTIVE_PUCK_MOUNT_LOCATION: &str = "unique-active-puck-mount-location";
+
 pub fn unique_puck_mount_location() -> IndexCreateStatement {
     Index::create()
         .name("unique-puck-mount-location")
@@ -78,13 +100,18 @@ impl Related<puck_mount::Entity> for Entity {
 
 #[async_trait]
 impl ActiveModelBehavior for ActiveModel {
-    async fn before_save<C>(self, _db: &C, _insert: bool) -> Result<Self, DbErr>
+    async fn before_save<C>(self, db: &C, _insert: bool) -> Result<Self, DbErr>
     where
         C: ConnectionTrait,
     {
-        (*self.puck_location.as_ref() > 0 && *self.puck_location.as_ref() <= PUCK_SLOTS)
-            .then_some(())
-            .ok_or(DbErr::Custom("Invalid Cane Position".to_string()))?;
+        // Only a change of location needs the slots of the puck, unmounting leaves it unchanged
+        if self.puck_mount_id.is_set() || self.puck_location.is_set() {
+            let puck_location = *self.puck_location.as_ref();
+            (puck_location > 0
+                && puck_location <= slots_of_puck_mount(db, *self.puck_mount_id.as_ref()).await?)
+                .then_some(())
+                .ok_or(DbErr::Custom("Invalid Puck Position".to_string()))?;
+        }
 
         Ok(self)
     }
@@ -1,4 +1,7 @@
-use super::puck_mount;
+use super::{
+    puck_mount, puck_type,
+    status_change::{LibraryItem, LibraryStatus},
+};
 use async_graphql::{Enum, SimpleObject};
 use axum::async_trait;
 use sea_orm::{
@@ -19,6 +22,18 @@ pub enum PuckStatus {
     Broken,
 }
 
+impl LibraryStatus for PuckStatus {
+    const ITEM: LibraryItem = LibraryItem::Puck;
+
+    fn can_become(self, status: Self) -> bool {
+        use PuckStatus::*;
+        matches!(
+            (self, status),
+            (Ready, Filling | Broken) | (Filling, Away | Broken) | (Away, Ready | Broken)
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
 #[sea_orm(table_name = "puck_library")]
 #[graphql(name = "LibraryPuck", complex)]
@@ -26,6 +41,8 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub barcode: String,
     pub status: PuckStatus,
+    #[sea_orm(default_value = "Unipuck")]
+    pub puck_type: String,
 }
 
 #[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
@@ -40,5 +57,16 @@ impl Related<puck_mount::Entity> for Entity {
     }
 }
 
+// Not a variant of [`Relation`], which would add its foreign key to the table created by the
+// initial migration before puck types existed
+impl Related<puck_type::Entity> for Entity {
+    fn to() -> sea_orm::RelationDef {
+        Entity::belongs_to(puck_type::Entity)
+            .from(Column::PuckType)
+            .to(puck_type::Column::Name)
+            .into()
+    }
+}
+
 #[async_trait]
 impl ActiveModelBehavior for ActiveModel {}
@@ -1,7 +1,4 @@
-use super::{
-    cane_mount::{self, CANE_SLOTS},
-    pin_mount, puck_library,
-};
+use super::{cane_mount, cane_type::slots_of_cane_mount, pin_mount, puck_library};
 use async_graphql::SimpleObject;
 use axum::async_trait;
 use chrono::{DateTime, Utc};
@@ -12,8 +9,6 @@ use sea_orm::{
 };
 use uuid::Uuid;
 
-pub const PUCK_SLOTS: i16 = 16;
-
 #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
 #[sea_orm(table_name = "puck_mount")]
 #[graphql(name = "MountedPuck", complex)]
@@ -74,7 +69,7 @@ impl Related<cane_mount::Entity> for Entity {
 
 #[async_trait]
 impl ActiveModelBehavior for ActiveModel {
-    async fn before_save<C>(self, _db: &C, _insert: bool) -> Result<Self, DbErr>
+    async fn before_save<C>(self, db: &C, _insert: bool) -> Result<Self, DbErr>
     where
         C: ConnectionTrait,
     {
@@ -84,11 +79,16 @@ impl ActiveModelBehavior for ActiveModel {
                 "Both CaneMountId and CaneLocation must be non-null together".to_string(),
             ))?;
 
-        (self.cane_location.as_ref().is_none()
-            || *self.cane_location.as_ref() > Some(0)
-                && *self.cane_location.as_ref() <= Some(CANE_SLOTS))
-        .then_some(())
-        .ok_or(DbErr::Custom("Invalid Cane Position".to_string()))?;
+        // Only a change of location needs the slots of the cane
+        if let (Some(cane_mount_id), Some(cane_location), true) = (
+            *self.cane_mount_id.as_ref(),
+            *self.cane_location.as_ref(),
+            self.cane_mount_id.is_set() || self.cane_location.is_set(),
+        ) {
+            (cane_location > 0 && cane_location <= slots_of_cane_mount(db, cane_mount_id).await?)
+                .then_some(())
+                .ok_or(DbErr::Custom("Invalid Cane Position".to_string()))?;
+        }
 
         Ok(self)
     }
@@ -0,0 +1,56 @@
+use super::{puck_library, puck_mount};
+use async_graphql::SimpleObject;
+use axum::async_trait;
+use sea_orm::{
+    ActiveModelBehavior, ColumnTrait, ConnectionTrait, DbErr, DeriveEntityModel, DerivePrimaryKey,
+    DeriveRelation, EntityTrait, EnumIter, JoinType, PrimaryKeyTrait, QueryFilter, QuerySelect,
+    Related, RelationTrait,
+};
+use uuid::Uuid;
+
+/// The type of pucks registered without one, including those registered before puck types existed
+pub const DEFAULT_PUCK_TYPE: &str = "Unipuck";
+/// The number of slots in the default puck type
+pub const DEFAULT_PUCK_SLOTS: i16 = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
+#[sea_orm(table_name = "puck_type")]
+#[graphql(name = "PuckType")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub name: String,
+    /// The number of pin slots
+    pub slots: i16,
+}
+
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "puck_library::Entity")]
+    LibraryPuck,
+}
+
+impl Related<puck_library::Entity> for Entity {
+    fn to() -> sea_orm::RelationDef {
+        Relation::LibraryPuck.def()
+    }
+}
+
+#[async_trait]
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Finds the number of slots in a mounted puck
+pub async fn slots_of_puck_mount(
+    database: &impl ConnectionTrait,
+    puck_mount_id: Uuid,
+) -> Result<i16, DbErr> {
+    Ok(Entity::find()
+        .join(JoinType::InnerJoin, Relation::LibraryPuck.def())
+        .join(JoinType::InnerJoin, puck_library::Relation::PuckMount.def())
+        .filter(puck_mount::Column::Id.eq(puck_mount_id))
+        .one(database)
+        .await?
+        .ok_or(DbErr::RecordNotFound(format!(
+            "Could not find the type of puck mount {puck_mount_id}"
+        )))?
+        .slots)
+}
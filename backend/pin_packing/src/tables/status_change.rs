@@ -0,0 +1,174 @@
+use async_graphql::{Enum, SimpleObject};
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveEnum, ActiveModelBehavior, ActiveValue, DeriveActiveEnum, DeriveEntityModel,
+    DerivePrimaryKey, DeriveRelation, EntityTrait, EnumIter, PrimaryKeyTrait,
+};
+use uuid::Uuid;
+
+/// The kind of library item whose status changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, DeriveActiveEnum, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum LibraryItem {
+    #[sea_orm(string_value = "Pin")]
+    Pin,
+    #[sea_orm(string_value = "Puck")]
+    Puck,
+    #[sea_orm(string_value = "Cane")]
+    Cane,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
+#[sea_orm(table_name = "status_change")]
+#[graphql(name = "StatusChange")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub item: LibraryItem,
+    pub barcode: String,
+    pub previous_status: String,
+    pub status: String,
+    pub timestamp: DateTime<Utc>,
+    pub operator_id: String,
+}
+
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+#[async_trait]
+impl ActiveModelBehavior for ActiveModel {}
+
+/// The status of a library item, which may only change along the allowed transitions
+pub trait LibraryStatus: ActiveEnum<Value = String> + Copy {
+    /// The kind of library item with this status
+    const ITEM: LibraryItem;
+
+    /// Whether an item with this status may change to the other status
+    fn can_become(self, status: Self) -> bool;
+}
+
+/// Checks that a library item may change status, producing a record of the change
+pub fn transition<S: LibraryStatus>(
+    barcode: String,
+    previous_status: S,
+    status: S,
+    operator_id: String,
+) -> Result<ActiveModel, String> {
+    if !previous_status.can_become(status) {
+        return Err(format!(
+            "{:?} with barcode '{barcode}' cannot change from {} to {}",
+            S::ITEM,
+            previous_status.to_value(),
+            status.to_value()
+        ));
+    }
+    Ok(ActiveModel {
+        id: ActiveValue::Set(Uuid::now_v7()),
+        item: ActiveValue::Set(S::ITEM),
+        barcode: ActiveValue::Set(barcode),
+        previous_status: ActiveValue::Set(previous_status.to_value()),
+        status: ActiveValue::Set(status.to_value()),
+        timestamp: ActiveValue::Set(Utc::now()),
+        operator_id: ActiveValue::Set(operator_id),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{transition, LibraryItem, LibraryStatus};
+    use crate::tables::{
+        cane_library::CaneStatus, pin_library::PinStatus, puck_library::PuckStatus,
+    };
+    use sea_orm::{ActiveValue, Iterable};
+    use std::fmt::Debug;
+
+    /// Checks that exactly the listed transitions are allowed, amongst every pair of statuses
+    fn assert_transitions<S: LibraryStatus + Iterable + PartialEq + Debug>(allowed: &[(S, S)]) {
+        for from in S::iter() {
+            for to in S::iter() {
+                let expected = allowed.contains(&(from, to));
+                assert_eq!(
+                    expected,
+                    from.can_become(to),
+                    "{from:?} to {to:?} should be {}",
+                    if expected { "allowed" } else { "rejected" }
+                );
+                assert_eq!(
+                    expected,
+                    transition("A1".to_string(), from, to, "operator".to_string()).is_ok()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pin_transitions() {
+        use PinStatus::*;
+        assert_transitions(&[
+            (Ready, Occupied),
+            (Ready, Broken),
+            (Occupied, Dirty),
+            (Occupied, Broken),
+            (Dirty, Ready),
+            (Dirty, Broken),
+        ]);
+    }
+
+    #[test]
+    fn puck_transitions() {
+        use PuckStatus::*;
+        assert_transitions(&[
+            (Ready, Filling),
+            (Ready, Broken),
+            (Filling, Away),
+            (Filling, Broken),
+            (Away, Ready),
+            (Away, Broken),
+        ]);
+    }
+
+    #[test]
+    fn cane_transitions() {
+        use CaneStatus::*;
+        assert_transitions(&[
+            (Ready, Filling),
+            (Ready, Broken),
+            (Filling, Away),
+            (Filling, Broken),
+            (Away, Ready),
+            (Away, Broken),
+        ]);
+    }
+
+    #[test]
+    fn transitions_are_recorded() {
+        let status_change = transition(
+            "A1".to_string(),
+            PuckStatus::Filling,
+            PuckStatus::Away,
+            "operator".to_string(),
+        )
+        .unwrap();
+        assert_eq!(ActiveValue::Set(LibraryItem::Puck), status_change.item);
+        assert_eq!(
+            ActiveValue::Set("Filling".to_string()),
+            status_change.previous_status
+        );
+        assert_eq!(ActiveValue::Set("Away".to_string()), status_change.status);
+    }
+
+    #[test]
+    fn rejected_transitions_are_explained() {
+        assert_eq!(
+            "Pin with barcode 'A1' cannot change from Broken to Ready",
+            transition(
+                "A1".to_string(),
+                PinStatus::Broken,
+                PinStatus::Ready,
+                "operator".to_string()
+            )
+            .unwrap_err()
+        );
+    }
+}
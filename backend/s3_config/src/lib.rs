@@ -0,0 +1,77 @@
+#![forbid(unsafe_code)]
+#![warn(missing_docs)]
+#![doc = include_str!("../README.md")]
+
+use anyhow::Context;
+use aws_credential_types::{provider::SharedCredentialsProvider, Credentials};
+use aws_sdk_s3::{config::Region, Client};
+use clap::{ArgAction::SetTrue, Args};
+use derive_more::{Deref, FromStr, Into};
+use url::Url;
+
+/// The name of an S3 bucket.
+#[derive(Debug, Clone, Deref, FromStr, Into)]
+pub struct S3Bucket(String);
+
+/// Arguments for configuring the S3 Client.
+#[derive(Debug, Clone, Args)]
+pub struct S3ClientArgs {
+    /// The URL of the S3 endpoint to retrieve objects from.
+    #[arg(long, env)]
+    s3_endpoint_url: Option<Url>,
+    /// The ID of the access key used for S3 authorization.
+    #[arg(long, env)]
+    s3_access_key_id: Option<String>,
+    /// The secret access key used for S3 authorization.
+    #[arg(long, env)]
+    s3_secret_access_key: Option<String>,
+    /// Forces path style endpoint URIs for S3 queries.
+    #[arg(long, env, action = SetTrue)]
+    s3_force_path_style: bool,
+    /// The AWS region of the S3 bucket.
+    #[arg(long, env)]
+    s3_region: Option<String>,
+}
+
+/// A type which can be constructed from [`S3ClientArgs`].
+pub trait FromS3ClientArgs {
+    /// Creates a S3 [`Client`] with the supplied credentials using the supplied endpoint configuration.
+    fn from_s3_client_args(args: S3ClientArgs) -> Self;
+}
+
+impl FromS3ClientArgs for Client {
+    fn from_s3_client_args(args: S3ClientArgs) -> Self {
+        let credentials = Credentials::new(
+            args.s3_access_key_id.unwrap_or_default(),
+            args.s3_secret_access_key.unwrap_or_default(),
+            None,
+            None,
+            "chimp-chomp-cli",
+        );
+        let credentials_provider = SharedCredentialsProvider::new(credentials);
+        let mut config_builder = aws_sdk_s3::config::Builder::new();
+        config_builder.set_credentials_provider(Some(credentials_provider));
+        config_builder.set_endpoint_url(args.s3_endpoint_url.map(String::from));
+        config_builder.set_force_path_style(Some(args.s3_force_path_style));
+        config_builder.set_region(Some(Region::new(
+            args.s3_region.unwrap_or(String::from("undefined")),
+        )));
+        let config = config_builder.build();
+        Client::from_conf(config)
+    }
+}
+
+/// Creates the bucket, succeeding if it is already owned by the client.
+pub async fn setup_bucket(s3_client: &Client, bucket: S3Bucket) -> Result<(), anyhow::Error> {
+    match s3_client.create_bucket().bucket(bucket).send().await {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            let err = err.into_service_error();
+            if err.is_bucket_already_owned_by_you() {
+                Ok(())
+            } else {
+                Err(err).context("Failed to create bucket")
+            }
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use crate::{
+    image_file::ThumbnailSize,
+    tables::{image, prediction, prediction_crystal, prediction_drop, prediction_edit},
+};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use s3_config::S3Bucket;
+use sea_orm::{
+    prelude::Uuid, sea_query::Query, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, QuerySelect, TransactionTrait,
+};
+use std::collections::{BTreeMap, HashSet};
+use tracing::{info, warn};
+
+/// The age below which unreferenced objects are retained, as images are uploaded before being recorded
+const ORPHAN_GRACE_PERIOD: Duration = Duration::hours(1);
+
+/// Deletes predictions, along with any reviews of them, and their drops, crystals and edits
+pub async fn delete_predictions(
+    database: &impl ConnectionTrait,
+    prediction_ids: Vec<Uuid>,
+) -> Result<(), DbErr> {
+    let review_ids = prediction::Entity::find()
+        .select_only()
+        .column(prediction::Column::Id)
+        .filter(prediction::Column::ReviewOf.is_in(prediction_ids.clone()))
+        .into_tuple::<Uuid>()
+        .all(database)
+        .await?;
+    let prediction_ids = prediction_ids
+        .into_iter()
+        .chain(review_ids)
+        .collect::<Vec<_>>();
+
+    prediction_crystal::Entity::delete_many()
+        .filter(
+            prediction_crystal::Column::DropId.in_subquery(
+                Query::select()
+                    .column(prediction_drop::Column::Id)
+                    .from(prediction_drop::Entity)
+                    .and_where(prediction_drop::Column::PredictionId.is_in(prediction_ids.clone()))
+                    .to_owned(),
+            ),
+        )
+        .exec(database)
+        .await?;
+    prediction_drop::Entity::delete_many()
+        .filter(prediction_drop::Column::PredictionId.is_in(prediction_ids.clone()))
+        .exec(database)
+        .await?;
+    prediction_edit::Entity::delete_many()
+        .filter(prediction_edit::Column::PredictionId.is_in(prediction_ids.clone()))
+        .exec(database)
+        .await?;
+    prediction::Entity::delete_many()
+        .filter(prediction::Column::Id.is_in(prediction_ids))
+        .exec(database)
+        .await?;
+    Ok(())
+}
+
+/// Permanently deletes an image, its predictions and its stored objects, unless these are shared with another image
+pub async fn purge_image(
+    database: &DatabaseConnection,
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &S3Bucket,
+    image: &image::Model,
+) -> Result<(), anyhow::Error> {
+    let transaction = database.begin().await?;
+    let prediction_ids = prediction::Entity::find()
+        .select_only()
+        .column(prediction::Column::Id)
+        .filter(prediction::Column::ImageId.eq(image.id))
+        .into_tuple::<Uuid>()
+        .all(&transaction)
+        .await?;
+    delete_predictions(&transaction, prediction_ids).await?;
+    image::Entity::delete_by_id(image.id)
+        .exec(&transaction)
+        .await?;
+    transaction.commit().await?;
+
+    let thumbnail_keys = ThumbnailSize::ALL
+        .into_iter()
+        .map(|size| image.thumbnail_key(size));
+    delete_unrecorded_objects(
+        database,
+        s3_client,
+        bucket,
+        &image.object_key,
+        std::iter::once(image.object_key.clone()).chain(thumbnail_keys),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Deletes the objects stored under an image key unless an image records them, holding the key
+/// locked such that no such image is recorded meanwhile, returning whether they were deleted
+async fn delete_unrecorded_objects(
+    database: &DatabaseConnection,
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &S3Bucket,
+    object_key: &str,
+    keys: impl IntoIterator<Item = String>,
+) -> Result<bool, anyhow::Error> {
+    let transaction = database.begin().await?;
+    image::lock_object_key(&transaction, object_key).await?;
+    let recorded = image::Entity::find()
+        .filter(image::Column::ObjectKey.eq(object_key))
+        .one(&transaction)
+        .await?
+        .is_some();
+    if !recorded {
+        delete_objects(s3_client, bucket, keys).await?;
+    }
+    // The transaction changes nothing and is only held for the lock
+    transaction.rollback().await?;
+    Ok(!recorded)
+}
+
+/// Deletes stored objects by key
+async fn delete_objects(
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &S3Bucket,
+    keys: impl IntoIterator<Item = String>,
+) -> Result<(), anyhow::Error> {
+    for key in keys {
+        s3_client
+            .delete_object()
+            .bucket(bucket.clone())
+            .key(key)
+            .send()
+            .await?;
+    }
+    Ok(())
+}
+
+/// Purges images which were marked for deletion longer ago than the retention period
+async fn purge_expired_images(
+    database: &DatabaseConnection,
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &S3Bucket,
+    retention: Duration,
+) -> Result<(), anyhow::Error> {
+    let expired_images = image::Entity::find()
+        .filter(image::Column::DeletedAt.lt(Utc::now() - retention))
+        .all(database)
+        .await?;
+    for image in expired_images {
+        purge_image(database, s3_client, bucket, &image)
+            .await
+            .with_context(|| format!("Failed to purge image {}", image.id))?;
+        info!("Purged image {}", image.id);
+    }
+    Ok(())
+}
+
+/// The key of the image to which an object stored by this service belongs, or `None` if the
+/// object was not stored by this service and must be left alone
+///
+/// Images are stored under `sha256/<checksum>`, or `<plate>/<well>` and `<plate>/<well>/<id>` if
+/// stored before being addressed by their contents, with their thumbnails under
+/// `<image key>/thumbnails/<size>`.
+fn stored_image_key(key: &str) -> Option<&str> {
+    let image_key = match key.split_once("/thumbnails/") {
+        Some((image_key, size)) => size.parse::<u32>().ok().map(|_| image_key)?,
+        None => key,
+    };
+    let mut segments = image_key.split('/');
+    let recognised = match (
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+    ) {
+        (Some("sha256"), Some(checksum), None, None) => {
+            checksum.len() == 64 && checksum.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        (Some(plate), Some(well), id, None) => {
+            Uuid::parse_str(plate).is_ok()
+                && well.parse::<i16>().is_ok()
+                && id.map_or(true, |id| Uuid::parse_str(id).is_ok())
+        }
+        _ => false,
+    };
+    recognised.then_some(image_key)
+}
+
+/// Deletes objects stored by this service which do not belong to any recorded image
+async fn delete_orphaned_objects(
+    database: &DatabaseConnection,
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &S3Bucket,
+) -> Result<(), anyhow::Error> {
+    let cutoff = (Utc::now() - ORPHAN_GRACE_PERIOD).timestamp();
+
+    let mut continuation_token = None;
+    loop {
+        let page = s3_client
+            .list_objects_v2()
+            .bucket(bucket.clone())
+            .set_continuation_token(continuation_token)
+            .send()
+            .await?;
+        let candidates = page
+            .contents()
+            .unwrap_or_default()
+            .iter()
+            .filter(|object| {
+                object
+                    .last_modified()
+                    .is_some_and(|last_modified| last_modified.secs() <= cutoff)
+            })
+            .filter_map(|object| {
+                let key = object.key()?;
+                Some((key, stored_image_key(key)?))
+            })
+            .collect::<Vec<_>>();
+        let recorded_keys = if candidates.is_empty() {
+            HashSet::new()
+        } else {
+            image::Entity::find()
+                .select_only()
+                .column(image::Column::ObjectKey)
+                .filter(
+                    image::Column::ObjectKey
+                        .is_in(candidates.iter().map(|(_, image_key)| *image_key)),
+                )
+                .into_tuple::<String>()
+                .all(database)
+                .await?
+                .into_iter()
+                .collect()
+        };
+        let mut orphans = BTreeMap::<_, Vec<_>>::new();
+        for (key, image_key) in candidates {
+            if !recorded_keys.contains(image_key) {
+                orphans.entry(image_key).or_default().push(key.to_string());
+            }
+        }
+        for (image_key, keys) in orphans {
+            if delete_unrecorded_objects(database, s3_client, bucket, image_key, keys.clone())
+                .await?
+            {
+                info!("Deleted orphaned objects {keys:?}");
+            }
+        }
+        continuation_token = page.next_continuation_token().map(str::to_string);
+        if continuation_token.is_none() {
+            return Ok(());
+        }
+    }
+}
+
+/// Periodically purges expired images and reconciles stored objects against the database
+pub async fn sweep(
+    database: DatabaseConnection,
+    s3_client: aws_sdk_s3::Client,
+    bucket: S3Bucket,
+    interval: std::time::Duration,
+    retention: Duration,
+) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        if let Err(err) = purge_expired_images(&database, &s3_client, &bucket, retention).await {
+            warn!("Failed to purge deleted images: {err:#}");
+        }
+        if let Err(err) = delete_orphaned_objects(&database, &s3_client, &bucket).await {
+            warn!("Failed to delete orphaned objects: {err:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{delete_orphaned_objects, purge_image, stored_image_key};
+    use crate::{
+        image_file::ThumbnailSize,
+        mock_s3::{MockS3, BUCKET},
+        tables::image::{self, content_key},
+    };
+    use chrono::Utc;
+    use sea_orm::{
+        prelude::Uuid, DatabaseBackend, DatabaseConnection, MockDatabase, MockExecResult, Value,
+    };
+    use std::collections::BTreeMap;
+
+    const CHECKSUM: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+    const PLATE: &str = "0193ab6e-7f3c-7c8e-a3a6-2f1e4d0b9c11";
+
+    fn image(object_key: String) -> image::Model {
+        image::Model {
+            id: Uuid::now_v7(),
+            plate: Uuid::parse_str(PLATE).unwrap(),
+            well: 1,
+            inspection: 1,
+            imaged_at: Utc::now(),
+            object_key,
+            imager_id: None,
+            objective: None,
+            magnification: None,
+            illumination: None,
+            width: None,
+            height: None,
+            content_type: None,
+            checksum: Some(CHECKSUM.to_string()),
+            has_thumbnails: true,
+            timestamp: Utc::now(),
+            operator_id: "operator".to_string(),
+            deleted_at: Some(Utc::now()),
+        }
+    }
+
+    fn object_keys(keys: &[&str]) -> Vec<BTreeMap<&'static str, Value>> {
+        keys.iter()
+            .map(|key| BTreeMap::from([("object_key", Value::from(key.to_string()))]))
+            .collect()
+    }
+
+    fn exec_results(count: usize) -> Vec<MockExecResult> {
+        vec![
+            MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            };
+            count
+        ]
+    }
+
+    /// A database in which purging an image finds no predictions and deletes one row of each table
+    fn purge_database(remaining: Vec<image::Model>) -> DatabaseConnection {
+        MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results(vec![Vec::<BTreeMap<&str, Value>>::new(); 2])
+            .append_query_results([remaining])
+            .append_exec_results(exec_results(6))
+            .into_connection()
+    }
+
+    #[test]
+    fn stored_image_keys_are_recognised() {
+        let key = content_key(CHECKSUM);
+        assert_eq!(Some(key.as_str()), stored_image_key(&key));
+        assert_eq!(
+            Some(key.as_str()),
+            stored_image_key(&format!("{key}/thumbnails/128"))
+        );
+        let legacy_key = format!("{PLATE}/7");
+        assert_eq!(Some(legacy_key.as_str()), stored_image_key(&legacy_key));
+        let legacy_key = format!("{PLATE}/7/{}", Uuid::now_v7());
+        assert_eq!(
+            Some(legacy_key.as_str()),
+            stored_image_key(&format!("{legacy_key}/thumbnails/512"))
+        );
+    }
+
+    #[test]
+    fn foreign_keys_are_not_recognised() {
+        assert_eq!(None, stored_image_key("sha256/not-a-checksum"));
+        assert_eq!(
+            None,
+            stored_image_key(&format!("{}/extra", content_key(CHECKSUM)))
+        );
+        assert_eq!(None, stored_image_key(&format!("{PLATE}/A1")));
+        assert_eq!(None, stored_image_key(&format!("{PLATE}/7/photo.jpg")));
+        assert_eq!(
+            None,
+            stored_image_key(&format!("{}/thumbnails/large", content_key(CHECKSUM)))
+        );
+        assert_eq!(None, stored_image_key("crystals/0193ab6e"));
+        assert_eq!(None, stored_image_key("backups/database.sql"));
+    }
+
+    #[tokio::test]
+    async fn purge_deletes_unshared_objects() {
+        let image = image(content_key(CHECKSUM));
+        let database = purge_database(Vec::new());
+        let s3 = MockS3::new(&[]);
+
+        purge_image(&database, &s3.client, &BUCKET.parse().unwrap(), &image)
+            .await
+            .unwrap();
+
+        let expected = std::iter::once(image.object_key.clone())
+            .chain(ThumbnailSize::ALL.map(|size| image.thumbnail_key(size)))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, s3.deleted());
+    }
+
+    #[tokio::test]
+    async fn purge_retains_shared_objects() {
+        let purged = image(content_key(CHECKSUM));
+        let database = purge_database(vec![image(content_key(CHECKSUM))]);
+        let s3 = MockS3::new(&[]);
+
+        purge_image(&database, &s3.client, &BUCKET.parse().unwrap(), &purged)
+            .await
+            .unwrap();
+
+        assert!(s3.deleted().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sweep_deletes_only_old_unrecorded_stored_objects() {
+        let recorded = content_key(CHECKSUM);
+        let orphaned = content_key(&"0".repeat(64));
+        let recent = content_key(&"1".repeat(64));
+        let legacy = format!("{PLATE}/7");
+        let old = "2020-01-01T00:00:00.000Z";
+        let now = Utc::now().format("%Y-%m-%dT%H:%M:%S.000Z").to_string();
+        let s3 = MockS3::new(&[
+            (&recorded, old),
+            (&format!("{recorded}/thumbnails/128"), old),
+            (&orphaned, old),
+            (&format!("{orphaned}/thumbnails/128"), old),
+            (&recent, &now),
+            (&legacy, old),
+            ("crystals/0193ab6e", old),
+        ]);
+        let database = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([object_keys(&[&recorded, &legacy]), object_keys(&[])])
+            .append_exec_results(exec_results(1))
+            .into_connection();
+
+        delete_orphaned_objects(&database, &s3.client, &BUCKET.parse().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![orphaned.clone(), format!("{orphaned}/thumbnails/128")],
+            s3.deleted()
+        );
+    }
+
+    #[tokio::test]
+    async fn sweep_retains_objects_recorded_while_sweeping() {
+        let key = content_key(CHECKSUM);
+        let s3 = MockS3::new(&[(&key, "2020-01-01T00:00:00.000Z")]);
+        let database = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([object_keys(&[])])
+            .append_query_results([vec![image(key)]])
+            .append_exec_results(exec_results(1))
+            .into_connection();
+
+        delete_orphaned_objects(&database, &s3.client, &BUCKET.parse().unwrap())
+            .await
+            .unwrap();
+
+        assert!(s3.deleted().is_empty());
+    }
+}
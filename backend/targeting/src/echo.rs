@@ -0,0 +1,201 @@
+//! Pick lists for the Echo acoustic liquid handler.
+//!
+//! A pick list is a comma separated values file with a header row followed by one row per transfer:
+//!
+//! | Column                      | Contents                                                                          |
+//! |-----------------------------|-----------------------------------------------------------------------------------|
+//! | `Source Plate Name`         | The name of the compound plate                                                    |
+//! | `Source Well`               | The compound well, such as `A1`                                                   |
+//! | `Destination Plate Name`    | The name of the crystal plate                                                     |
+//! | `Destination Well`          | The crystal well, such as `B7`                                                    |
+//! | `Transfer Volume`           | The volume to dispense, in nanolitres                                             |
+//! | `Destination Well X Offset` | The horizontal offset of the insertion point from the well centre, in micrometres |
+//! | `Destination Well Y Offset` | The vertical offset of the insertion point from the well centre, in micrometres   |
+//!
+//! Offsets follow the orientation of the plate images, with positive X towards the right hand
+//! columns and positive Y towards the bottom rows.
+
+use crate::graphql::RootSchema;
+use async_graphql::{SimpleObject, Value, Variables};
+use axum::{
+    extract::State,
+    headers::{authorization::Bearer, Authorization},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json, TypedHeader,
+};
+use opa_client::{AuthorizationToken, Unauhtorized};
+use serde::{Deserialize, Serialize};
+
+/// The query executed to produce a pick list file
+const PICK_LIST_QUERY: &str = r#"
+query EchoPickList(
+    $plate: UUID!
+    $transfers: [EchoTransferInput!]!
+    $micronsPerPixel: Float!
+    $destinationPlateName: String
+    $columns: Int = 12
+) {
+    echoPickList(
+        plate: $plate
+        transfers: $transfers
+        micronsPerPixel: $micronsPerPixel
+        destinationPlateName: $destinationPlateName
+        columns: $columns
+    ) {
+        csv
+    }
+}
+"#;
+
+/// A single transfer of compound into a crystal drop
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct EchoTransfer {
+    #[serde(rename = "Source Plate Name")]
+    pub source_plate: String,
+    #[serde(rename = "Source Well")]
+    pub source_well: String,
+    #[serde(rename = "Destination Plate Name")]
+    pub destination_plate: String,
+    #[serde(rename = "Destination Well")]
+    pub destination_well: String,
+    /// The volume to dispense, in nanolitres
+    #[serde(rename = "Transfer Volume")]
+    pub transfer_volume: f64,
+    /// The horizontal offset of the insertion point from the well centre, in micrometres
+    #[serde(rename = "Destination Well X Offset")]
+    pub x_offset: i32,
+    /// The vertical offset of the insertion point from the well centre, in micrometres
+    #[serde(rename = "Destination Well Y Offset")]
+    pub y_offset: i32,
+}
+
+/// Writes transfers as an Echo pick list
+pub fn write_pick_list(transfers: &[EchoTransfer]) -> Result<String, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for transfer in transfers {
+        writer.serialize(transfer)?;
+    }
+    let contents = writer
+        .into_inner()
+        .map_err(|err| csv::Error::from(err.into_error()))?;
+    Ok(String::from_utf8(contents).expect("Serialized strings are valid UTF-8"))
+}
+
+/// Produces an Echo pick list file for a plate, from a JSON body containing the `echoPickList` query arguments
+pub async fn pick_list_file(
+    State(schema): State<RootSchema>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    Json(variables): Json<Variables>,
+) -> Response {
+    let authenticated = authorization.is_some();
+    let token = AuthorizationToken::new(
+        authorization.map(|authorization| authorization.token().to_string()),
+    );
+    let response = schema
+        .execute(
+            async_graphql::Request::new(PICK_LIST_QUERY)
+                .variables(variables)
+                .data(token),
+        )
+        .await;
+    if !response.errors.is_empty() {
+        // Refused requests are reported as unauthenticated, unless a token was presented
+        let status = if !response
+            .errors
+            .iter()
+            .any(|error| error.source::<Unauhtorized>().is_some())
+        {
+            StatusCode::BAD_REQUEST
+        } else if authenticated {
+            StatusCode::FORBIDDEN
+        } else {
+            StatusCode::UNAUTHORIZED
+        };
+        let errors = response
+            .errors
+            .into_iter()
+            .map(|error| error.message)
+            .collect::<Vec<_>>();
+        return (status, errors.join("\n")).into_response();
+    }
+    let csv = match response.data {
+        Value::Object(mut data) => match data.swap_remove("echoPickList") {
+            Some(Value::Object(mut pick_list)) => pick_list.swap_remove("csv"),
+            _ => None,
+        },
+        _ => None,
+    };
+    match csv {
+        Some(Value::String(csv)) => (
+            [
+                (header::CONTENT_TYPE, "text/csv"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"pick_list.csv\"",
+                ),
+            ],
+            csv,
+        )
+            .into_response(),
+        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_pick_list, EchoTransfer};
+
+    fn read_pick_list(contents: &str) -> Result<Vec<EchoTransfer>, csv::Error> {
+        csv::Reader::from_reader(contents.as_bytes())
+            .deserialize()
+            .collect()
+    }
+
+    #[test]
+    fn pick_list_round_trips() {
+        let transfers = vec![
+            EchoTransfer {
+                source_plate: "Compounds, batch 1".to_string(),
+                source_well: "A1".to_string(),
+                destination_plate: "0193ab6e-7f3c-7c8e-a3a6-2f1e4d0b9c11".to_string(),
+                destination_well: "B7".to_string(),
+                transfer_volume: 25.0,
+                x_offset: -120,
+                y_offset: 45,
+            },
+            EchoTransfer {
+                source_plate: "Compounds, batch 1".to_string(),
+                source_well: "P24".to_string(),
+                destination_plate: "0193ab6e-7f3c-7c8e-a3a6-2f1e4d0b9c11".to_string(),
+                destination_well: "H12".to_string(),
+                transfer_volume: 2.5,
+                x_offset: 0,
+                y_offset: -310,
+            },
+        ];
+        let pick_list = write_pick_list(&transfers).unwrap();
+        assert_eq!(
+            "Source Plate Name,Source Well,Destination Plate Name,Destination Well,Transfer Volume,Destination Well X Offset,Destination Well Y Offset",
+            pick_list.lines().next().unwrap()
+        );
+        assert_eq!(transfers, read_pick_list(&pick_list).unwrap());
+    }
+
+    #[test]
+    fn pick_list_reads_echo_file() {
+        let pick_list = "Source Plate Name,Source Well,Destination Plate Name,Destination Well,Transfer Volume,Destination Well X Offset,Destination Well Y Offset\r\nSource[1],C3,Destination[1],A2,12.5,100,-100\r\n";
+        assert_eq!(
+            vec![EchoTransfer {
+                source_plate: "Source[1]".to_string(),
+                source_well: "C3".to_string(),
+                destination_plate: "Destination[1]".to_string(),
+                destination_well: "A2".to_string(),
+                transfer_volume: 12.5,
+                x_offset: 100,
+                y_offset: -100,
+            }],
+            read_pick_list(pick_list).unwrap()
+        );
+    }
+}
@@ -1,6 +1,11 @@
 use crate::resolvers::{
     image::{ImageMutation, ImageQuery, ImageSubscription},
-    prediction::{PredicitonMutation, PredictionQuery},
+    ingestion::IngestionMutation,
+    pick_list::PickListQuery,
+    prediction::{PredicitonMutation, PredictionQuery, PredictionSubscription},
+    review::{ReviewMutation, ReviewQuery},
+    subgraph_extensions::SubgraphExtensionsQuery,
+    summary::SummaryQuery,
 };
 use async_graphql::{MergedObject, MergedSubscription, Schema, SchemaBuilder};
 
@@ -10,15 +15,28 @@ pub fn root_schema_builder() -> SchemaBuilder<RootQuery, RootMutation, RootSubsc
         RootMutation::default(),
         RootSubscription::default(),
     )
+    .enable_federation()
 }
 
 pub type RootSchema = Schema<RootQuery, RootMutation, RootSubscription>;
 
 #[derive(Debug, Clone, Default, MergedObject)]
-pub struct RootQuery(ImageQuery, PredictionQuery);
+pub struct RootQuery(
+    ImageQuery,
+    PickListQuery,
+    PredictionQuery,
+    ReviewQuery,
+    SubgraphExtensionsQuery,
+    SummaryQuery,
+);
 
 #[derive(Debug, Clone, Default, MergedObject)]
-pub struct RootMutation(ImageMutation, PredicitonMutation);
+pub struct RootMutation(
+    ImageMutation,
+    IngestionMutation,
+    PredicitonMutation,
+    ReviewMutation,
+);
 
 #[derive(Debug, Clone, Default, MergedSubscription)]
-pub struct RootSubscription(ImageSubscription);
+pub struct RootSubscription(ImageSubscription, PredictionSubscription);
@@ -0,0 +1,123 @@
+use async_graphql::Enum;
+use image::{io::Reader, DynamicImage, ImageError, ImageOutputFormat};
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+
+/// The JPEG quality with which thumbnails are encoded
+const THUMBNAIL_QUALITY: u8 = 85;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImageFileError {
+    #[error("File is not an image of a supported format")]
+    UnsupportedFormat,
+    #[error("File was uploaded as '{declared}' but contains '{detected}'")]
+    ContentTypeMismatch {
+        declared: String,
+        detected: &'static str,
+    },
+    #[error("Image could not be read: {0}")]
+    Unreadable(#[from] ImageError),
+}
+
+/// The properties of an image which can be read from the encoded file
+#[derive(Debug, Clone)]
+pub struct ImageFileProperties {
+    pub content_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    /// The hex encoded SHA-256 digest of the file
+    pub checksum: String,
+}
+
+impl ImageFileProperties {
+    /// Reads the properties of an image file, checking the contents match the declared content type if one is given
+    pub fn read(bytes: &[u8], declared_content_type: Option<&str>) -> Result<Self, ImageFileError> {
+        let reader = Reader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .expect("Reading from an in-memory buffer cannot fail");
+        let content_type = reader
+            .format()
+            .ok_or(ImageFileError::UnsupportedFormat)?
+            .to_mime_type();
+        match declared_content_type {
+            Some(declared)
+                if declared != content_type && declared != "application/octet-stream" =>
+            {
+                Err(ImageFileError::ContentTypeMismatch {
+                    declared: declared.to_string(),
+                    detected: content_type,
+                })
+            }
+            _ => Ok(()),
+        }?;
+        let (width, height) = reader.into_dimensions()?;
+
+        Ok(Self {
+            content_type,
+            width,
+            height,
+            checksum: hex::encode(Sha256::digest(bytes)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+pub enum ThumbnailSize {
+    /// At most 128 pixels along the longest edge
+    Small,
+    /// At most 256 pixels along the longest edge
+    Medium,
+    /// At most 512 pixels along the longest edge
+    Large,
+}
+
+impl ThumbnailSize {
+    pub const ALL: [ThumbnailSize; 3] = [Self::Small, Self::Medium, Self::Large];
+
+    pub fn max_dimension(self) -> u32 {
+        match self {
+            Self::Small => 128,
+            Self::Medium => 256,
+            Self::Large => 512,
+        }
+    }
+}
+
+/// A downscaled, JPEG encoded, copy of an image
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    pub size: ThumbnailSize,
+    pub contents: Vec<u8>,
+}
+
+impl Thumbnail {
+    pub const CONTENT_TYPE: &'static str = "image/jpeg";
+
+    /// Decodes an image file and produces a thumbnail of each [`ThumbnailSize`]
+    pub fn create_all(bytes: &[u8]) -> Result<Vec<Self>, ImageFileError> {
+        let image = DynamicImage::from(
+            Reader::new(Cursor::new(bytes))
+                .with_guessed_format()
+                .expect("Reading from an in-memory buffer cannot fail")
+                .decode()?
+                .into_rgb8(),
+        );
+        ThumbnailSize::ALL
+            .into_iter()
+            .map(|size| {
+                let max_dimension = size.max_dimension();
+                let mut contents = Vec::new();
+                let thumbnail = if image.width() > max_dimension || image.height() > max_dimension {
+                    image.thumbnail(max_dimension, max_dimension)
+                } else {
+                    image.clone()
+                };
+                thumbnail.write_to(
+                    &mut Cursor::new(&mut contents),
+                    ImageOutputFormat::Jpeg(THUMBNAIL_QUALITY),
+                )?;
+                Ok(Self { size, contents })
+            })
+            .collect()
+    }
+}
@@ -1,16 +1,26 @@
+mod deletion;
+mod echo;
 mod graphql;
+mod image_file;
+mod loaders;
 mod migrations;
+#[cfg(test)]
+mod mock_s3;
 mod resolvers;
 mod tables;
+mod well_name;
 
-use anyhow::Context;
-use aws_sdk_s3::Client;
-use axum::{routing::get, Router, Server};
-use derive_more::{Deref, FromStr, Into};
+use axum::{
+    routing::{get, post},
+    Router, Server,
+};
+pub use deletion::sweep;
 pub use graphql::root_schema_builder;
 use graphql::RootSchema;
 use graphql_endpoints::{GraphQLHandler, GraphQLSubscription, GraphiQLHandler};
+pub use loaders::add_loaders;
 use migrations::Migrator;
+pub use resolvers::image::DuplicatePolicy;
 use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbErr, TransactionError};
 use sea_orm_migration::MigratorTrait;
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
@@ -19,6 +29,7 @@ use url::Url;
 pub fn setup_router(schema: RootSchema) -> Router {
     const GRAPHQL_ENDPOINT: &str = "/";
     const SUBSCRIPTION_ENDPOINT: &str = "/ws";
+    const ECHO_PICK_LIST_ENDPOINT: &str = "/export/echo";
 
     Router::new()
         .route(
@@ -29,6 +40,10 @@ pub fn setup_router(schema: RootSchema) -> Router {
             ))
             .post(GraphQLHandler::new(schema.clone())),
         )
+        .route(
+            ECHO_PICK_LIST_ENDPOINT,
+            post(echo::pick_list_file).with_state(schema.clone()),
+        )
         .route_service(SUBSCRIPTION_ENDPOINT, GraphQLSubscription::new(schema))
 }
 
@@ -41,23 +56,6 @@ pub async fn setup_database(
     Ok(connection)
 }
 
-pub async fn setup_bucket(s3_client: &Client, bucket: S3Bucket) -> Result<(), anyhow::Error> {
-    match s3_client.create_bucket().bucket(bucket).send().await {
-        Ok(_) => Ok(()),
-        Err(err) => {
-            let err = err.into_service_error();
-            if err.is_bucket_already_owned_by_you() {
-                Ok(())
-            } else {
-                Err(err).context("Failed to create bucket")
-            }
-        }
-    }
-}
-
-#[derive(Debug, Clone, Deref, FromStr, Into)]
-pub struct S3Bucket(String);
-
 pub async fn serve(router: Router, port: u16) {
     let socket_addr: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port));
     println!("GraphiQL IDE: {}", socket_addr);
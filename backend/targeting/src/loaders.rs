@@ -0,0 +1,289 @@
+use crate::{
+    graphql::{RootMutation, RootQuery, RootSubscription},
+    resolvers::prediction::of_retained_image,
+    tables::{image, prediction, prediction_crystal, prediction_drop, prediction_edit},
+};
+use async_graphql::{
+    dataloader::{DataLoader, Loader},
+    SchemaBuilder,
+};
+use axum::async_trait;
+use graphql_loaders::{load_grouped, load_keyed, share_connection};
+use sea_orm::{
+    prelude::Uuid, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// Adds the [`DataLoader`]s used to batch lookups of related entities to the schema
+pub fn add_loaders(
+    schema_builder: SchemaBuilder<RootQuery, RootMutation, RootSubscription>,
+    database: &DatabaseConnection,
+) -> SchemaBuilder<RootQuery, RootMutation, RootSubscription> {
+    schema_builder
+        .data(DataLoader::new(
+            ImageLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            PredictionLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            ChecksumImagesLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            ImagePredictionsLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            ReviewsLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            DropsLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            CrystalsLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            EditsLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            WellImagesLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            WellPredictionsLoader(share_connection(database)),
+            tokio::spawn,
+        ))
+}
+
+/// Matches rows belonging to any of the given plate and well pairs
+fn in_wells(
+    plate_column: impl ColumnTrait,
+    well_column: impl ColumnTrait,
+    wells: &[(Uuid, i16)],
+) -> Condition {
+    wells
+        .iter()
+        .fold(Condition::any(), |condition, (plate, well)| {
+            condition.add(plate_column.eq(*plate).and(well_column.eq(*well)))
+        })
+}
+
+pub struct ImageLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for ImageLoader {
+    type Value = image::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_keyed(
+            &self.0,
+            image::Entity::find().filter(image::Column::Id.is_in(keys.iter().copied())),
+            |image| image.id,
+        )
+        .await
+    }
+}
+
+pub struct PredictionLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for PredictionLoader {
+    type Value = prediction::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_keyed(
+            &self.0,
+            prediction::Entity::find().filter(prediction::Column::Id.is_in(keys.iter().copied())),
+            |prediction| prediction.id,
+        )
+        .await
+    }
+}
+
+/// Loads the retained images with identical contents, keyed by checksum
+pub struct ChecksumImagesLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<String> for ChecksumImagesLoader {
+    type Value = Vec<image::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let mut images = load_grouped(
+            &self.0,
+            image::Entity::find()
+                .filter(image::Column::Checksum.is_in(keys.iter().cloned()))
+                .filter(image::Column::DeletedAt.is_null()),
+            |image| image.checksum.clone(),
+        )
+        .await?;
+        Ok(keys
+            .iter()
+            .filter_map(|key| Some((key.clone(), images.remove(&Some(key.clone()))?)))
+            .collect())
+    }
+}
+
+/// Loads the predictions of images, keyed by image
+pub struct ImagePredictionsLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for ImagePredictionsLoader {
+    type Value = Vec<prediction::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_grouped(
+            &self.0,
+            prediction::Entity::find()
+                .filter(prediction::Column::ImageId.is_in(keys.iter().copied())),
+            |prediction| prediction.image_id,
+        )
+        .await
+    }
+}
+
+/// Loads the reviews of predictions, keyed by the reviewed prediction
+pub struct ReviewsLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for ReviewsLoader {
+    type Value = Vec<prediction::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let mut reviews = load_grouped(
+            &self.0,
+            prediction::Entity::find()
+                .filter(prediction::Column::ReviewOf.is_in(keys.iter().copied())),
+            |prediction| prediction.review_of,
+        )
+        .await?;
+        Ok(keys
+            .iter()
+            .filter_map(|key| Some((*key, reviews.remove(&Some(*key))?)))
+            .collect())
+    }
+}
+
+/// Loads the drops of predictions in the order they were created, keyed by prediction
+pub struct DropsLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for DropsLoader {
+    type Value = Vec<prediction_drop::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_grouped(
+            &self.0,
+            prediction_drop::Entity::find()
+                .filter(prediction_drop::Column::PredictionId.is_in(keys.iter().copied()))
+                .order_by_asc(prediction_drop::Column::Id),
+            |drop| drop.prediction_id,
+        )
+        .await
+    }
+}
+
+/// Loads the crystals of drops, keyed by drop
+pub struct CrystalsLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for CrystalsLoader {
+    type Value = Vec<prediction_crystal::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_grouped(
+            &self.0,
+            prediction_crystal::Entity::find()
+                .filter(prediction_crystal::Column::DropId.is_in(keys.iter().copied())),
+            |crystal| crystal.drop_id,
+        )
+        .await
+    }
+}
+
+/// Loads the edits made to predictions in the order they were made, keyed by prediction
+pub struct EditsLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<Uuid> for EditsLoader {
+    type Value = Vec<prediction_edit::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        load_grouped(
+            &self.0,
+            prediction_edit::Entity::find()
+                .filter(prediction_edit::Column::PredictionId.is_in(keys.iter().copied()))
+                .order_by_asc(prediction_edit::Column::Timestamp),
+            |edit| edit.prediction_id,
+        )
+        .await
+    }
+}
+
+/// Loads the retained images of wells in the order they were inspected, keyed by plate and well
+pub struct WellImagesLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<(Uuid, i16)> for WellImagesLoader {
+    type Value = Vec<image::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[(Uuid, i16)],
+    ) -> Result<HashMap<(Uuid, i16), Self::Value>, Self::Error> {
+        load_grouped(
+            &self.0,
+            image::Entity::find()
+                .filter(in_wells(image::Column::Plate, image::Column::Well, keys))
+                .filter(image::Column::DeletedAt.is_null())
+                .order_by_asc(image::Column::Inspection)
+                .order_by_asc(image::Column::ImagedAt),
+            |image| (image.plate, image.well),
+        )
+        .await
+    }
+}
+
+/// Loads the predictions of retained images of wells in the order they were made, keyed by plate and well
+pub struct WellPredictionsLoader(DatabaseConnection);
+
+#[async_trait]
+impl Loader<(Uuid, i16)> for WellPredictionsLoader {
+    type Value = Vec<prediction::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[(Uuid, i16)],
+    ) -> Result<HashMap<(Uuid, i16), Self::Value>, Self::Error> {
+        load_grouped(
+            &self.0,
+            prediction::Entity::find()
+                .filter(in_wells(
+                    prediction::Column::Plate,
+                    prediction::Column::Well,
+                    keys,
+                ))
+                .filter(of_retained_image())
+                .order_by_asc(prediction::Column::Timestamp),
+            |prediction| (prediction.plate, prediction.well),
+        )
+        .await
+    }
+}
@@ -1,15 +1,32 @@
 use axum::async_trait;
-use sea_orm::{DbErr, DeriveMigrationName, Schema};
-use sea_orm_migration::{MigrationTrait, MigratorTrait, SchemaManager};
+use sea_orm::{
+    sea_query::{ColumnDef, Index, Table},
+    ActiveEnum, ConnectionTrait, DbErr, DeriveMigrationName, Schema,
+};
+use sea_orm_migration::{MigrationName, MigrationTrait, MigratorTrait, SchemaManager};
 
-use crate::tables::{image, prediction, prediction_crystal, prediction_drop};
+use crate::tables::{
+    image::{self, UNIQUE_WELL_INSPECTION},
+    prediction::{self, ReviewStatus},
+    prediction_crystal, prediction_drop, prediction_edit,
+};
 
 pub struct Migrator;
 
 #[async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(Initial)]
+        vec![
+            Box::new(Initial),
+            Box::new(PredictionModelVersion),
+            Box::new(PredictionReview),
+            Box::new(ImageIdentity),
+            Box::new(ImageMetadata),
+            Box::new(ImageThumbnails),
+            Box::new(ImageDeletion),
+            Box::new(ImageChecksumIndex),
+            Box::new(ImageInspectionIndex),
+        ]
     }
 }
 
@@ -41,3 +58,275 @@ impl MigrationTrait for Initial {
         Ok(())
     }
 }
+
+struct PredictionModelVersion;
+
+impl MigrationName for PredictionModelVersion {
+    fn name(&self) -> &str {
+        "prediction_model_version"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for PredictionModelVersion {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(prediction::Entity)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(prediction::Column::ModelVersion).string(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+struct PredictionReview;
+
+impl MigrationName for PredictionReview {
+    fn name(&self) -> &str {
+        "prediction_review"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for PredictionReview {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+        let schema = Schema::new(backend);
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(prediction::Entity)
+                    .add_column_if_not_exists(ColumnDef::new(prediction::Column::ReviewOf).uuid())
+                    .add_column_if_not_exists(
+                        ColumnDef::new(prediction::Column::Status)
+                            .string()
+                            .not_null()
+                            .default(ReviewStatus::Unreviewed.to_value()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                schema
+                    .create_table_from_entity(prediction_edit::Entity)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+struct ImageIdentity;
+
+impl MigrationName for ImageIdentity {
+    fn name(&self) -> &str {
+        "image_identity"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for ImageIdentity {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.has_column("image", "id").await? {
+            return Ok(());
+        }
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                ALTER TABLE prediction DROP CONSTRAINT "fk-prediction-plate-well";
+
+                ALTER TABLE image
+                    ADD COLUMN id uuid NOT NULL DEFAULT gen_random_uuid(),
+                    ADD COLUMN inspection integer NOT NULL DEFAULT 1,
+                    ADD COLUMN imaged_at timestamp with time zone,
+                    ADD COLUMN object_key varchar;
+                UPDATE image SET imaged_at = "timestamp", object_key = plate || '/' || well;
+                ALTER TABLE image
+                    ALTER COLUMN id DROP DEFAULT,
+                    ALTER COLUMN inspection DROP DEFAULT,
+                    ALTER COLUMN imaged_at SET NOT NULL,
+                    ALTER COLUMN object_key SET NOT NULL,
+                    DROP CONSTRAINT "pk-image",
+                    ADD PRIMARY KEY (id);
+
+                ALTER TABLE prediction ADD COLUMN image_id uuid;
+                UPDATE prediction SET image_id = image.id
+                    FROM image
+                    WHERE image.plate = prediction.plate AND image.well = prediction.well;
+                ALTER TABLE prediction
+                    ALTER COLUMN image_id SET NOT NULL,
+                    ADD CONSTRAINT "fk-prediction-image_id"
+                        FOREIGN KEY (image_id) REFERENCES image (id);
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+struct ImageMetadata;
+
+impl MigrationName for ImageMetadata {
+    fn name(&self) -> &str {
+        "image_metadata"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for ImageMetadata {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(image::Entity)
+                    .add_column_if_not_exists(ColumnDef::new(image::Column::ImagerId).string())
+                    .add_column_if_not_exists(ColumnDef::new(image::Column::Objective).string())
+                    .add_column_if_not_exists(ColumnDef::new(image::Column::Magnification).double())
+                    .add_column_if_not_exists(ColumnDef::new(image::Column::Illumination).string())
+                    .add_column_if_not_exists(ColumnDef::new(image::Column::Width).integer())
+                    .add_column_if_not_exists(ColumnDef::new(image::Column::Height).integer())
+                    .add_column_if_not_exists(ColumnDef::new(image::Column::ContentType).string())
+                    .add_column_if_not_exists(ColumnDef::new(image::Column::Checksum).string())
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+struct ImageThumbnails;
+
+impl MigrationName for ImageThumbnails {
+    fn name(&self) -> &str {
+        "image_thumbnails"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for ImageThumbnails {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(image::Entity)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(image::Column::HasThumbnails)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+struct ImageDeletion;
+
+impl MigrationName for ImageDeletion {
+    fn name(&self) -> &str {
+        "image_deletion"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for ImageDeletion {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(image::Entity)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(image::Column::DeletedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+struct ImageChecksumIndex;
+
+impl MigrationName for ImageChecksumIndex {
+    fn name(&self) -> &str {
+        "image_checksum_index"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for ImageChecksumIndex {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("image_checksum")
+                    .table(image::Entity)
+                    .col(image::Column::Checksum)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+struct ImageInspectionIndex;
+
+impl MigrationName for ImageInspectionIndex {
+    fn name(&self) -> &str {
+        "image_inspection_index"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for ImageInspectionIndex {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Wells with several images from one inspection are renumbered in the order they were imaged
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                UPDATE image SET inspection = numbered.inspection
+                FROM (
+                    SELECT id, ROW_NUMBER() OVER (
+                        PARTITION BY plate, well ORDER BY inspection, imaged_at, id
+                    ) AS inspection
+                    FROM image
+                    WHERE deleted_at IS NULL AND (plate, well) IN (
+                        SELECT plate, well FROM image
+                        WHERE deleted_at IS NULL
+                        GROUP BY plate, well, inspection
+                        HAVING COUNT(*) > 1
+                    )
+                ) AS numbered
+                WHERE image.id = numbered.id;
+                "#,
+            )
+            .await?;
+        // Images marked for deletion give up their inspection, such that they may be replaced
+        manager
+            .get_connection()
+            .execute_unprepared(&format!(
+                r#"
+                CREATE UNIQUE INDEX IF NOT EXISTS "{UNIQUE_WELL_INSPECTION}"
+                    ON image (plate, well, inspection)
+                    WHERE deleted_at IS NULL;
+                "#
+            ))
+            .await?;
+
+        Ok(())
+    }
+}
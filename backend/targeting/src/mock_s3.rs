@@ -0,0 +1,95 @@
+//! An S3 client backed by an in-memory connection, for testing code which stores objects.
+
+use aws_credential_types::Credentials;
+use aws_sdk_s3::config::Region;
+use aws_smithy_client::{
+    erase::DynConnector, http_connector::HttpConnector, test_connection::infallible_connection_fn,
+};
+use std::{
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+/// The bucket which all requests are made to
+pub const BUCKET: &str = "images";
+
+/// An S3 client which lists a fixed set of objects and records the objects it is asked to change
+pub struct MockS3 {
+    pub client: aws_sdk_s3::Client,
+    requests: Arc<Mutex<Vec<(http::Method, String)>>>,
+}
+
+impl MockS3 {
+    /// Creates a client which lists the given keys, each with its last modified time
+    pub fn new(listed: &[(&str, &str)]) -> Self {
+        let mut listing = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Name>{BUCKET}</Name><IsTruncated>false</IsTruncated>"#
+        );
+        for (key, last_modified) in listed {
+            write!(
+                listing,
+                "<Contents><Key>{key}</Key><LastModified>{last_modified}</LastModified></Contents>"
+            )
+            .unwrap();
+        }
+        listing.push_str("</ListBucketResult>");
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let connector = infallible_connection_fn({
+            let requests = requests.clone();
+            move |request| {
+                let key = request
+                    .uri()
+                    .path()
+                    .trim_start_matches(&format!("/{BUCKET}"))
+                    .trim_start_matches('/');
+                if request.method() == http::Method::GET {
+                    return http::Response::builder()
+                        .status(200)
+                        .body(listing.clone())
+                        .unwrap();
+                }
+                requests
+                    .lock()
+                    .unwrap()
+                    .push((request.method().clone(), key.to_string()));
+                http::Response::builder()
+                    .status(200)
+                    .body(String::new())
+                    .unwrap()
+            }
+        });
+        let config = aws_sdk_s3::Config::builder()
+            .credentials_provider(Credentials::new("access", "secret", None, None, "test"))
+            .region(Region::new("undefined"))
+            .endpoint_url("http://s3.test")
+            .force_path_style(true)
+            .http_connector(HttpConnector::Prebuilt(Some(DynConnector::new(connector))))
+            .build();
+        Self {
+            client: aws_sdk_s3::Client::from_conf(config),
+            requests,
+        }
+    }
+
+    /// The keys of the objects which were requested with the method, in the order requested
+    fn requested(&self, method: http::Method) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(requested, _)| *requested == method)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+
+    /// The keys of the objects which were deleted, in the order they were deleted
+    pub fn deleted(&self) -> Vec<String> {
+        self.requested(http::Method::DELETE)
+    }
+
+    /// The keys of the objects which were uploaded, in the order they were uploaded
+    pub fn uploaded(&self) -> Vec<String> {
+        self.requested(http::Method::PUT)
+    }
+}
@@ -1,36 +1,68 @@
 use crate::{
-    resolvers::Well,
-    tables::{image, prediction},
-    S3Bucket,
+    deletion::purge_image,
+    image_file::{ImageFileProperties, Thumbnail, ThumbnailSize},
+    loaders::{ChecksumImagesLoader, ImagePredictionsLoader},
+    resolvers::{violates_constraint, TimestampRange, Well},
+    tables::{
+        image::{self, Illumination, UNIQUE_WELL_INSPECTION},
+        prediction,
+    },
 };
-use async_graphql::{ComplexObject, Context, Object, Subscription, Upload};
-use aws_sdk_s3::presigning::PresigningConfig;
-use chrono::Utc;
+use async_graphql::{
+    dataloader::DataLoader, ComplexObject, Context, Enum, InputObject, Object, Subscription, Upload,
+};
+use aws_sdk_s3::{error::SdkError, presigning::PresigningConfig};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
 use graphql_event_broker::EventBroker;
 use opa_client::subject_authorization;
+use s3_config::S3Bucket;
 use sea_orm::{
-    prelude::Uuid, ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter,
-    QueryTrait,
+    prelude::Uuid, sea_query::Query, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection,
+    DbErr, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, TransactionTrait,
+};
+use std::{io::Read, time::Duration};
+use the_paginator::{
+    graphql::{CursorInput, ModelConnection},
+    KeyOrder,
 };
-use std::time::Duration;
 use tokio_stream::Stream;
+use tracing::warn;
 use url::Url;
 
+async fn presigned_url(ctx: &Context<'_>, key: String) -> async_graphql::Result<Url> {
+    let s3_client = ctx.data::<aws_sdk_s3::Client>()?;
+    let bucket = ctx.data::<S3Bucket>()?;
+    let object_uri = s3_client
+        .get_object()
+        .bucket(bucket.clone())
+        .key(key)
+        .presigned(PresigningConfig::expires_in(Duration::from_secs(10 * 60))?)
+        .await?
+        .uri()
+        .clone();
+    let object_url = Url::parse(&object_uri.to_string())?;
+    Ok(object_url)
+}
+
 #[ComplexObject]
 impl image::Model {
     async fn download_url(&self, ctx: &Context<'_>) -> async_graphql::Result<Url> {
-        let s3_client = ctx.data::<aws_sdk_s3::Client>()?;
-        let bucket = ctx.data::<S3Bucket>()?;
-        let object_uri = s3_client
-            .get_object()
-            .bucket(bucket.clone())
-            .key(self.object_key())
-            .presigned(PresigningConfig::expires_in(Duration::from_secs(10 * 60))?)
-            .await?
-            .uri()
-            .clone();
-        let object_url = Url::parse(&object_uri.to_string())?;
-        Ok(object_url)
+        presigned_url(ctx, self.object_key.clone()).await
+    }
+
+    /// A URL from which a downscaled JPEG copy of the image can be retrieved, or the original where none exists
+    async fn thumbnail_url(
+        &self,
+        ctx: &Context<'_>,
+        size: ThumbnailSize,
+    ) -> async_graphql::Result<Url> {
+        let key = if self.has_thumbnails {
+            self.thumbnail_key(size)
+        } else {
+            self.object_key.clone()
+        };
+        presigned_url(ctx, key).await
     }
 
     async fn predictions(
@@ -38,8 +70,26 @@ impl image::Model {
         ctx: &Context<'_>,
     ) -> async_graphql::Result<Vec<prediction::Model>> {
         subject_authorization!("xchemlab.targeting.read_prediction", ctx).await?;
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self.find_related(prediction::Entity).all(database).await?)
+        Ok(ctx
+            .data::<DataLoader<ImagePredictionsLoader>>()?
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Other retained images with identical contents, which may indicate a mislabelled upload
+    async fn duplicates(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<image::Model>> {
+        let Some(checksum) = &self.checksum else {
+            return Ok(Vec::new());
+        };
+        Ok(ctx
+            .data::<DataLoader<ChecksumImagesLoader>>()?
+            .load_one(checksum.clone())
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|image| image.id != self.id)
+            .collect())
     }
 }
 
@@ -48,65 +98,405 @@ pub struct ImageQuery;
 
 #[Object]
 impl ImageQuery {
+    #[allow(clippy::too_many_arguments)]
     async fn images(
         &self,
         ctx: &Context<'_>,
+        cursor: CursorInput,
         plate: Option<Uuid>,
         well: Option<i16>,
-    ) -> async_graphql::Result<Vec<image::Model>> {
+        operator_id: Option<String>,
+        inspection: Option<i32>,
+        #[graphql(desc = "Only include images taken within this range")] created: Option<
+            TimestampRange,
+        >,
+        #[graphql(desc = "Only include images which do, or do not, have a prediction")]
+        has_prediction: Option<bool>,
+        #[graphql(desc = "Only include images whose file has this hex encoded SHA-256 digest")]
+        checksum: Option<String>,
+        #[graphql(default, desc = "Include images which have been marked for deletion")]
+        include_deleted: bool,
+        #[graphql(default)] order: KeyOrder,
+    ) -> async_graphql::Result<ModelConnection<image::Model>> {
         subject_authorization!("xchemlab.targeting.read_image", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
-        Ok(image::Entity::find()
-            .apply_if(plate, |query, plate| {
-                query.filter(image::Column::Plate.eq(plate))
-            })
-            .apply_if(well, |query, well| {
-                query.filter(image::Column::Well.eq(well))
-            })
+        let predicted_images = Query::select()
+            .column(prediction::Column::ImageId)
+            .from(prediction::Entity)
+            .to_owned();
+        Ok(cursor
+            .try_into_query_cursor::<image::Entity>()?
+            .filter(
+                Condition::all()
+                    .add_option(plate.map(|plate| image::Column::Plate.eq(plate)))
+                    .add_option(well.map(|well| image::Column::Well.eq(well)))
+                    .add_option(
+                        operator_id.map(|operator_id| image::Column::OperatorId.eq(operator_id)),
+                    )
+                    .add_option(
+                        inspection.map(|inspection| image::Column::Inspection.eq(inspection)),
+                    )
+                    .add_option(
+                        created.map(|created| created.into_condition(image::Column::ImagedAt)),
+                    )
+                    .add_option(
+                        checksum.map(|checksum| {
+                            image::Column::Checksum.eq(checksum.to_ascii_lowercase())
+                        }),
+                    )
+                    .add_option(has_prediction.map(|has_prediction| {
+                        if has_prediction {
+                            image::Column::Id.in_subquery(predicted_images)
+                        } else {
+                            image::Column::Id.not_in_subquery(predicted_images)
+                        }
+                    }))
+                    .add_option((!include_deleted).then(|| image::Column::DeletedAt.is_null())),
+            )
+            .key_order(order)
             .all(database)
-            .await?)
+            .await?
+            .try_into_connection()?)
+    }
+
+    async fn image(&self, ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<image::Model> {
+        subject_authorization!("xchemlab.targeting.read_image", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        Ok(image::Entity::find_by_id(id)
+            .one(database)
+            .await?
+            .ok_or(format!("Could not find image {id}"))?)
+    }
+
+    /// The image from the most recent inspection of a well
+    async fn latest_image(
+        &self,
+        ctx: &Context<'_>,
+        well: Well,
+    ) -> async_graphql::Result<Option<image::Model>> {
+        subject_authorization!("xchemlab.targeting.read_image", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        Ok(latest_image(database, &well).await?)
     }
 }
 
+async fn latest_image(
+    database: &impl ConnectionTrait,
+    well: &Well,
+) -> Result<Option<image::Model>, DbErr> {
+    image::Entity::find()
+        .filter(image::Column::Plate.eq(well.plate))
+        .filter(image::Column::Well.eq(well.well))
+        .filter(image::Column::DeletedAt.is_null())
+        .order_by_desc(image::Column::Inspection)
+        .order_by_desc(image::Column::ImagedAt)
+        .one(database)
+        .await
+}
+
+/// Uploads objects, each given by its key, content type and contents
+async fn put_objects(
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &S3Bucket,
+    objects: &[(String, &str, Vec<u8>)],
+) -> async_graphql::Result<()> {
+    for (key, content_type, contents) in objects {
+        s3_client
+            .put_object()
+            .key(key.clone())
+            .bucket(bucket.clone())
+            .content_type(*content_type)
+            .body(contents.clone().into())
+            .send()
+            .await?;
+    }
+    Ok(())
+}
+
+/// Whether an object is stored under the key
+async fn object_exists(
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &S3Bucket,
+    key: &str,
+) -> async_graphql::Result<bool> {
+    match s3_client
+        .head_object()
+        .bucket(bucket.clone())
+        .key(key)
+        .send()
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(SdkError::ServiceError(error)) if error.err().is_not_found() => Ok(false),
+        Err(error) => Err(error)?,
+    }
+}
+
+/// The number of times an image is recorded before giving up, should other images of the well
+/// concurrently take the inspection following the latest
+const INSPECTION_NUMBER_ATTEMPTS: usize = 5;
+
 static IMAGE_CREATION_BROKER: EventBroker<image::Model> = EventBroker::new();
 
+/// How uploads of files identical to those of existing images are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum, ValueEnum)]
+pub enum DuplicatePolicy {
+    /// Store the image without checking for duplicates
+    Allow,
+    /// Store the image, logging the images it duplicates
+    #[default]
+    Warn,
+    /// Refuse to store the image
+    Reject,
+}
+
+#[derive(Debug, Clone, Default, InputObject)]
+pub struct ImagingConditions {
+    /// The identifier of the imager which took the image
+    pub imager_id: Option<String>,
+    /// The microscope objective used to take the image, such as "10x/0.3"
+    pub objective: Option<String>,
+    /// The total magnification at which the image was taken, as a multiple of actual size
+    pub magnification: Option<f64>,
+    /// The illumination under which the image was taken
+    pub illumination: Option<Illumination>,
+}
+
+pub struct NewImage {
+    pub well: Well,
+    pub contents: Vec<u8>,
+    pub declared_content_type: Option<String>,
+    pub imaged_at: Option<DateTime<Utc>>,
+    pub inspection: Option<i32>,
+    pub conditions: ImagingConditions,
+    pub duplicate_policy: DuplicatePolicy,
+}
+
+impl NewImage {
+    /// Stores the image file and derived thumbnails, records the image and publishes its creation
+    pub async fn store(
+        self,
+        database: &DatabaseConnection,
+        s3_client: &aws_sdk_s3::Client,
+        bucket: &S3Bucket,
+        operator_id: String,
+    ) -> async_graphql::Result<image::Model> {
+        let id = Uuid::now_v7();
+        let timestamp = Utc::now();
+
+        let properties =
+            ImageFileProperties::read(&self.contents, self.declared_content_type.as_deref())?;
+        if self.duplicate_policy != DuplicatePolicy::Allow {
+            let duplicate = image::Entity::find()
+                .filter(image::Column::Checksum.eq(properties.checksum.as_str()))
+                .filter(image::Column::DeletedAt.is_null())
+                .one(database)
+                .await?;
+            if let Some(duplicate) = duplicate {
+                let message = format!(
+                    "Image of well {} duplicates image {} of well {}/{}",
+                    self.well.to_string(),
+                    duplicate.id,
+                    duplicate.plate,
+                    duplicate.well
+                );
+                if self.duplicate_policy == DuplicatePolicy::Reject {
+                    return Err(message.into());
+                }
+                warn!("{message}");
+            }
+        }
+
+        let (contents, thumbnails) = tokio::task::spawn_blocking(move || {
+            let thumbnails = Thumbnail::create_all(&self.contents);
+            (self.contents, thumbnails)
+        })
+        .await?;
+        let thumbnails = thumbnails?;
+
+        let object_key = image::content_key(&properties.checksum);
+        let objects = std::iter::once((object_key.clone(), properties.content_type, contents))
+            .chain(thumbnails.into_iter().map(|thumbnail| {
+                (
+                    image::thumbnail_key(&object_key, thumbnail.size),
+                    Thumbnail::CONTENT_TYPE,
+                    thumbnail.contents,
+                )
+            }))
+            .collect::<Vec<_>>();
+        // Objects are uploaded even if already stored, as the images storing them may be purged concurrently
+        put_objects(s3_client, bucket, &objects).await?;
+
+        let model = image::ActiveModel {
+            id: sea_orm::ActiveValue::Set(id),
+            plate: sea_orm::ActiveValue::Set(self.well.plate),
+            well: sea_orm::ActiveValue::Set(self.well.well),
+            inspection: sea_orm::ActiveValue::NotSet,
+            imaged_at: sea_orm::ActiveValue::Set(self.imaged_at.unwrap_or(timestamp)),
+            object_key: sea_orm::ActiveValue::Set(object_key.clone()),
+            imager_id: sea_orm::ActiveValue::Set(self.conditions.imager_id),
+            objective: sea_orm::ActiveValue::Set(self.conditions.objective),
+            magnification: sea_orm::ActiveValue::Set(self.conditions.magnification),
+            illumination: sea_orm::ActiveValue::Set(self.conditions.illumination),
+            width: sea_orm::ActiveValue::Set(Some(properties.width.try_into()?)),
+            height: sea_orm::ActiveValue::Set(Some(properties.height.try_into()?)),
+            content_type: sea_orm::ActiveValue::Set(Some(properties.content_type.to_string())),
+            checksum: sea_orm::ActiveValue::Set(Some(properties.checksum)),
+            has_thumbnails: sea_orm::ActiveValue::Set(true),
+            timestamp: sea_orm::ActiveValue::Set(timestamp),
+            operator_id: sea_orm::ActiveValue::Set(operator_id),
+            deleted_at: sea_orm::ActiveValue::Set(None),
+        };
+        // The key is locked whilst the image is recorded, such that its objects are not deleted meanwhile
+        let mut attempts = 1;
+        let inserted = loop {
+            let transaction = database.begin().await?;
+            image::lock_object_key(&transaction, &object_key).await?;
+            let inspection = match self.inspection {
+                Some(inspection) => inspection,
+                None => latest_image(&transaction, &self.well)
+                    .await?
+                    .map_or(1, |latest| latest.inspection + 1),
+            };
+            let mut model = model.clone();
+            model.inspection = sea_orm::ActiveValue::Set(inspection);
+            match image::Entity::insert(model)
+                .exec_with_returning(&transaction)
+                .await
+            {
+                Ok(inserted) => {
+                    transaction.commit().await?;
+                    break inserted;
+                }
+                Err(error) if violates_constraint(&error, UNIQUE_WELL_INSPECTION) => {
+                    if self.inspection.is_some() {
+                        Err(format!(
+                            "Well {} already has an image from inspection {inspection}",
+                            self.well.to_string()
+                        ))?;
+                    }
+                    // Another image of the well concurrently took the following inspection
+                    if attempts == INSPECTION_NUMBER_ATTEMPTS {
+                        Err(error)?;
+                    }
+                    attempts += 1;
+                }
+                Err(error) => Err(error)?,
+            }
+        };
+
+        // Objects deleted between being uploaded and the image being recorded are uploaded again
+        let mut missing = Vec::new();
+        for object in &objects {
+            if !object_exists(s3_client, bucket, &object.0).await? {
+                missing.push(object.clone());
+            }
+        }
+        put_objects(s3_client, bucket, &missing).await?;
+
+        IMAGE_CREATION_BROKER.publish(inserted.clone());
+
+        Ok(inserted)
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct ImageMutation;
 
 #[Object]
 impl ImageMutation {
+    #[allow(clippy::too_many_arguments)]
     async fn create_image(
         &self,
         ctx: &Context<'_>,
         well: Well,
         image: Upload,
+        #[graphql(desc = "The time at which the image was taken, defaults to the time of upload")]
+        imaged_at: Option<DateTime<Utc>>,
+        #[graphql(
+            desc = "The inspection in which the image was taken, defaults to the inspection following the latest"
+        )]
+        inspection: Option<i32>,
+        #[graphql(default)] conditions: ImagingConditions,
+        #[graphql(
+            desc = "How an image identical to an existing image is handled, defaults to the policy of the service"
+        )]
+        duplicate_policy: Option<DuplicatePolicy>,
     ) -> async_graphql::Result<image::Model> {
         let operator_id = subject_authorization!("xchemlab.targeting.write_image", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
         let s3_client = ctx.data::<aws_sdk_s3::Client>()?;
         let bucket = ctx.data::<S3Bucket>()?;
+        let duplicate_policy = match duplicate_policy {
+            Some(duplicate_policy) => duplicate_policy,
+            None => *ctx.data::<DuplicatePolicy>()?,
+        };
 
-        s3_client
-            .put_object()
-            .key(well.to_string())
-            .bucket(bucket.clone())
-            .body(image.value(ctx)?.content.into())
-            .send()
-            .await?;
+        let upload = image.value(ctx)?;
+        let declared_content_type = upload.content_type.clone();
+        let mut contents = Vec::new();
+        upload.into_read().read_to_end(&mut contents)?;
 
-        let model = image::ActiveModel {
-            plate: sea_orm::ActiveValue::Set(well.plate),
-            well: sea_orm::ActiveValue::Set(well.well),
-            timestamp: sea_orm::ActiveValue::Set(Utc::now()),
-            operator_id: sea_orm::ActiveValue::Set(operator_id),
-        };
-        let inserted = image::Entity::insert(model)
-            .exec_with_returning(database)
-            .await?;
+        NewImage {
+            well,
+            contents,
+            declared_content_type,
+            imaged_at,
+            inspection,
+            conditions,
+            duplicate_policy,
+        }
+        .store(database, s3_client, bucket, operator_id)
+        .await
+    }
 
-        IMAGE_CREATION_BROKER.publish(inserted.clone());
+    /// Deletes an image and its predictions, immediately if permanent or otherwise once the retention period has passed
+    async fn delete_image(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        #[graphql(default)] permanent: bool,
+    ) -> async_graphql::Result<image::Model> {
+        subject_authorization!("xchemlab.targeting.delete_image", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        let s3_client = ctx.data::<aws_sdk_s3::Client>()?;
+        let bucket = ctx.data::<S3Bucket>()?;
 
-        Ok(inserted)
+        let image = image::Entity::find_by_id(id)
+            .one(database)
+            .await?
+            .ok_or(format!("Could not find image {id}"))?;
+        if permanent {
+            purge_image(database, s3_client, bucket, &image).await?;
+            Ok(image)
+        } else {
+            let mut image = image.into_active_model();
+            image.deleted_at = sea_orm::ActiveValue::Set(Some(Utc::now()));
+            Ok(image::Entity::update(image).exec(database).await?)
+        }
+    }
+
+    /// Restores an image which was marked for deletion, before it is purged
+    async fn restore_image(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+    ) -> async_graphql::Result<image::Model> {
+        subject_authorization!("xchemlab.targeting.delete_image", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+
+        let mut image = image::Entity::find_by_id(id)
+            .one(database)
+            .await?
+            .ok_or(format!("Could not find image {id}"))?
+            .into_active_model();
+        image.deleted_at = sea_orm::ActiveValue::Set(None);
+        match image::Entity::update(image).exec(database).await {
+            Err(error) if violates_constraint(&error, UNIQUE_WELL_INSPECTION) => Err(format!(
+                "Image {id} cannot be restored as its inspection has since been imaged again"
+            ))?,
+            image => Ok(image?),
+        }
     }
 }
 
@@ -119,3 +509,174 @@ impl ImageSubscription {
         IMAGE_CREATION_BROKER.subscribe()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DuplicatePolicy, NewImage};
+    use crate::{
+        image_file::ThumbnailSize,
+        mock_s3::{MockS3, BUCKET},
+        resolvers::Well,
+        tables::image,
+    };
+    use ::image::{ImageOutputFormat, RgbImage};
+    use chrono::Utc;
+    use sea_orm::{
+        prelude::Uuid, DatabaseBackend, DatabaseConnection, MockDatabase, MockExecResult,
+    };
+    use std::io::Cursor;
+
+    fn png() -> Vec<u8> {
+        let mut contents = Vec::new();
+        RgbImage::new(4, 3)
+            .write_to(&mut Cursor::new(&mut contents), ImageOutputFormat::Png)
+            .unwrap();
+        contents
+    }
+
+    fn new_image(duplicate_policy: DuplicatePolicy) -> NewImage {
+        NewImage {
+            well: Well {
+                plate: Uuid::now_v7(),
+                well: 1,
+            },
+            contents: png(),
+            declared_content_type: None,
+            imaged_at: None,
+            inspection: Some(1),
+            conditions: Default::default(),
+            duplicate_policy,
+        }
+    }
+
+    fn stored_image(checksum: &str) -> image::Model {
+        image::Model {
+            id: Uuid::now_v7(),
+            plate: Uuid::now_v7(),
+            well: 2,
+            inspection: 1,
+            imaged_at: Utc::now(),
+            object_key: image::content_key(checksum),
+            imager_id: None,
+            objective: None,
+            magnification: None,
+            illumination: None,
+            width: Some(4),
+            height: Some(3),
+            content_type: Some("image/png".to_string()),
+            checksum: Some(checksum.to_string()),
+            has_thumbnails: true,
+            timestamp: Utc::now(),
+            operator_id: "operator".to_string(),
+            deleted_at: None,
+        }
+    }
+
+    /// A database which answers the given queries, followed by the insertion of the image
+    fn database(queries: Vec<Vec<image::Model>>, inserted: &image::Model) -> DatabaseConnection {
+        MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results(queries)
+            .append_query_results([vec![inserted.clone()]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection()
+    }
+
+    fn stored_keys(checksum: &str) -> Vec<String> {
+        let object_key = image::content_key(checksum);
+        std::iter::once(object_key.clone())
+            .chain(ThumbnailSize::ALL.map(|size| image::thumbnail_key(&object_key, size)))
+            .collect()
+    }
+
+    fn checksum() -> String {
+        crate::image_file::ImageFileProperties::read(&png(), None)
+            .unwrap()
+            .checksum
+    }
+
+    #[tokio::test]
+    async fn allowed_duplicates_are_stored_without_checking() {
+        let checksum = checksum();
+        let inserted = stored_image(&checksum);
+        let database = database(Vec::new(), &inserted);
+        let s3 = MockS3::new(&[]);
+
+        let image = new_image(DuplicatePolicy::Allow)
+            .store(
+                &database,
+                &s3.client,
+                &BUCKET.parse().unwrap(),
+                "operator".to_string(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(inserted, image);
+        assert_eq!(stored_keys(&checksum), s3.uploaded());
+    }
+
+    #[tokio::test]
+    async fn warned_duplicates_are_stored() {
+        let checksum = checksum();
+        let inserted = stored_image(&checksum);
+        let database = database(vec![vec![stored_image(&checksum)]], &inserted);
+        let s3 = MockS3::new(&[]);
+
+        let image = new_image(DuplicatePolicy::Warn)
+            .store(
+                &database,
+                &s3.client,
+                &BUCKET.parse().unwrap(),
+                "operator".to_string(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(inserted, image);
+        assert_eq!(stored_keys(&checksum), s3.uploaded());
+    }
+
+    #[tokio::test]
+    async fn rejected_duplicates_are_not_stored() {
+        let checksum = checksum();
+        let duplicate = stored_image(&checksum);
+        let database = database(vec![vec![duplicate.clone()]], &duplicate);
+        let s3 = MockS3::new(&[]);
+
+        let error = new_image(DuplicatePolicy::Reject)
+            .store(
+                &database,
+                &s3.client,
+                &BUCKET.parse().unwrap(),
+                "operator".to_string(),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(error.message.contains(&duplicate.id.to_string()));
+        assert!(s3.uploaded().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejected_originals_are_stored() {
+        let checksum = checksum();
+        let inserted = stored_image(&checksum);
+        let database = database(vec![Vec::new()], &inserted);
+        let s3 = MockS3::new(&[]);
+
+        new_image(DuplicatePolicy::Reject)
+            .store(
+                &database,
+                &s3.client,
+                &BUCKET.parse().unwrap(),
+                "operator".to_string(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(stored_keys(&checksum), s3.uploaded());
+    }
+}
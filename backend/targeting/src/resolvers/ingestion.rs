@@ -0,0 +1,266 @@
+use crate::{
+    resolvers::{
+        image::{DuplicatePolicy, ImagingConditions, NewImage},
+        Well,
+    },
+    tables::image,
+    well_name::{check_columns, parse_well_name},
+};
+use async_graphql::{Context, InputObject, Object, SimpleObject, Upload};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt};
+use opa_client::subject_authorization;
+use s3_config::S3Bucket;
+use sea_orm::{prelude::Uuid, DatabaseConnection};
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    path::Path,
+};
+use zip::ZipArchive;
+
+/// The number of files of an inspection which are decoded, thumbnailed and uploaded at once
+const CONCURRENT_INGESTIONS: usize = 8;
+
+/// An image file received as part of a plate inspection
+struct InspectionFile {
+    file_name: String,
+    content_type: Option<String>,
+    contents: Vec<u8>,
+}
+
+fn read_archive(contents: Vec<u8>) -> Result<Vec<InspectionFile>, zip::result::ZipError> {
+    let mut archive = ZipArchive::new(Cursor::new(contents))?;
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let file_name = match entry
+            .enclosed_name()
+            .and_then(Path::file_name)
+            .and_then(|file_name| file_name.to_str())
+        {
+            Some(file_name) if entry.is_file() && !file_name.starts_with('.') => {
+                file_name.to_string()
+            }
+            _ => continue,
+        };
+        let mut contents = Vec::with_capacity(entry.size().try_into().unwrap_or_default());
+        entry.read_to_end(&mut contents)?;
+        files.push(InspectionFile {
+            file_name,
+            content_type: None,
+            contents,
+        });
+    }
+    Ok(files)
+}
+
+/// Parses imager file names, such as `<plate>_B07_1.jpg`, into the plate and well they depict
+fn parse_file_name(file_name: &str, columns: i16) -> (Option<Uuid>, Option<i16>) {
+    let stem = Path::new(file_name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(file_name);
+    let tokens = stem.split(|c: char| c == '_' || c == ' ' || c == '.');
+    let mut plate = None;
+    let mut well = None;
+    for token in tokens {
+        if plate.is_none() {
+            plate = Uuid::parse_str(token).ok();
+        }
+        if well.is_none() {
+            well = parse_well_name(token, columns);
+        }
+    }
+    (plate, well)
+}
+
+#[derive(Debug, Clone, InputObject)]
+pub struct ManifestEntry {
+    /// The name of the file, as uploaded or within the archive
+    file_name: String,
+    /// The plate depicted, overriding any parsed from the file name
+    plate: Option<Uuid>,
+    /// The well depicted, overriding any parsed from the file name
+    well: Option<i16>,
+    /// The time at which the image was taken, overriding that of the inspection
+    imaged_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct WellIngestionResult {
+    /// The name of the ingested file
+    file_name: String,
+    /// The well the file was identified as depicting
+    well: Option<Well>,
+    /// The stored image, if ingestion succeeded
+    image: Option<image::Model>,
+    /// The reason ingestion failed, if it did
+    error: Option<String>,
+}
+
+impl WellIngestionResult {
+    fn failed(file_name: String, well: Option<Well>, error: impl ToString) -> Self {
+        Self {
+            file_name,
+            well,
+            image: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IngestionMutation;
+
+#[Object]
+impl IngestionMutation {
+    /// Ingests all images of a plate inspection, supplied as a ZIP archive or individual files
+    #[allow(clippy::too_many_arguments)]
+    async fn ingest_plate_inspection(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The plate depicted by files which do not name one")] plate: Option<Uuid>,
+        #[graphql(desc = "A ZIP archive of image files")] archive: Option<Upload>,
+        #[graphql(default, desc = "Individual image files")] files: Vec<Upload>,
+        #[graphql(
+            default,
+            desc = "The plate and well of each file, where these can not be parsed from file names"
+        )]
+        manifest: Vec<ManifestEntry>,
+        #[graphql(
+            desc = "The time at which the inspection took place, defaults to the time of upload"
+        )]
+        imaged_at: Option<DateTime<Utc>>,
+        #[graphql(
+            desc = "The inspection number, defaults to the inspection following the latest of each well"
+        )]
+        inspection: Option<i32>,
+        #[graphql(default = 12, desc = "The number of columns on the plate")] columns: i16,
+        #[graphql(default)] conditions: ImagingConditions,
+        #[graphql(
+            desc = "How images identical to existing images are handled, defaults to the policy of the service"
+        )]
+        duplicate_policy: Option<DuplicatePolicy>,
+    ) -> async_graphql::Result<Vec<WellIngestionResult>> {
+        let operator_id = subject_authorization!("xchemlab.targeting.write_image", ctx).await?;
+        let columns = check_columns(columns)?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        let s3_client = ctx.data::<aws_sdk_s3::Client>()?;
+        let bucket = ctx.data::<S3Bucket>()?;
+        let duplicate_policy = match duplicate_policy {
+            Some(duplicate_policy) => duplicate_policy,
+            None => *ctx.data::<DuplicatePolicy>()?,
+        };
+
+        let mut inspection_files = Vec::new();
+        if let Some(archive) = archive {
+            let mut contents = Vec::new();
+            archive.value(ctx)?.into_read().read_to_end(&mut contents)?;
+            inspection_files
+                .extend(tokio::task::spawn_blocking(move || read_archive(contents)).await??);
+        }
+        for file in files {
+            let upload = file.value(ctx)?;
+            let file_name = upload.filename.clone();
+            let content_type = upload.content_type.clone();
+            let mut contents = Vec::new();
+            upload.into_read().read_to_end(&mut contents)?;
+            inspection_files.push(InspectionFile {
+                file_name,
+                content_type,
+                contents,
+            });
+        }
+        if inspection_files.is_empty() {
+            Err("No image files were supplied")?;
+        }
+        let manifest = manifest
+            .into_iter()
+            .map(|entry| (entry.file_name.clone(), entry))
+            .collect::<HashMap<_, _>>();
+
+        let ingestions = stream::iter(inspection_files).map(|file| {
+            let (parsed_plate, parsed_well) = parse_file_name(&file.file_name, columns);
+            let entry = manifest.get(&file.file_name);
+            let well = entry.and_then(|entry| entry.well).or(parsed_well);
+            let plate = entry
+                .and_then(|entry| entry.plate)
+                .or(parsed_plate)
+                .or(plate);
+            let imaged_at = entry.and_then(|entry| entry.imaged_at).or(imaged_at);
+            let conditions = conditions.clone();
+            let operator_id = operator_id.clone();
+            async move {
+                let well = match (plate, well) {
+                    (Some(plate), Some(well)) => Well { plate, well },
+                    (None, _) => {
+                        let error = format!(
+                            "Could not identify the plate depicted by {}",
+                            file.file_name
+                        );
+                        return WellIngestionResult::failed(file.file_name, None, error);
+                    }
+                    (_, None) => {
+                        let error =
+                            format!("Could not identify the well depicted by {}", file.file_name);
+                        return WellIngestionResult::failed(file.file_name, None, error);
+                    }
+                };
+                let new_image = NewImage {
+                    well: well.clone(),
+                    contents: file.contents,
+                    declared_content_type: file.content_type,
+                    imaged_at,
+                    inspection,
+                    conditions,
+                    duplicate_policy,
+                };
+                match new_image
+                    .store(database, s3_client, bucket, operator_id)
+                    .await
+                {
+                    Ok(image) => WellIngestionResult {
+                        file_name: file.file_name,
+                        well: Some(well),
+                        image: Some(image),
+                        error: None,
+                    },
+                    Err(error) => {
+                        WellIngestionResult::failed(file.file_name, Some(well), error.message)
+                    }
+                }
+            }
+        });
+
+        Ok(ingestions.buffered(CONCURRENT_INGESTIONS).collect().await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_file_name;
+    use sea_orm::prelude::Uuid;
+
+    #[test]
+    fn file_names_identify_plate_and_well() {
+        let plate = Uuid::parse_str("0193ab6e-7f3c-7c8e-a3a6-2f1e4d0b9c11").unwrap();
+        assert_eq!(
+            (Some(plate), Some(19)),
+            parse_file_name(&format!("{plate}_B07_1.jpg"), 12)
+        );
+        assert_eq!(
+            (Some(plate), Some(96)),
+            parse_file_name(&format!("H12 {plate}.png"), 12)
+        );
+        assert_eq!((None, Some(1)), parse_file_name("inspection.A1.tiff", 12));
+        assert_eq!((None, Some(1)), parse_file_name("A01", 12));
+    }
+
+    #[test]
+    fn file_names_without_plate_or_well_are_unidentified() {
+        assert_eq!((None, None), parse_file_name("inspection_1.jpg", 12));
+        assert_eq!((None, None), parse_file_name("plate_H13.jpg", 12));
+        assert_eq!((None, None), parse_file_name("", 12));
+    }
+}
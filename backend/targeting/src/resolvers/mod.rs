@@ -1,7 +1,14 @@
 pub mod image;
+pub mod ingestion;
+pub mod pick_list;
 pub mod prediction;
+pub mod review;
+pub mod subgraph_extensions;
+pub mod summary;
 
 use async_graphql::{InputObject, SimpleObject};
+use chrono::{DateTime, Utc};
+use sea_orm::{ColumnTrait, Condition, DbErr, RuntimeErr};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, SimpleObject, InputObject)]
@@ -16,3 +23,32 @@ impl ToString for Well {
         format!("{}/{}", self.plate, self.well)
     }
 }
+
+#[derive(Debug, Clone, InputObject)]
+pub struct TimestampRange {
+    /// The inclusive start of the range
+    pub start: Option<DateTime<Utc>>,
+    /// The exclusive end of the range
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl TimestampRange {
+    pub fn into_condition(self, column: impl ColumnTrait) -> Condition {
+        Condition::all()
+            .add_option(self.start.map(|start| column.gte(start)))
+            .add_option(self.end.map(|end| column.lt(end)))
+    }
+}
+
+/// Whether a database error was caused by violating the named constraint or unique index
+pub fn violates_constraint(error: &DbErr, constraint: &str) -> bool {
+    match error {
+        DbErr::Exec(RuntimeErr::SqlxError(error)) | DbErr::Query(RuntimeErr::SqlxError(error)) => {
+            error
+                .as_database_error()
+                .and_then(|error| error.constraint())
+                == Some(constraint)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,119 @@
+use super::prediction::of_retained_image;
+use crate::{
+    echo::{write_pick_list, EchoTransfer},
+    tables::{
+        prediction::{self, ReviewStatus},
+        prediction_drop,
+    },
+    well_name::{check_columns, format_well_name},
+};
+use async_graphql::{Context, InputObject, Object, SimpleObject};
+use opa_client::subject_authorization;
+use sea_orm::{
+    prelude::Uuid, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+};
+use std::collections::{hash_map::Entry, HashMap};
+
+#[derive(Debug, Clone, InputObject)]
+pub struct EchoTransferInput {
+    /// The name of the compound plate
+    source_plate: String,
+    /// The compound well, such as `A1`
+    source_well: String,
+    /// The crystal well number on the targeted plate
+    destination_well: i16,
+    /// The volume to dispense, in nanolitres
+    transfer_volume: f64,
+    /// The drop to dispense into, defaults to the first drop of the target
+    drop_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct EchoPickList {
+    transfers: Vec<EchoTransfer>,
+    /// The transfers formatted as an Echo pick list file
+    csv: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PickListQuery;
+
+#[Object]
+impl PickListQuery {
+    /// Produces an Echo pick list dispensing into the accepted target of each destination well
+    async fn echo_pick_list(
+        &self,
+        ctx: &Context<'_>,
+        plate: Uuid,
+        transfers: Vec<EchoTransferInput>,
+        #[graphql(desc = "The scale of the plate images, used to convert offsets to micrometres")]
+        microns_per_pixel: f64,
+        #[graphql(desc = "The name of the crystal plate, defaults to the plate identifier")]
+        destination_plate_name: Option<String>,
+        #[graphql(default = 12, desc = "The number of columns on the plate")] columns: i16,
+    ) -> async_graphql::Result<EchoPickList> {
+        subject_authorization!("xchemlab.targeting.read_prediction", ctx).await?;
+        let columns = check_columns(columns)?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        let destination_plate = destination_plate_name.unwrap_or(plate.to_string());
+
+        let mut targets = HashMap::new();
+        for prediction in prediction::Entity::find()
+            .filter(prediction::Column::Plate.eq(plate))
+            .filter(prediction::Column::Status.eq(ReviewStatus::Accepted))
+            .filter(of_retained_image())
+            .order_by_desc(prediction::Column::Timestamp)
+            .all(database)
+            .await?
+        {
+            if let Entry::Vacant(entry) = targets.entry(prediction.well) {
+                entry.insert(prediction);
+            }
+        }
+        let mut drops = HashMap::<_, Vec<_>>::new();
+        for drop in prediction_drop::Entity::find()
+            .filter(
+                prediction_drop::Column::PredictionId
+                    .is_in(targets.values().map(|target| target.id)),
+            )
+            .order_by_asc(prediction_drop::Column::Id)
+            .all(database)
+            .await?
+        {
+            drops.entry(drop.prediction_id).or_default().push(drop);
+        }
+
+        let transfers = transfers
+            .into_iter()
+            .map(|transfer| {
+                let well = transfer.destination_well;
+                let target = targets
+                    .get(&well)
+                    .ok_or(format!("Could not find an accepted target for well {well}"))?;
+                let target_drops = drops.get(&target.id).map(Vec::as_slice).unwrap_or_default();
+                let drop = match transfer.drop_id {
+                    Some(drop_id) => target_drops.iter().find(|drop| drop.id == drop_id),
+                    None => target_drops.first(),
+                }
+                .ok_or(format!("Could not find the target drop for well {well}"))?;
+                let offset = |insertion_point: i32, centroid: i32| {
+                    (f64::from(insertion_point - centroid) * microns_per_pixel).round() as i32
+                };
+                Ok(EchoTransfer {
+                    source_plate: transfer.source_plate,
+                    source_well: transfer.source_well,
+                    destination_plate: destination_plate.clone(),
+                    destination_well: format_well_name(well, columns).ok_or(format!(
+                        "Well {well} is not on a plate with {columns} columns"
+                    ))?,
+                    transfer_volume: transfer.transfer_volume,
+                    x_offset: offset(drop.insertion_point_x, target.well_centroid_x),
+                    y_offset: offset(drop.insertion_point_y, target.well_centroid_y),
+                })
+            })
+            .collect::<async_graphql::Result<Vec<_>>>()?;
+
+        let csv = write_pick_list(&transfers)?;
+        Ok(EchoPickList { transfers, csv })
+    }
+}
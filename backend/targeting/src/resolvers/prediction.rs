@@ -1,29 +1,51 @@
 use crate::{
-    resolvers::Well,
-    tables::{prediction, prediction_crystal, prediction_drop},
+    deletion::delete_predictions,
+    loaders::{
+        CrystalsLoader, DropsLoader, EditsLoader, ImageLoader, PredictionLoader, ReviewsLoader,
+    },
+    resolvers::{TimestampRange, Well},
+    tables::{
+        image,
+        prediction::{self, ReviewStatus},
+        prediction_crystal, prediction_drop, prediction_edit,
+    },
 };
-use async_graphql::{ComplexObject, Context, InputObject, Object, SimpleObject};
-use chrono::Utc;
+use async_graphql::{
+    dataloader::DataLoader, ComplexObject, Context, InputObject, Object, SimpleObject, Subscription,
+};
+use chrono::{DateTime, Utc};
+use futures_util::{future::ready, StreamExt};
+use graphql_event_broker::EventBroker;
 use opa_client::subject_authorization;
 use sea_orm::{
-    prelude::Uuid, ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, ModelTrait,
-    QueryFilter, QueryTrait, TransactionTrait,
+    prelude::Uuid,
+    sea_query::{Query, SimpleExpr},
+    ActiveValue, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, TransactionTrait,
+};
+use the_paginator::{
+    graphql::{CursorInput, ModelConnection},
+    KeyOrder,
 };
+use tokio_stream::Stream;
+
+pub(super) static PREDICTION_CREATION_BROKER: EventBroker<prediction::Model> = EventBroker::new();
+pub(super) static PREDICTION_UPDATE_BROKER: EventBroker<prediction::Model> = EventBroker::new();
+static PREDICTION_FAILURE_BROKER: EventBroker<PredictionFailure> = EventBroker::new();
 
 #[derive(Debug, Clone, SimpleObject, InputObject)]
 #[graphql(input_name = "PointInput")]
-struct Point {
-    x: i32,
-    y: i32,
+pub(super) struct Point {
+    pub(super) x: i32,
+    pub(super) y: i32,
 }
 
 #[derive(Debug, Clone, SimpleObject, InputObject)]
 #[graphql(input_name = "BoundingBoxInput")]
-struct BoundingBox {
-    left: i32,
-    right: i32,
-    top: i32,
-    bottom: i32,
+pub(super) struct BoundingBox {
+    pub(super) left: i32,
+    pub(super) right: i32,
+    pub(super) top: i32,
+    pub(super) bottom: i32,
 }
 
 #[ComplexObject]
@@ -39,12 +61,15 @@ impl prediction_crystal::Model {
 }
 
 #[derive(Debug, Clone, InputObject)]
-struct CrystalInput {
-    bounding_box: BoundingBox,
+pub(super) struct CrystalInput {
+    pub(super) bounding_box: BoundingBox,
 }
 
 impl prediction_crystal::ActiveModel {
-    fn from_crystal_input_and_drop_id(crystal_input: &CrystalInput, drop_id: Uuid) -> Self {
+    pub(super) fn from_crystal_input_and_drop_id(
+        crystal_input: &CrystalInput,
+        drop_id: Uuid,
+    ) -> Self {
         Self {
             id: ActiveValue::Set(Uuid::now_v7()),
             drop_id: ActiveValue::Set(drop_id),
@@ -78,11 +103,11 @@ impl prediction_drop::Model {
         &self,
         ctx: &Context<'_>,
     ) -> async_graphql::Result<Vec<prediction_crystal::Model>> {
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self
-            .find_related(prediction_crystal::Entity)
-            .all(database)
-            .await?)
+        Ok(ctx
+            .data::<DataLoader<CrystalsLoader>>()?
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default())
     }
 }
 
@@ -110,7 +135,16 @@ impl prediction_drop::ActiveModel {
 
 #[ComplexObject]
 impl prediction::Model {
-    async fn image(&self) -> Well {
+    async fn image(&self, ctx: &Context<'_>) -> async_graphql::Result<image::Model> {
+        subject_authorization!("xchemlab.targeting.read_image", ctx).await?;
+        Ok(ctx
+            .data::<DataLoader<ImageLoader>>()?
+            .load_one(self.image_id)
+            .await?
+            .ok_or(format!("Could not find image {}", self.image_id))?)
+    }
+
+    async fn well(&self) -> Well {
         Well {
             plate: self.plate,
             well: self.well,
@@ -125,42 +159,118 @@ impl prediction::Model {
     }
 
     async fn drops(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<prediction_drop::Model>> {
-        let database = ctx.data::<DatabaseConnection>()?;
-        Ok(self
-            .find_related(prediction_drop::Entity)
-            .all(database)
-            .await?)
+        Ok(ctx
+            .data::<DataLoader<DropsLoader>>()?
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// The machine prediction which this prediction is a review of, if any
+    async fn review_of(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<prediction::Model>> {
+        Ok(match self.review_of {
+            Some(review_of) => {
+                ctx.data::<DataLoader<PredictionLoader>>()?
+                    .load_one(review_of)
+                    .await?
+            }
+            None => None,
+        })
+    }
+
+    /// The human reviews of this prediction, each an edited copy of it
+    async fn reviews(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<prediction::Model>> {
+        Ok(ctx
+            .data::<DataLoader<ReviewsLoader>>()?
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// The changes made to this prediction, in the order they were made
+    async fn edits(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<prediction_edit::Model>> {
+        Ok(ctx
+            .data::<DataLoader<EditsLoader>>()?
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default())
     }
 }
 
+/// Excludes predictions of images which have been marked for deletion
+pub(crate) fn of_retained_image() -> SimpleExpr {
+    prediction::Column::ImageId.in_subquery(
+        Query::select()
+            .column(image::Column::Id)
+            .from(image::Entity)
+            .and_where(image::Column::DeletedAt.is_null())
+            .to_owned(),
+    )
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct PredictionFailure {
+    image_id: Uuid,
+    plate: Uuid,
+    well: i16,
+    /// A description of the error encountered whilst predicting
+    error: String,
+    /// The version of the model which failed to produce a prediction
+    model_version: Option<String>,
+    timestamp: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct PredictionQuery;
 
 #[Object]
 impl PredictionQuery {
+    #[allow(clippy::too_many_arguments)]
     async fn predictions(
         &self,
         ctx: &Context<'_>,
+        cursor: CursorInput,
         id: Option<Uuid>,
+        image_id: Option<Uuid>,
         plate: Option<Uuid>,
         well: Option<i16>,
         operator_id: Option<String>,
-    ) -> async_graphql::Result<Vec<prediction::Model>> {
+        model_version: Option<String>,
+        created: Option<TimestampRange>,
+        #[graphql(default)] order: KeyOrder,
+    ) -> async_graphql::Result<ModelConnection<prediction::Model>> {
         subject_authorization!("xchemlab.targeting.read_prediction", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
-        Ok(prediction::Entity::find()
-            .apply_if(id, |query, id| query.filter(prediction::Column::Id.eq(id)))
-            .apply_if(plate, |query, plate| {
-                query.filter(prediction::Column::Plate.eq(plate))
-            })
-            .apply_if(well, |query, well| {
-                query.filter(prediction::Column::Well.eq(well))
-            })
-            .apply_if(operator_id, |query, operator_id| {
-                query.filter(prediction::Column::OperatorId.eq(operator_id))
-            })
+        Ok(cursor
+            .try_into_query_cursor::<prediction::Entity>()?
+            .filter(
+                Condition::all()
+                    .add(of_retained_image())
+                    .add_option(id.map(|id| prediction::Column::Id.eq(id)))
+                    .add_option(image_id.map(|image_id| prediction::Column::ImageId.eq(image_id)))
+                    .add_option(plate.map(|plate| prediction::Column::Plate.eq(plate)))
+                    .add_option(well.map(|well| prediction::Column::Well.eq(well)))
+                    .add_option(
+                        operator_id
+                            .map(|operator_id| prediction::Column::OperatorId.eq(operator_id)),
+                    )
+                    .add_option(
+                        model_version.map(|model_version| {
+                            prediction::Column::ModelVersion.eq(model_version)
+                        }),
+                    )
+                    .add_option(
+                        created
+                            .map(|created| created.into_condition(prediction::Column::Timestamp)),
+                    ),
+            )
+            .key_order(order)
             .all(database)
-            .await?)
+            .await?
+            .try_into_connection()?)
     }
 }
 
@@ -172,27 +282,38 @@ impl PredicitonMutation {
     async fn create_prediction(
         &self,
         ctx: &Context<'_>,
-        plate: Well,
+        image_id: Uuid,
         well_centroid: Point,
         well_radius: i32,
         drops: Vec<DropInput>,
+        #[graphql(desc = "The version of the model which produced the prediction")]
+        model_version: Option<String>,
     ) -> async_graphql::Result<prediction::Model> {
         let operator_id =
             subject_authorization!("xchemlab.targeting.write_prediction", ctx).await?;
         let database = ctx.data::<DatabaseConnection>()?;
 
+        let image = image::Entity::find_by_id(image_id)
+            .one(database)
+            .await?
+            .ok_or(format!("Could not find image {image_id}"))?;
+
         let prediction = database
             .transaction::<_, _, DbErr>(|transaction| {
                 Box::pin(async move {
                     let prediction = prediction::Entity::insert(prediction::ActiveModel {
                         id: ActiveValue::Set(Uuid::now_v7()),
-                        plate: ActiveValue::Set(plate.plate),
-                        well: ActiveValue::Set(plate.well),
+                        image_id: ActiveValue::Set(image.id),
+                        plate: ActiveValue::Set(image.plate),
+                        well: ActiveValue::Set(image.well),
                         well_centroid_x: ActiveValue::Set(well_centroid.x),
                         well_centroid_y: ActiveValue::Set(well_centroid.y),
                         well_radius: ActiveValue::Set(well_radius),
                         timestamp: ActiveValue::Set(Utc::now()),
                         operator_id: ActiveValue::Set(operator_id),
+                        model_version: ActiveValue::Set(model_version),
+                        review_of: ActiveValue::Set(None),
+                        status: ActiveValue::Set(ReviewStatus::Unreviewed),
                     })
                     .exec_with_returning(transaction)
                     .await?;
@@ -224,6 +345,95 @@ impl PredicitonMutation {
             })
             .await?;
 
+        PREDICTION_CREATION_BROKER.publish(prediction.clone());
+
+        Ok(prediction)
+    }
+
+    /// Deletes a prediction, along with any reviews of it
+    async fn delete_prediction(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+    ) -> async_graphql::Result<prediction::Model> {
+        subject_authorization!("xchemlab.targeting.delete_prediction", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+
+        let prediction = prediction::Entity::find_by_id(id)
+            .one(database)
+            .await?
+            .ok_or(format!("Could not find prediction {id}"))?;
+        database
+            .transaction(|transaction| {
+                Box::pin(async move { delete_predictions(transaction, vec![id]).await })
+            })
+            .await?;
         Ok(prediction)
     }
+
+    /// Reports that a prediction could not be produced for an image
+    async fn report_prediction_failure(
+        &self,
+        ctx: &Context<'_>,
+        image_id: Uuid,
+        error: String,
+        #[graphql(desc = "The version of the model which failed to produce a prediction")]
+        model_version: Option<String>,
+    ) -> async_graphql::Result<PredictionFailure> {
+        subject_authorization!("xchemlab.targeting.write_prediction", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+
+        let image = image::Entity::find_by_id(image_id)
+            .one(database)
+            .await?
+            .ok_or(format!("Could not find image {image_id}"))?;
+        let failure = PredictionFailure {
+            image_id: image.id,
+            plate: image.plate,
+            well: image.well,
+            error,
+            model_version,
+            timestamp: Utc::now(),
+        };
+
+        PREDICTION_FAILURE_BROKER.publish(failure.clone());
+
+        Ok(failure)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PredictionSubscription;
+
+#[Subscription]
+impl PredictionSubscription {
+    /// Machine predictions as they are created
+    async fn prediction_created(
+        &self,
+        plate: Option<Uuid>,
+    ) -> impl Stream<Item = prediction::Model> {
+        PREDICTION_CREATION_BROKER
+            .subscribe()
+            .filter(move |prediction| ready(plate.map_or(true, |plate| prediction.plate == plate)))
+    }
+
+    /// Reviews of predictions as they are started, corrected and have their status changed
+    async fn prediction_updated(
+        &self,
+        plate: Option<Uuid>,
+    ) -> impl Stream<Item = prediction::Model> {
+        PREDICTION_UPDATE_BROKER
+            .subscribe()
+            .filter(move |prediction| ready(plate.map_or(true, |plate| prediction.plate == plate)))
+    }
+
+    /// Failures to produce a prediction for an image as they are reported
+    async fn prediction_failed(
+        &self,
+        plate: Option<Uuid>,
+    ) -> impl Stream<Item = PredictionFailure> {
+        PREDICTION_FAILURE_BROKER
+            .subscribe()
+            .filter(move |failure| ready(plate.map_or(true, |plate| failure.plate == plate)))
+    }
 }
@@ -0,0 +1,657 @@
+use super::prediction::{of_retained_image, CrystalInput, Point, PREDICTION_UPDATE_BROKER};
+use crate::{
+    resolvers::Well,
+    tables::{
+        prediction::{self, ReviewStatus},
+        prediction_crystal, prediction_drop,
+        prediction_edit::{self, EditAction},
+    },
+};
+use async_graphql::{Context, Object};
+use chrono::Utc;
+use opa_client::subject_authorization;
+use sea_orm::{
+    prelude::Uuid, ActiveEnum, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection,
+    DbErr, EntityTrait, IntoActiveModel, ModelTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+
+impl prediction_edit::ActiveModel {
+    fn new(
+        prediction_id: Uuid,
+        action: EditAction,
+        subject_id: Uuid,
+        previous: Option<String>,
+        current: Option<String>,
+        operator_id: String,
+    ) -> Self {
+        Self {
+            id: ActiveValue::Set(Uuid::now_v7()),
+            prediction_id: ActiveValue::Set(prediction_id),
+            action: ActiveValue::Set(action),
+            subject_id: ActiveValue::Set(subject_id),
+            previous: ActiveValue::Set(previous),
+            current: ActiveValue::Set(current),
+            timestamp: ActiveValue::Set(Utc::now()),
+            operator_id: ActiveValue::Set(operator_id),
+        }
+    }
+}
+
+impl prediction_crystal::Model {
+    fn describe_bounding_box(&self) -> String {
+        format!(
+            "left: {}, right: {}, top: {}, bottom: {}",
+            self.left, self.right, self.top, self.bottom
+        )
+    }
+}
+
+/// Ensures a prediction is a review, as machine predictions are kept as produced
+fn as_review(prediction: prediction::Model) -> Result<prediction::Model, String> {
+    match prediction.review_of {
+        Some(_) => Ok(prediction),
+        None => Err(format!(
+            "Prediction {} is a machine prediction, a review must be started to correct it",
+            prediction.id
+        )),
+    }
+}
+
+async fn find_review(
+    database: &impl ConnectionTrait,
+    prediction_id: Uuid,
+) -> async_graphql::Result<prediction::Model> {
+    let prediction = prediction::Entity::find_by_id(prediction_id)
+        .one(database)
+        .await?
+        .ok_or(format!("Could not find prediction {prediction_id}"))?;
+    Ok(as_review(prediction)?)
+}
+
+/// Moves the insertion point of a drop in a review, recording the edit, returning the review and the drop
+async fn move_drop_insertion_point(
+    database: &DatabaseConnection,
+    drop_id: Uuid,
+    insertion_point: Point,
+    operator_id: String,
+) -> async_graphql::Result<(prediction::Model, prediction_drop::Model)> {
+    let drop = prediction_drop::Entity::find_by_id(drop_id)
+        .one(database)
+        .await?
+        .ok_or(format!("Could not find drop {drop_id}"))?;
+    let review = find_review(database, drop.prediction_id).await?;
+    let edit = prediction_edit::ActiveModel::new(
+        review.id,
+        EditAction::InsertionPointMoved,
+        drop.id,
+        Some(format!(
+            "x: {}, y: {}",
+            drop.insertion_point_x, drop.insertion_point_y
+        )),
+        Some(format!(
+            "x: {}, y: {}",
+            insertion_point.x, insertion_point.y
+        )),
+        operator_id,
+    );
+    let mut drop = drop.into_active_model();
+    drop.insertion_point_x = ActiveValue::Set(insertion_point.x);
+    drop.insertion_point_y = ActiveValue::Set(insertion_point.y);
+
+    let drop = database
+        .transaction(|transaction| {
+            Box::pin(async {
+                prediction_edit::Entity::insert(edit)
+                    .exec(transaction)
+                    .await?;
+                prediction_drop::Entity::update(drop)
+                    .exec(transaction)
+                    .await
+            })
+        })
+        .await?;
+
+    Ok((review, drop))
+}
+
+/// Adds a crystal to a drop in a review, recording the edit, returning the review and the crystal
+async fn add_drop_crystal(
+    database: &DatabaseConnection,
+    drop_id: Uuid,
+    crystal: CrystalInput,
+    operator_id: String,
+) -> async_graphql::Result<(prediction::Model, prediction_crystal::Model)> {
+    let drop = prediction_drop::Entity::find_by_id(drop_id)
+        .one(database)
+        .await?
+        .ok_or(format!("Could not find drop {drop_id}"))?;
+    let review = find_review(database, drop.prediction_id).await?;
+
+    let crystal = database
+        .transaction::<_, _, DbErr>(|transaction| {
+            Box::pin(async move {
+                let crystal = prediction_crystal::Entity::insert(
+                    prediction_crystal::ActiveModel::from_crystal_input_and_drop_id(
+                        &crystal, drop.id,
+                    ),
+                )
+                .exec_with_returning(transaction)
+                .await?;
+                prediction_edit::Entity::insert(prediction_edit::ActiveModel::new(
+                    review.id,
+                    EditAction::CrystalAdded,
+                    crystal.id,
+                    None,
+                    Some(crystal.describe_bounding_box()),
+                    operator_id,
+                ))
+                .exec(transaction)
+                .await?;
+                Ok(crystal)
+            })
+        })
+        .await?;
+
+    Ok((review, crystal))
+}
+
+/// Deletes a crystal from a drop in a review, recording the edit, returning the review and the crystal
+async fn delete_review_crystal(
+    database: &DatabaseConnection,
+    crystal_id: Uuid,
+    operator_id: String,
+) -> async_graphql::Result<(prediction::Model, prediction_crystal::Model)> {
+    let crystal = prediction_crystal::Entity::find_by_id(crystal_id)
+        .one(database)
+        .await?
+        .ok_or(format!("Could not find crystal {crystal_id}"))?;
+    let drop = crystal
+        .find_related(prediction_drop::Entity)
+        .one(database)
+        .await?
+        .ok_or(format!("Could not find drop {}", crystal.drop_id))?;
+    let review = find_review(database, drop.prediction_id).await?;
+    let edit = prediction_edit::ActiveModel::new(
+        review.id,
+        EditAction::CrystalDeleted,
+        crystal.id,
+        Some(crystal.describe_bounding_box()),
+        None,
+        operator_id,
+    );
+
+    let crystal = database
+        .transaction::<_, _, DbErr>(|transaction| {
+            Box::pin(async move {
+                prediction_edit::Entity::insert(edit)
+                    .exec(transaction)
+                    .await?;
+                prediction_crystal::Entity::delete_by_id(crystal.id)
+                    .exec(transaction)
+                    .await?;
+                Ok(crystal)
+            })
+        })
+        .await?;
+
+    Ok((review, crystal))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReviewQuery;
+
+#[Object]
+impl ReviewQuery {
+    /// The most recently accepted prediction for a well, to be used as the soaking target
+    async fn target(
+        &self,
+        ctx: &Context<'_>,
+        well: Well,
+    ) -> async_graphql::Result<Option<prediction::Model>> {
+        subject_authorization!("xchemlab.targeting.read_prediction", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        Ok(prediction::Entity::find()
+            .filter(prediction::Column::Plate.eq(well.plate))
+            .filter(prediction::Column::Well.eq(well.well))
+            .filter(prediction::Column::Status.eq(ReviewStatus::Accepted))
+            .filter(of_retained_image())
+            .order_by_desc(prediction::Column::Timestamp)
+            .one(database)
+            .await?)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReviewMutation;
+
+#[Object]
+impl ReviewMutation {
+    /// Creates an editable copy of a machine prediction, leaving the original intact
+    async fn review_prediction(
+        &self,
+        ctx: &Context<'_>,
+        prediction_id: Uuid,
+    ) -> async_graphql::Result<prediction::Model> {
+        let operator_id =
+            subject_authorization!("xchemlab.targeting.review_prediction", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+
+        let original = prediction::Entity::find_by_id(prediction_id)
+            .one(database)
+            .await?
+            .ok_or(format!("Could not find prediction {prediction_id}"))?;
+        if original.review_of.is_some() {
+            Err(format!("Prediction {prediction_id} is already a review"))?;
+        }
+        let mut drops = Vec::new();
+        for drop in original
+            .find_related(prediction_drop::Entity)
+            .all(database)
+            .await?
+        {
+            let crystals = drop
+                .find_related(prediction_crystal::Entity)
+                .all(database)
+                .await?;
+            drops.push((drop, crystals));
+        }
+
+        let review = database
+            .transaction::<_, _, DbErr>(|transaction| {
+                Box::pin(async move {
+                    let review = prediction::Entity::insert(prediction::ActiveModel {
+                        id: ActiveValue::Set(Uuid::now_v7()),
+                        image_id: ActiveValue::Set(original.image_id),
+                        plate: ActiveValue::Set(original.plate),
+                        well: ActiveValue::Set(original.well),
+                        well_centroid_x: ActiveValue::Set(original.well_centroid_x),
+                        well_centroid_y: ActiveValue::Set(original.well_centroid_y),
+                        well_radius: ActiveValue::Set(original.well_radius),
+                        timestamp: ActiveValue::Set(Utc::now()),
+                        operator_id: ActiveValue::Set(operator_id.clone()),
+                        model_version: ActiveValue::Set(original.model_version),
+                        review_of: ActiveValue::Set(Some(original.id)),
+                        status: ActiveValue::Set(ReviewStatus::Unreviewed),
+                    })
+                    .exec_with_returning(transaction)
+                    .await?;
+
+                    for (drop, crystals) in drops {
+                        let drop_id = Uuid::now_v7();
+                        prediction_drop::Entity::insert(prediction_drop::ActiveModel {
+                            id: ActiveValue::Set(drop_id),
+                            prediction_id: ActiveValue::Set(review.id),
+                            ..drop.into_active_model()
+                        })
+                        .exec(transaction)
+                        .await?;
+                        for crystal in crystals {
+                            prediction_crystal::Entity::insert(prediction_crystal::ActiveModel {
+                                id: ActiveValue::Set(Uuid::now_v7()),
+                                drop_id: ActiveValue::Set(drop_id),
+                                ..crystal.into_active_model()
+                            })
+                            .exec(transaction)
+                            .await?;
+                        }
+                    }
+
+                    prediction_edit::Entity::insert(prediction_edit::ActiveModel::new(
+                        review.id,
+                        EditAction::ReviewStarted,
+                        original.id,
+                        None,
+                        None,
+                        operator_id,
+                    ))
+                    .exec(transaction)
+                    .await?;
+
+                    Ok(review)
+                })
+            })
+            .await?;
+
+        PREDICTION_UPDATE_BROKER.publish(review.clone());
+
+        Ok(review)
+    }
+
+    /// Marks a review as reviewed, accepted or rejected, leaving the machine prediction intact
+    async fn set_prediction_status(
+        &self,
+        ctx: &Context<'_>,
+        prediction_id: Uuid,
+        status: ReviewStatus,
+    ) -> async_graphql::Result<prediction::Model> {
+        let operator_id =
+            subject_authorization!("xchemlab.targeting.review_prediction", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+
+        let prediction = find_review(database, prediction_id).await?;
+        let edit = prediction_edit::ActiveModel::new(
+            prediction.id,
+            EditAction::StatusChanged,
+            prediction.id,
+            Some(prediction.status.to_value()),
+            Some(status.to_value()),
+            operator_id,
+        );
+        let mut prediction = prediction.into_active_model();
+        prediction.status = ActiveValue::Set(status);
+
+        let prediction = database
+            .transaction(|transaction| {
+                Box::pin(async {
+                    prediction_edit::Entity::insert(edit)
+                        .exec(transaction)
+                        .await?;
+                    prediction::Entity::update(prediction)
+                        .exec(transaction)
+                        .await
+                })
+            })
+            .await?;
+
+        PREDICTION_UPDATE_BROKER.publish(prediction.clone());
+
+        Ok(prediction)
+    }
+
+    /// Moves the point of a drop in a review at which the compound is to be dispensed
+    async fn move_insertion_point(
+        &self,
+        ctx: &Context<'_>,
+        drop_id: Uuid,
+        insertion_point: Point,
+    ) -> async_graphql::Result<prediction_drop::Model> {
+        let operator_id =
+            subject_authorization!("xchemlab.targeting.review_prediction", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+
+        let (review, drop) =
+            move_drop_insertion_point(database, drop_id, insertion_point, operator_id).await?;
+
+        PREDICTION_UPDATE_BROKER.publish(review);
+
+        Ok(drop)
+    }
+
+    /// Adds a crystal missed by the machine prediction to a drop in a review
+    async fn add_crystal(
+        &self,
+        ctx: &Context<'_>,
+        drop_id: Uuid,
+        crystal: CrystalInput,
+    ) -> async_graphql::Result<prediction_crystal::Model> {
+        let operator_id =
+            subject_authorization!("xchemlab.targeting.review_prediction", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+
+        let (review, crystal) = add_drop_crystal(database, drop_id, crystal, operator_id).await?;
+
+        PREDICTION_UPDATE_BROKER.publish(review);
+
+        Ok(crystal)
+    }
+
+    /// Deletes a crystal wrongly identified by the machine prediction from a drop in a review
+    async fn delete_crystal(
+        &self,
+        ctx: &Context<'_>,
+        crystal_id: Uuid,
+    ) -> async_graphql::Result<prediction_crystal::Model> {
+        let operator_id =
+            subject_authorization!("xchemlab.targeting.review_prediction", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+
+        let (review, crystal) = delete_review_crystal(database, crystal_id, operator_id).await?;
+
+        PREDICTION_UPDATE_BROKER.publish(review);
+
+        Ok(crystal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_drop_crystal, as_review, delete_review_crystal, move_drop_insertion_point};
+    use crate::{
+        resolvers::prediction::{BoundingBox, CrystalInput, Point},
+        tables::{
+            prediction::{self, ReviewStatus},
+            prediction_crystal, prediction_drop,
+            prediction_edit::EditAction,
+        },
+    };
+    use chrono::Utc;
+    use sea_orm::{
+        prelude::Uuid, DatabaseBackend, DatabaseConnection, MockDatabase, MockExecResult,
+    };
+
+    fn prediction(review_of: Option<Uuid>) -> prediction::Model {
+        prediction::Model {
+            id: Uuid::now_v7(),
+            image_id: Uuid::now_v7(),
+            plate: Uuid::now_v7(),
+            well: 1,
+            well_centroid_x: 0,
+            well_centroid_y: 0,
+            well_radius: 0,
+            timestamp: Utc::now(),
+            operator_id: "chimp".to_string(),
+            model_version: None,
+            review_of,
+            status: ReviewStatus::Unreviewed,
+        }
+    }
+
+    fn drop(prediction: &prediction::Model) -> prediction_drop::Model {
+        prediction_drop::Model {
+            id: Uuid::now_v7(),
+            prediction_id: prediction.id,
+            insertion_point_x: 10,
+            insertion_point_y: 20,
+            left: 0,
+            right: 100,
+            top: 0,
+            bottom: 100,
+        }
+    }
+
+    fn crystal(drop: &prediction_drop::Model) -> prediction_crystal::Model {
+        prediction_crystal::Model {
+            id: Uuid::now_v7(),
+            drop_id: drop.id,
+            left: 30,
+            right: 40,
+            top: 50,
+            bottom: 60,
+        }
+    }
+
+    fn exec_results(count: usize) -> Vec<MockExecResult> {
+        vec![
+            MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            };
+            count
+        ]
+    }
+
+    /// Whether an edit with the action was recorded in the database
+    fn edited(database: DatabaseConnection, action: EditAction) -> bool {
+        format!("{:?}", database.into_transaction_log()).contains(&format!("{action:?}"))
+    }
+
+    #[test]
+    fn review_is_editable() {
+        let review = prediction(Some(Uuid::now_v7()));
+        assert_eq!(as_review(review.clone()), Ok(review));
+    }
+
+    #[test]
+    fn machine_prediction_is_not_editable() {
+        let machine = prediction(None);
+        assert_eq!(
+            as_review(machine.clone()),
+            Err(format!(
+                "Prediction {} is a machine prediction, a review must be started to correct it",
+                machine.id
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn insertion_point_of_review_is_moved() {
+        let review = prediction(Some(Uuid::now_v7()));
+        let original = drop(&review);
+        let moved = prediction_drop::Model {
+            insertion_point_x: 15,
+            insertion_point_y: 25,
+            ..original.clone()
+        };
+        let database = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([[original]])
+            .append_query_results([[review.clone()]])
+            .append_query_results([[moved.clone()]])
+            .append_exec_results(exec_results(1))
+            .into_connection();
+
+        let (edited_review, drop) = move_drop_insertion_point(
+            &database,
+            moved.id,
+            Point { x: 15, y: 25 },
+            "operator".to_string(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!((review, moved), (edited_review, drop));
+        assert!(edited(database, EditAction::InsertionPointMoved));
+    }
+
+    #[tokio::test]
+    async fn insertion_point_of_machine_prediction_is_not_moved() {
+        let machine = prediction(None);
+        let original = drop(&machine);
+        let database = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([[original.clone()]])
+            .append_query_results([[machine.clone()]])
+            .into_connection();
+
+        let error = move_drop_insertion_point(
+            &database,
+            original.id,
+            Point { x: 15, y: 25 },
+            "operator".to_string(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(error.message.contains("is a machine prediction"));
+        assert!(!edited(database, EditAction::InsertionPointMoved));
+    }
+
+    #[tokio::test]
+    async fn crystal_is_added_to_review() {
+        let review = prediction(Some(Uuid::now_v7()));
+        let drop = drop(&review);
+        let added = crystal(&drop);
+        let database = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([[drop.clone()]])
+            .append_query_results([[review.clone()]])
+            .append_query_results([[added.clone()]])
+            .append_exec_results(exec_results(1))
+            .into_connection();
+
+        let (edited_review, crystal) = add_drop_crystal(
+            &database,
+            drop.id,
+            CrystalInput {
+                bounding_box: BoundingBox {
+                    left: 30,
+                    right: 40,
+                    top: 50,
+                    bottom: 60,
+                },
+            },
+            "operator".to_string(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!((review, added), (edited_review, crystal));
+        assert!(edited(database, EditAction::CrystalAdded));
+    }
+
+    #[tokio::test]
+    async fn crystal_is_not_added_to_machine_prediction() {
+        let machine = prediction(None);
+        let drop = drop(&machine);
+        let database = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([[drop.clone()]])
+            .append_query_results([[machine]])
+            .into_connection();
+
+        let error = add_drop_crystal(
+            &database,
+            drop.id,
+            CrystalInput {
+                bounding_box: BoundingBox {
+                    left: 30,
+                    right: 40,
+                    top: 50,
+                    bottom: 60,
+                },
+            },
+            "operator".to_string(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(error.message.contains("is a machine prediction"));
+        assert!(!edited(database, EditAction::CrystalAdded));
+    }
+
+    #[tokio::test]
+    async fn crystal_is_deleted_from_review() {
+        let review = prediction(Some(Uuid::now_v7()));
+        let drop = drop(&review);
+        let deleted = crystal(&drop);
+        let database = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([[deleted.clone()]])
+            .append_query_results([[drop]])
+            .append_query_results([[review.clone()]])
+            .append_exec_results(exec_results(2))
+            .into_connection();
+
+        let (edited_review, crystal) =
+            delete_review_crystal(&database, deleted.id, "operator".to_string())
+                .await
+                .unwrap();
+
+        assert_eq!((review, deleted), (edited_review, crystal));
+        assert!(edited(database, EditAction::CrystalDeleted));
+    }
+
+    #[tokio::test]
+    async fn crystal_is_not_deleted_from_machine_prediction() {
+        let machine = prediction(None);
+        let drop = drop(&machine);
+        let crystal = crystal(&drop);
+        let database = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([[crystal.clone()]])
+            .append_query_results([[drop]])
+            .append_query_results([[machine]])
+            .into_connection();
+
+        let error = delete_review_crystal(&database, crystal.id, "operator".to_string())
+            .await
+            .unwrap_err();
+
+        assert!(error.message.contains("is a machine prediction"));
+        assert!(!edited(database, EditAction::CrystalDeleted));
+    }
+}
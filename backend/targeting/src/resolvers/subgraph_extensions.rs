@@ -0,0 +1,72 @@
+use crate::{
+    loaders::{WellImagesLoader, WellPredictionsLoader},
+    tables::{image, prediction, prediction_crystal},
+};
+use async_graphql::{dataloader::DataLoader, ComplexObject, Context, Object, SimpleObject};
+use opa_client::subject_authorization;
+use sea_orm::{prelude::Uuid, DatabaseConnection, EntityTrait};
+
+/// A crystal well, extended from the crystal library subgraph
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(name = "crystal_wells", complex)]
+pub struct CrystalWells {
+    /// ID of the plate on which the crystal is located
+    pub plate_id: Uuid,
+    /// Well on the plate in which crystal is located
+    pub well_number: i16,
+}
+
+#[ComplexObject]
+impl CrystalWells {
+    /// The images of the well, in the order they were inspected
+    async fn images(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<image::Model>> {
+        subject_authorization!("xchemlab.targeting.read_image", ctx).await?;
+        Ok(ctx
+            .data::<DataLoader<WellImagesLoader>>()?
+            .load_one((self.plate_id, self.well_number))
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// The predictions made for the well, in the order they were made
+    async fn predictions(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Vec<prediction::Model>> {
+        subject_authorization!("xchemlab.targeting.read_prediction", ctx).await?;
+        Ok(ctx
+            .data::<DataLoader<WellPredictionsLoader>>()?
+            .load_one((self.plate_id, self.well_number))
+            .await?
+            .unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SubgraphExtensionsQuery;
+
+#[Object]
+impl SubgraphExtensionsQuery {
+    /// Reference resolver for crystal wells
+    #[graphql(entity)]
+    async fn route_crystal_well(&self, plate_id: Uuid, well_number: i16) -> CrystalWells {
+        CrystalWells {
+            plate_id,
+            well_number,
+        }
+    }
+
+    /// Reference resolver for predicted crystals
+    #[graphql(entity)]
+    async fn route_predicted_crystal(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+    ) -> async_graphql::Result<Option<prediction_crystal::Model>> {
+        subject_authorization!("xchemlab.targeting.read_prediction", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        Ok(prediction_crystal::Entity::find_by_id(id)
+            .one(database)
+            .await?)
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{
+    loaders::{ImageLoader, PredictionLoader},
+    tables::{image, prediction, prediction::ReviewStatus},
+};
+use async_graphql::{dataloader::DataLoader, ComplexObject, Context, Object, SimpleObject};
+use opa_client::subject_authorization;
+use sea_orm::{prelude::Uuid, ConnectionTrait, DatabaseConnection, FromQueryResult, Statement};
+
+/// Aggregates the images and predictions of each well on a plate, counting from the most recent prediction
+const PLATE_SUMMARY_QUERY: &str = r#"
+WITH images AS (
+    SELECT
+        well,
+        COUNT(*) AS image_count,
+        (ARRAY_AGG(id ORDER BY inspection DESC, imaged_at DESC))[1] AS latest_image_id
+    FROM image
+    WHERE plate = $1 AND deleted_at IS NULL
+    GROUP BY well
+),
+predictions AS (
+    SELECT
+        prediction.id,
+        prediction.well,
+        prediction.status,
+        prediction.review_of,
+        prediction.timestamp
+    FROM prediction
+    JOIN image ON image.id = prediction.image_id
+    WHERE prediction.plate = $1 AND image.deleted_at IS NULL
+),
+latest_predictions AS (
+    SELECT DISTINCT ON (well) well, id, status
+    FROM predictions
+    ORDER BY well, timestamp DESC
+),
+prediction_counts AS (
+    SELECT
+        well,
+        COUNT(*) FILTER (WHERE review_of IS NULL) AS machine_prediction_count,
+        COUNT(*) FILTER (WHERE review_of IS NOT NULL) AS review_count
+    FROM predictions
+    GROUP BY well
+),
+drop_counts AS (
+    SELECT
+        drop_prediction.prediction_id,
+        COUNT(DISTINCT drop_prediction.id) AS drop_count,
+        COUNT(crystal_prediction.id) AS crystal_count,
+        SUM(
+            (crystal_prediction.right - crystal_prediction.left)::BIGINT
+            * (crystal_prediction.bottom - crystal_prediction.top)::BIGINT
+        )::BIGINT AS crystal_area
+    FROM drop_prediction
+    LEFT JOIN crystal_prediction ON crystal_prediction.drop_id = drop_prediction.id
+    WHERE drop_prediction.prediction_id IN (SELECT id FROM latest_predictions)
+    GROUP BY drop_prediction.prediction_id
+)
+SELECT
+    COALESCE(images.well, latest_predictions.well) AS well,
+    COALESCE(images.image_count, 0) AS image_count,
+    images.latest_image_id,
+    latest_predictions.id AS latest_prediction_id,
+    latest_predictions.status,
+    COALESCE(prediction_counts.machine_prediction_count, 0) AS machine_prediction_count,
+    COALESCE(prediction_counts.review_count, 0) AS review_count,
+    COALESCE(drop_counts.drop_count, 0) AS drop_count,
+    COALESCE(drop_counts.crystal_count, 0) AS crystal_count,
+    COALESCE(drop_counts.crystal_area, 0) AS crystal_area
+FROM images
+FULL JOIN latest_predictions ON latest_predictions.well = images.well
+LEFT JOIN prediction_counts ON prediction_counts.well = latest_predictions.well
+LEFT JOIN drop_counts ON drop_counts.prediction_id = latest_predictions.id
+ORDER BY well
+"#;
+
+#[derive(Debug, Clone, FromQueryResult, SimpleObject)]
+#[graphql(complex)]
+pub struct WellSummary {
+    well: i16,
+    /// The number of images taken of the well
+    image_count: i64,
+    #[graphql(skip)]
+    latest_image_id: Option<Uuid>,
+    #[graphql(skip)]
+    latest_prediction_id: Option<Uuid>,
+    /// The review status of the most recent prediction
+    status: Option<ReviewStatus>,
+    /// The number of predictions produced by a model
+    machine_prediction_count: i64,
+    /// The number of human reviews of predictions
+    review_count: i64,
+    /// The number of drops in the most recent prediction
+    drop_count: i64,
+    /// The number of crystals in the most recent prediction
+    crystal_count: i64,
+    /// The total area of the crystal bounding boxes in the most recent prediction, in square pixels
+    crystal_area: i64,
+}
+
+#[ComplexObject]
+impl WellSummary {
+    /// The image from the most recent inspection of the well
+    async fn latest_image(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<image::Model>> {
+        subject_authorization!("xchemlab.targeting.read_image", ctx).await?;
+        let Some(latest_image_id) = self.latest_image_id else {
+            return Ok(None);
+        };
+        Ok(ctx
+            .data::<DataLoader<ImageLoader>>()?
+            .load_one(latest_image_id)
+            .await?)
+    }
+
+    /// The most recent prediction for the well, whether produced by a model or a review
+    async fn latest_prediction(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<prediction::Model>> {
+        subject_authorization!("xchemlab.targeting.read_prediction", ctx).await?;
+        let Some(latest_prediction_id) = self.latest_prediction_id else {
+            return Ok(None);
+        };
+        Ok(ctx
+            .data::<DataLoader<PredictionLoader>>()?
+            .load_one(latest_prediction_id)
+            .await?)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SummaryQuery;
+
+#[Object]
+impl SummaryQuery {
+    /// Summarises the images and predictions of each well on a plate
+    async fn plate_summary(
+        &self,
+        ctx: &Context<'_>,
+        plate: Uuid,
+    ) -> async_graphql::Result<Vec<WellSummary>> {
+        subject_authorization!("xchemlab.targeting.read_prediction", ctx).await?;
+        let database = ctx.data::<DatabaseConnection>()?;
+        Ok(
+            WellSummary::find_by_statement(Statement::from_sql_and_values(
+                database.get_database_backend(),
+                PLATE_SUMMARY_QUERY,
+                [plate.into()],
+            ))
+            .all(database)
+            .await?,
+        )
+    }
+}
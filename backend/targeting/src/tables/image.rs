@@ -1,27 +1,61 @@
 use super::prediction;
-use async_graphql::SimpleObject;
+use crate::image_file::ThumbnailSize;
+use async_graphql::{Enum, SimpleObject};
 use chrono::{DateTime, Utc};
 use sea_orm::{
-    prelude::Uuid, ActiveModelBehavior, DeriveEntityModel, DerivePrimaryKey, DeriveRelation,
-    EntityTrait, EnumIter, PrimaryKeyTrait, Related, RelationTrait,
+    prelude::Uuid, ActiveModelBehavior, ConnectionTrait, DbBackend, DbErr, DeriveActiveEnum,
+    DeriveEntityModel, DerivePrimaryKey, DeriveRelation, EntityTrait, EnumIter, PrimaryKeyTrait,
+    Related, RelationTrait, Statement,
 };
 
-#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum Illumination {
+    #[sea_orm(string_value = "Visible")]
+    Visible,
+    #[sea_orm(string_value = "Ultraviolet")]
+    Ultraviolet,
+    #[sea_orm(string_value = "Sonicc")]
+    Sonicc,
+}
+
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel, SimpleObject)]
 #[sea_orm(table_name = "image")]
 #[graphql(name = "Image", complex)]
 pub struct Model {
-    #[sea_orm(primary_key)]
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
     pub plate: Uuid,
-    #[sea_orm(primary_key)]
     pub well: i16,
+    /// The sequence number of the inspection of the well in which the image was taken
+    pub inspection: i32,
+    /// The time at which the image was taken
+    pub imaged_at: DateTime<Utc>,
+    #[graphql(skip)]
+    pub object_key: String,
+    /// The identifier of the imager which took the image
+    pub imager_id: Option<String>,
+    /// The microscope objective used to take the image, such as "10x/0.3"
+    pub objective: Option<String>,
+    /// The total magnification at which the image was taken, as a multiple of actual size
+    pub magnification: Option<f64>,
+    /// The illumination under which the image was taken
+    pub illumination: Option<Illumination>,
+    /// The width of the image in pixels
+    pub width: Option<i32>,
+    /// The height of the image in pixels
+    pub height: Option<i32>,
+    /// The MIME type of the image file
+    pub content_type: Option<String>,
+    /// The hex encoded SHA-256 digest of the image file
+    pub checksum: Option<String>,
+    #[graphql(skip)]
+    #[sea_orm(default_value = false)]
+    pub has_thumbnails: bool,
     pub timestamp: DateTime<Utc>,
     pub operator_id: String,
-}
-
-impl Model {
-    pub fn object_key(&self) -> String {
-        format!("{}/{}", self.plate, self.well)
-    }
+    /// The time at which the image was marked for deletion, after which it is hidden and later purged
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
@@ -36,4 +70,39 @@ impl Related<prediction::Entity> for Entity {
     }
 }
 
+/// The name of the index ensuring each inspection of a well has at most one image which has not
+/// been marked for deletion
+pub const UNIQUE_WELL_INSPECTION: &str = "unique-well-inspection";
+
+/// The key under which an image file is stored, shared by all images with identical contents
+pub fn content_key(checksum: &str) -> String {
+    format!("sha256/{checksum}")
+}
+
+/// Locks an object key until the end of the transaction, such that its objects are not deleted
+/// while an image referencing them is being recorded
+pub async fn lock_object_key(
+    transaction: &impl ConnectionTrait,
+    object_key: &str,
+) -> Result<(), DbErr> {
+    transaction
+        .execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT pg_advisory_xact_lock(hashtext($1))",
+            [object_key.into()],
+        ))
+        .await?;
+    Ok(())
+}
+
+pub fn thumbnail_key(object_key: &str, size: ThumbnailSize) -> String {
+    format!("{object_key}/thumbnails/{}", size.max_dimension())
+}
+
+impl Model {
+    pub fn thumbnail_key(&self, size: ThumbnailSize) -> String {
+        thumbnail_key(&self.object_key, size)
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -2,3 +2,4 @@ pub mod image;
 pub mod prediction;
 pub mod prediction_crystal;
 pub mod prediction_drop;
+pub mod prediction_edit;
@@ -1,11 +1,24 @@
-use super::{image, prediction_drop};
-use async_graphql::SimpleObject;
+use super::{image, prediction_drop, prediction_edit};
+use async_graphql::{Enum, SimpleObject};
 use chrono::{DateTime, Utc};
 use sea_orm::{
-    prelude::Uuid, ActiveModelBehavior, DeriveEntityModel, DerivePrimaryKey, DeriveRelation,
-    EntityTrait, EnumIter, PrimaryKeyTrait, Related, RelationTrait,
+    prelude::Uuid, ActiveModelBehavior, DeriveActiveEnum, DeriveEntityModel, DerivePrimaryKey,
+    DeriveRelation, EntityTrait, EnumIter, PrimaryKeyTrait, Related, RelationTrait,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum ReviewStatus {
+    #[sea_orm(string_value = "Unreviewed")]
+    Unreviewed,
+    #[sea_orm(string_value = "Reviewed")]
+    Reviewed,
+    #[sea_orm(string_value = "Accepted")]
+    Accepted,
+    #[sea_orm(string_value = "Rejected")]
+    Rejected,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
 #[sea_orm(table_name = "prediction")]
 #[graphql(name = "Prediction", complex)]
@@ -13,6 +26,8 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: Uuid,
     #[graphql(skip)]
+    pub image_id: Uuid,
+    #[graphql(skip)]
     pub plate: Uuid,
     #[graphql(skip)]
     pub well: i16,
@@ -23,23 +38,30 @@ pub struct Model {
     pub well_radius: i32,
     pub timestamp: DateTime<Utc>,
     pub operator_id: String,
+    pub model_version: Option<String>,
+    #[graphql(skip)]
+    pub review_of: Option<Uuid>,
+    #[sea_orm(default_value = "Unreviewed")]
+    pub status: ReviewStatus,
 }
 
 #[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
         belongs_to = "image::Entity",
-        from = "(Column::Plate, Column::Well)",
-        to = "(image::Column::Plate, image::Column::Well)"
+        from = "Column::ImageId",
+        to = "image::Column::Id"
     )]
-    Well,
+    Image,
     #[sea_orm(has_many = "prediction_drop::Entity")]
     Drops,
+    #[sea_orm(has_many = "prediction_edit::Entity")]
+    Edits,
 }
 
 impl Related<image::Entity> for Entity {
     fn to() -> sea_orm::RelationDef {
-        Relation::Well.def()
+        Relation::Image.def()
     }
 }
 
@@ -49,4 +71,10 @@ impl Related<prediction_drop::Entity> for Entity {
     }
 }
 
+impl Related<prediction_edit::Entity> for Entity {
+    fn to() -> sea_orm::RelationDef {
+        Relation::Edits.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
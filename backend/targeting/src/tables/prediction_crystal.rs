@@ -7,7 +7,7 @@ use sea_orm::{
 
 #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
 #[sea_orm(table_name = "crystal_prediction")]
-#[graphql(name = "Crystal", complex)]
+#[graphql(name = "PredictedCrystal", complex)]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: Uuid,
@@ -0,0 +1,58 @@
+use super::prediction;
+use async_graphql::{Enum, SimpleObject};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    prelude::Uuid, ActiveModelBehavior, DeriveActiveEnum, DeriveEntityModel, DerivePrimaryKey,
+    DeriveRelation, EntityTrait, EnumIter, PrimaryKeyTrait, Related, RelationTrait,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum EditAction {
+    #[sea_orm(string_value = "ReviewStarted")]
+    ReviewStarted,
+    #[sea_orm(string_value = "StatusChanged")]
+    StatusChanged,
+    #[sea_orm(string_value = "InsertionPointMoved")]
+    InsertionPointMoved,
+    #[sea_orm(string_value = "CrystalAdded")]
+    CrystalAdded,
+    #[sea_orm(string_value = "CrystalDeleted")]
+    CrystalDeleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel, SimpleObject)]
+#[sea_orm(table_name = "prediction_edit")]
+#[graphql(name = "PredictionEdit")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub prediction_id: Uuid,
+    pub action: EditAction,
+    /// The ID of the prediction, drop or crystal which was changed
+    pub subject_id: Uuid,
+    /// The value before the change, if any
+    pub previous: Option<String>,
+    /// The value after the change, if any
+    pub current: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub operator_id: String,
+}
+
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "prediction::Entity",
+        from = "Column::PredictionId",
+        to = "prediction::Column::Id"
+    )]
+    Prediction,
+}
+
+impl Related<prediction::Entity> for Entity {
+    fn to() -> sea_orm::RelationDef {
+        Relation::Prediction.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
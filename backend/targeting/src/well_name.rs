@@ -0,0 +1,107 @@
+use std::ops::RangeInclusive;
+
+/// The numbers of columns a plate may have
+pub const COLUMNS: RangeInclusive<i16> = 1..=48;
+
+/// Ensures a number of plate columns is within [`COLUMNS`]
+pub fn check_columns(columns: i16) -> Result<i16, String> {
+    if COLUMNS.contains(&columns) {
+        Ok(columns)
+    } else {
+        Err(format!(
+            "A plate must have between {} and {} columns, not {columns}",
+            COLUMNS.start(),
+            COLUMNS.end()
+        ))
+    }
+}
+
+/// The number of rows named by a single letter, after which rows are named `AA`, `AB` and so on
+const LETTERS: i16 = 26;
+
+/// Parses a well name, such as `B07` or `AF48`, into a row-major well number starting from one
+pub fn parse_well_name(name: &str, columns: i16) -> Option<i16> {
+    let (row, column) = name.split_at(name.find(|c: char| !c.is_ascii_alphabetic())?);
+    let row = match row.to_ascii_uppercase().as_bytes() {
+        [letter] => i16::from(letter - b'A'),
+        [first, second] => (i16::from(first - b'A') + 1) * LETTERS + i16::from(second - b'A'),
+        _ => return None,
+    };
+    if column.is_empty() || column.len() > 2 || !column.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let column = column.parse::<i16>().ok()?;
+    if !(1..=columns).contains(&column) {
+        return None;
+    }
+    row.checked_mul(columns)?.checked_add(column)
+}
+
+/// Formats a row-major well number starting from one as a well name, such as `B7` or `AF48`
+pub fn format_well_name(well: i16, columns: i16) -> Option<String> {
+    let index = well.checked_sub(1).filter(|index| *index >= 0)?;
+    let columns = Some(columns).filter(|columns| *columns > 0)?;
+    let row = index / columns;
+    let letter = |offset: i16| char::from(b'A' + offset as u8);
+    let row = if row < LETTERS {
+        letter(row).to_string()
+    } else {
+        let row = row - LETTERS;
+        if row >= LETTERS * LETTERS {
+            return None;
+        }
+        format!("{}{}", letter(row / LETTERS), letter(row % LETTERS))
+    };
+    Some(format!("{row}{}", index % columns + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_columns, format_well_name, parse_well_name};
+
+    #[test]
+    fn well_names_round_trip() {
+        for well in 1..=96 {
+            let name = format_well_name(well, 12).unwrap();
+            assert_eq!(Some(well), parse_well_name(&name, 12));
+        }
+        assert_eq!(Some("B7".to_string()), format_well_name(19, 12));
+        assert_eq!(Some(19), parse_well_name("b07", 12));
+        assert_eq!(None, parse_well_name("H13", 12));
+        assert_eq!(None, format_well_name(0, 12));
+    }
+
+    #[test]
+    fn rows_beyond_z_are_named_by_two_letters() {
+        for well in 1..=1536 {
+            let name = format_well_name(well, 48).unwrap();
+            assert_eq!(Some(well), parse_well_name(&name, 48));
+        }
+        assert_eq!(Some("Z48".to_string()), format_well_name(26 * 48, 48));
+        assert_eq!(Some("AA1".to_string()), format_well_name(26 * 48 + 1, 48));
+        assert_eq!(Some("AF48".to_string()), format_well_name(1536, 48));
+        assert_eq!(Some(1536), parse_well_name("af48", 48));
+        assert_eq!(None, parse_well_name("AAA1", 48));
+        assert_eq!(None, parse_well_name("A1B", 48));
+    }
+
+    #[test]
+    fn invalid_columns_are_rejected() {
+        assert_eq!(Ok(1), check_columns(1));
+        assert_eq!(Ok(48), check_columns(48));
+        assert!(check_columns(0).is_err());
+        assert!(check_columns(-12).is_err());
+        assert!(check_columns(49).is_err());
+        assert!(check_columns(i16::MAX).is_err());
+    }
+
+    #[test]
+    fn invalid_columns_do_not_panic() {
+        assert_eq!(None, format_well_name(19, 0));
+        assert_eq!(None, format_well_name(19, -12));
+        assert_eq!(None, parse_well_name("B07", 0));
+        assert_eq!(None, parse_well_name("B07", -12));
+        assert_eq!(None, parse_well_name("Z07", i16::MAX));
+        assert_eq!(Some("A19".to_string()), format_well_name(19, i16::MAX));
+    }
+}
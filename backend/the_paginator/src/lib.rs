@@ -8,11 +8,11 @@ pub mod graphql;
 
 use sea_orm::{
     sea_query::{
-        Alias, ColumnRef, Expr, IntoIden, IntoValueTuple, Query, SeaRc, SelectStatement,
-        SimpleExpr, UnionType, ValueTuple, Values, WindowStatement,
+        Alias, ColumnRef, Expr, IntoCondition, IntoIden, IntoValueTuple, Query, SeaRc,
+        SelectStatement, SimpleExpr, UnionType, ValueTuple, Values, WindowStatement,
     },
     Condition, ConnectionTrait, DbErr, DynIden, EntityTrait, FromQueryResult, Iden, Iterable,
-    Order, OrderedStatement, PrimaryKeyTrait, QueryTrait, Value,
+    Order, OrderedStatement, PrimaryKeyTrait, QueryFilter, QueryTrait, Value,
 };
 
 /// The contents of a cursor indexed page, with indicators for the existance of previous and next pages.
@@ -52,6 +52,8 @@ pub struct QueryCursor<Entity: EntityTrait> {
     before: Option<<Entity::PrimaryKey as PrimaryKeyTrait>::ValueType>,
     limit: u64,
     direction: PageDirection,
+    key_order: KeyOrder,
+    condition: Option<Condition>,
 }
 
 /// An error which occured when attempting to create the [`QueryCursor`]
@@ -74,6 +76,17 @@ pub enum PageDirection {
     Backward,
 }
 
+/// The order in which rows are paginated, by primary key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "async-graphql", derive(async_graphql::Enum))]
+pub enum KeyOrder {
+    /// Rows with the lowest primary key come first
+    #[default]
+    Ascending,
+    /// Rows with the highest primary key come first
+    Descending,
+}
+
 const BASE_TABLE_PREFIX: &str = "book_";
 
 impl<Entity> QueryCursor<Entity>
@@ -93,6 +106,8 @@ where
             before,
             limit,
             direction,
+            key_order: KeyOrder::default(),
+            condition: None,
         }
     }
 
@@ -109,12 +124,31 @@ where
             (None, Some(last)) => Ok((last, PageDirection::Backward)),
             (None, None) => Err(CursorCreationError::UnspecifiedLimit),
         }?;
-        Ok(Self {
-            after,
-            before,
-            limit,
-            direction,
-        })
+        Ok(Self::new(after, before, limit, direction))
+    }
+
+    /// Restricts the page to rows which satisfy the condition, in addition to any previously applied
+    pub fn filter(mut self, filter: impl IntoCondition) -> Self {
+        self.condition = Some(
+            self.condition
+                .unwrap_or_else(Condition::all)
+                .add(filter.into_condition()),
+        );
+        self
+    }
+
+    /// Sets the order in which rows are paginated
+    pub fn key_order(mut self, key_order: KeyOrder) -> Self {
+        self.key_order = key_order;
+        self
+    }
+
+    fn ascending(&self) -> bool {
+        matches!(
+            (self.direction, self.key_order),
+            (PageDirection::Forward, KeyOrder::Ascending)
+                | (PageDirection::Backward, KeyOrder::Descending)
+        )
     }
 
     fn lag(&self) -> u64 {
@@ -131,17 +165,29 @@ where
         }
     }
 
+    /// The number of items preceding the page to select, such that neighbours may be found
+    ///
+    /// Without a bound, the page begins at the first item and no such item exists
+    fn anchor_limit(&self) -> u64 {
+        match self.lower_bound() {
+            Some(_) => 1,
+            None => 0,
+        }
+    }
+
     fn order(&self) -> Order {
-        match self.direction {
-            PageDirection::Forward => Order::Asc,
-            PageDirection::Backward => Order::Desc,
+        if self.ascending() {
+            Order::Asc
+        } else {
+            Order::Desc
         }
     }
 
     fn rev_order(&self) -> Order {
-        match self.direction {
-            PageDirection::Forward => Order::Desc,
-            PageDirection::Backward => Order::Asc,
+        if self.ascending() {
+            Order::Desc
+        } else {
+            Order::Asc
         }
     }
 
@@ -153,18 +199,24 @@ where
     }
 
     fn filter_expr(&self) -> impl Fn(&DynIden, Value) -> SimpleExpr {
-        let direction = self.direction;
-        move |c, v| match direction {
-            PageDirection::Forward => Expr::col(SeaRc::clone(c)).gt(v),
-            PageDirection::Backward => Expr::col(SeaRc::clone(c)).lt(v),
+        let ascending = self.ascending();
+        move |c, v| {
+            if ascending {
+                Expr::col(SeaRc::clone(c)).gt(v)
+            } else {
+                Expr::col(SeaRc::clone(c)).lt(v)
+            }
         }
     }
 
     fn rev_filter_expr(&self) -> impl Fn(&DynIden, Value) -> SimpleExpr {
-        let direction = self.direction;
-        move |c, v| match direction {
-            PageDirection::Forward => Expr::col(SeaRc::clone(c)).lte(v),
-            PageDirection::Backward => Expr::col(SeaRc::clone(c)).gte(v),
+        let ascending = self.ascending();
+        move |c, v| {
+            if ascending {
+                Expr::col(SeaRc::clone(c)).lte(v)
+            } else {
+                Expr::col(SeaRc::clone(c)).gte(v)
+            }
         }
     }
 
@@ -203,6 +255,7 @@ where
                             .column(ColumnRef::Asterisk)
                             .from_subquery(
                                 Entity::find()
+                                    .apply_if(self.condition.clone(), QueryFilter::filter)
                                     .into_query()
                                     .apply_prefix(BASE_TABLE_PREFIX)
                                     .apply_order_by(&cursor_by, self.rev_order())
@@ -211,13 +264,14 @@ where
                                         self.lower_bound().map(|bound| bound.into_value_tuple()),
                                         self.rev_filter_expr(),
                                     )
-                                    .limit(1)
+                                    .limit(self.anchor_limit())
                                     .to_owned(),
                                 Alias::new("before").into_iden(),
                             )
                             .union(
                                 UnionType::All,
                                 Entity::find()
+                                    .apply_if(self.condition.clone(), QueryFilter::filter)
                                     .into_query()
                                     .apply_prefix(BASE_TABLE_PREFIX)
                                     .apply_order_by(&cursor_by, self.order())
@@ -393,8 +447,8 @@ impl ApplyPrefix for SelectStatement {
 
 #[cfg(test)]
 mod tests {
-    use crate::{CursorPage, PageDirection, QueryCursor};
-    use sea_orm::{DbBackend, MockDatabase, Statement, Transaction};
+    use crate::{CursorPage, KeyOrder, PageDirection, QueryCursor};
+    use sea_orm::{ColumnTrait, DbBackend, MockDatabase, Statement, Transaction};
 
     mod table {
         use super::result_table;
@@ -514,7 +568,7 @@ mod tests {
                 ]
                 .join("")
                 .as_str(),
-                [1.into(), 3.into(), 1_u64.into(), 4_u64.into(), 3_u64.into()]
+                [1.into(), 3.into(), 0_u64.into(), 4_u64.into(), 3_u64.into()]
             )])]
         )
     }
@@ -672,7 +726,7 @@ mod tests {
                 ]
                 .join("")
                 .as_str(),
-                [3.into(), 1.into(), 1_u64.into(), 4_u64.into(), 3_u64.into()]
+                [3.into(), 1.into(), 0_u64.into(), 4_u64.into(), 3_u64.into()]
             )])]
         )
     }
@@ -758,4 +812,149 @@ mod tests {
             )])]
         )
     }
+
+    #[tokio::test]
+    async fn page_after_cursor_descending() {
+        let models = vec![
+            result_table::Model {
+                book_id: 31,
+                neighbours_has_next: true,
+                neighbours_has_previous: true,
+            },
+            result_table::Model {
+                book_id: 29,
+                neighbours_has_next: true,
+                neighbours_has_previous: true,
+            },
+        ];
+        let db = MockDatabase::new(sea_orm::DatabaseBackend::Postgres)
+            .append_query_results([models.clone()])
+            .into_connection();
+
+        let page = QueryCursor::<table::Entity>::new(Some(32), None, 2, PageDirection::Forward)
+            .key_order(KeyOrder::Descending)
+            .all(&db)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            CursorPage {
+                has_next: true,
+                has_previous: true,
+                items: models
+                    .into_iter()
+                    .map(table::Model::from)
+                    .collect::<Vec<_>>(),
+            },
+            page
+        );
+
+        assert_eq!(
+            db.into_transaction_log(),
+            [Transaction::many([Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                [
+                    r#"SELECT * "#,
+                    r#"FROM ("#,
+                        r#"SELECT "#,
+                            r#"*, "#,
+                            r#"LAG(TRUE, $1, FALSE) OVER (  ORDER BY "book_id" DESC ) AS "neighbours_has_previous", "#,
+                            r#"LEAD(TRUE, $2, FALSE) OVER (  ORDER BY "book_id" DESC ) AS "neighbours_has_next" "#,
+                        r#"FROM ("#,
+                            r#"SELECT * "#,
+                            r#"FROM ("#,
+                                r#"SELECT "#,
+                                    r#""table"."id" AS "book_id" "#,
+                                r#"FROM "table" "#,
+                                r#"WHERE "id" >= $3 "#,
+                                r#"ORDER BY "id" ASC "#,
+                                r#"LIMIT $4"#,
+                            r#") AS "before" "#,
+                            r#"UNION ALL ("#,
+                                r#"SELECT "#,
+                                    r#""table"."id" AS "book_id" "#,
+                                r#"FROM "table" "#,
+                                r#"WHERE "id" < $5 "#,
+                                r#"ORDER BY "id" DESC "#,
+                                r#"LIMIT $6"#,
+                            r#")"#,
+                        r#") AS "page""#,
+                    r#") AS "cursored_page" "#,
+                    r#"WHERE "book_id" < $7 "#,
+                    r#"ORDER BY "book_id" DESC "#,
+                    r#"LIMIT $8"#
+                ]
+                .join("")
+                .as_str(),
+                [1.into(), 2.into(), 32_u64.into(), 1_u64.into(), 32_u64.into(), 3_u64.into(), 32_u64.into(), 2_u64.into()]
+            )])]
+        )
+    }
+
+    #[tokio::test]
+    async fn page_after_start_filtered() {
+        let models = vec![result_table::Model {
+            book_id: 7,
+            neighbours_has_previous: false,
+            neighbours_has_next: false,
+        }];
+        let db = MockDatabase::new(sea_orm::DatabaseBackend::Postgres)
+            .append_query_results([models.clone()])
+            .into_connection();
+
+        let page = QueryCursor::<table::Entity>::new(None, None, 3, PageDirection::Forward)
+            .filter(table::Column::Id.gte(5_u64))
+            .all(&db)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            CursorPage {
+                items: models.into_iter().map(table::Model::from).collect(),
+                has_next: false,
+                has_previous: false
+            },
+            page
+        );
+
+        assert_eq!(
+            db.into_transaction_log(),
+            [Transaction::many([Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                [
+                    r#"SELECT * "#,
+                    r#"FROM ("#,
+                        r#"SELECT "#,
+                            r#"*, "#,
+                            r#"LAG(TRUE, $1, FALSE) OVER (  ORDER BY "book_id" ASC ) AS "neighbours_has_previous", "#,
+                            r#"LEAD(TRUE, $2, FALSE) OVER (  ORDER BY "book_id" ASC ) AS "neighbours_has_next" "#,
+                        r#"FROM ("#,
+                            r#"SELECT * "#,
+                            r#"FROM ("#,
+                                r#"SELECT "#,
+                                    r#""table"."id" AS "book_id" "#,
+                                r#"FROM "table" "#,
+                                r#"WHERE "table"."id" >= $3 "#,
+                                r#"ORDER BY "id" DESC "#,
+                                r#"LIMIT $4"#,
+                            r#") AS "before" "#,
+                            r#"UNION ALL ("#,
+                                r#"SELECT "#,
+                                    r#""table"."id" AS "book_id" "#,
+                                r#"FROM "table" "#,
+                                r#"WHERE "table"."id" >= $5 "#,
+                                r#"ORDER BY "id" ASC "#,
+                                r#"LIMIT $6"#,
+                            r#")"#,
+                        r#") AS "page""#,
+                    r#") AS "cursored_page" "#,
+                    r#"ORDER BY "book_id" ASC "#,
+                    r#"LIMIT $7"#
+                ]
+                .join("")
+                .as_str(),
+                [1.into(), 3.into(), 5_u64.into(), 0_u64.into(), 5_u64.into(), 4_u64.into(), 3_u64.into()]
+            )])]
+        )
+    }
 }
@@ -11,6 +11,12 @@ default read_prediction = {"allowed": false}
 
 default write_prediction = {"allowed": false}
 
+default review_prediction = {"allowed": false}
+
+default delete_image = {"allowed": false}
+
+default delete_prediction = {"allowed": false}
+
 read_image = response if {
     xchemlab.valid_token
     response := {
@@ -42,3 +48,27 @@ write_prediction = response if {
         "subject": xchemlab.subject
     }
 }
+
+review_prediction = response if {
+    xchemlab.valid_token
+    response := {
+        "allowed": true,
+        "subject": xchemlab.subject
+    }
+}
+
+delete_image = response if {
+    xchemlab.valid_token
+    response := {
+        "allowed": true,
+        "subject": xchemlab.subject
+    }
+}
+
+delete_prediction = response if {
+    xchemlab.valid_token
+    response := {
+        "allowed": true,
+        "subject": xchemlab.subject
+    }
+}